@@ -0,0 +1,71 @@
+//! Command-line tools for Mailnir templates.
+//!
+//! ```text
+//! mailnir-cli test [--update] <template.mailnir.yml>...
+//! ```
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use mailnir_lib::snapshot::{run_template_test_file, update_snapshots};
+
+const USAGE: &str = "usage: mailnir-cli test [--update] <template.mailnir.yml>...
+
+commands:
+  test    run the template's snapshot tests and print diffs of failures
+
+options:
+  --update    rewrite the snapshots with the current output";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("test") => run_tests(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run_tests(args: &[String]) -> ExitCode {
+    let update = args.iter().any(|a| a == "--update");
+    let paths: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    if paths.is_empty() || args.iter().any(|a| a.starts_with("--") && a != "--update") {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    }
+
+    let mut failed = false;
+    for path in paths {
+        let path = Path::new(path);
+        let result = if update {
+            update_snapshots(path)
+        } else {
+            run_template_test_file(path)
+        };
+        match result {
+            Ok(report) => {
+                println!("{}:\n{report}", path.display());
+                if update {
+                    println!("snapshots updated");
+                } else {
+                    failed |= !report.passed();
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
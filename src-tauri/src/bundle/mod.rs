@@ -0,0 +1,887 @@
+//! Portable template bundles (`.mailnir.zip`).
+//!
+//! A bundle holds a template, the files it references (stylesheet,
+//! translation catalogs, attachments, inline images, the sibling snapshot
+//! test file) and, optionally, the data files bound to its sources, plus a
+//! `manifest.json` with a SHA-256 checksum per file. Files inside the template directory keep
+//! their relative path; others go under `external/`, and import points the
+//! template at them. The `.env` file is never bundled.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::env::Environment;
+use crate::join::build_contexts_lenient;
+use crate::render::{literal_inline_images, CompiledTemplate};
+use crate::snapshot::test_file_path;
+use crate::template::{edit_top_level_keys, parse_template, parse_template_str, Localized};
+use crate::MailnirError;
+
+/// File extension of bundles.
+pub const BUNDLE_EXTENSION: &str = "mailnir.zip";
+
+/// Name of the manifest inside a bundle.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Manifest format written by this version; newer bundles are refused.
+pub const BUNDLE_FORMAT: u32 = 1;
+
+/// Directory for referenced files that live outside the template directory.
+const EXTERNAL_DIR: &str = "external";
+
+/// Directory for bundled data files, one subdirectory per namespace.
+const DATA_DIR: &str = "data";
+
+/// What a bundled file is to the template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleFileKind {
+    Template,
+    TestFile,
+    Stylesheet,
+    Translation,
+    Attachment,
+    /// A local image the body shows inline.
+    InlineImage,
+    Data,
+}
+
+/// One file stored in a bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub kind: BundleFileKind,
+    /// Path inside the archive, `/`-separated.
+    pub path: String,
+    /// The path as the template wrote it; the original file path for data.
+    pub reference: String,
+    /// Source namespace of a data file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the contents.
+    pub sha256: String,
+}
+
+/// A file the template references that was not found at export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MissingReference {
+    pub kind: BundleFileKind,
+    pub reference: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    /// Version of Mailnir that wrote the bundle.
+    pub mailnir_version: String,
+    /// Archive path of the template.
+    pub template: String,
+    pub files: Vec<BundleFile>,
+    /// References that could not be bundled.
+    #[serde(default)]
+    pub missing: Vec<MissingReference>,
+}
+
+/// What to bundle besides the template and the files it names.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions<'a> {
+    /// Data files to include as `(namespace, path)`.
+    pub data_files: &'a [(String, PathBuf)],
+    /// Loaded source data. When given, every entry is rendered so that
+    /// attachment and image paths built with Handlebars are bundled too;
+    /// otherwise only attachment lines without `{{` and images the body
+    /// names literally are.
+    pub sources: Option<&'a HashMap<String, Value>>,
+}
+
+/// A template reference that import pointed somewhere else.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathRewrite {
+    pub kind: BundleFileKind,
+    pub from: String,
+    pub to: String,
+}
+
+/// Outcome of [`import_bundle`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportReport {
+    pub template_path: PathBuf,
+    /// Extracted data files by namespace.
+    pub data_files: BTreeMap<String, PathBuf>,
+    pub rewritten: Vec<PathRewrite>,
+    /// Missing at export, or listed in the manifest but absent from the archive.
+    pub missing: Vec<MissingReference>,
+    /// Archive paths whose contents do not match the manifest checksum;
+    /// these are not extracted.
+    pub corrupted: Vec<String>,
+    /// External attachments whose paths are built with Handlebars and could
+    /// not be rewritten; they were extracted under `external/`.
+    pub unresolved: Vec<String>,
+}
+
+impl ImportReport {
+    /// Whether every file arrived intact and every reference was rewritten.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty() && self.unresolved.is_empty()
+    }
+}
+
+/// Write the template at `template_path` and everything it references to a
+/// bundle at `out_path`.
+pub fn export_bundle(
+    template_path: &Path,
+    out_path: &Path,
+    options: &ExportOptions<'_>,
+) -> crate::Result<BundleManifest> {
+    let template = parse_template(template_path)?;
+    let template_dir = template_path.parent().unwrap_or(Path::new("."));
+    let template_name = file_name(template_path);
+
+    let mut wanted: Vec<(BundleFileKind, String, PathBuf, Option<String>)> = vec![(
+        BundleFileKind::Template,
+        template_name.clone(),
+        template_path.to_path_buf(),
+        None,
+    )];
+    let test_file = test_file_path(template_path);
+    if test_file.exists() {
+        wanted.push((
+            BundleFileKind::TestFile,
+            file_name(&test_file),
+            test_file,
+            None,
+        ));
+    }
+    if let Some(stylesheet) = &template.stylesheet {
+        wanted.push((
+            BundleFileKind::Stylesheet,
+            stylesheet.clone(),
+            template_dir.join(stylesheet),
+            None,
+        ));
+    }
+    for path in template.translations.iter().flat_map(BTreeMap::values) {
+        wanted.push((
+            BundleFileKind::Translation,
+            path.clone(),
+            crate::i18n::catalog_path(template_dir, path),
+            None,
+        ));
+    }
+    let references = file_references(&template, template_dir, options.sources);
+    for reference in references.attachments {
+        let path = template_dir.join(&reference);
+        wanted.push((BundleFileKind::Attachment, reference, path, None));
+    }
+    for reference in references.inline_images {
+        let path = template_dir.join(&reference);
+        wanted.push((BundleFileKind::InlineImage, reference, path, None));
+    }
+    for (namespace, path) in options.data_files {
+        wanted.push((
+            BundleFileKind::Data,
+            path.display().to_string(),
+            path.clone(),
+            Some(namespace.clone()),
+        ));
+    }
+
+    let mut manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        mailnir_version: env!("CARGO_PKG_VERSION").to_string(),
+        template: template_name,
+        files: Vec::new(),
+        missing: Vec::new(),
+    };
+    let mut contents: Vec<Vec<u8>> = Vec::new();
+    let mut taken: BTreeSet<String> = BTreeSet::new();
+    for (kind, reference, path, namespace) in wanted {
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                manifest.missing.push(MissingReference { kind, reference });
+                continue;
+            }
+            Err(source) => return Err(MailnirError::Io { path, source }),
+        };
+        let archive_path = match (&namespace, kind) {
+            (Some(namespace), _) => unique_path(
+                &mut taken,
+                &format!("{DATA_DIR}/{namespace}"),
+                &file_name(&path),
+            ),
+            (None, BundleFileKind::Template | BundleFileKind::TestFile) => {
+                taken.insert(reference.clone());
+                reference.clone()
+            }
+            (None, _) => match relative_inside(&reference) {
+                Some(inside) if taken.insert(inside.clone()) => inside,
+                // Referenced twice (e.g. by two locales): store it once.
+                Some(inside) if manifest.files.iter().any(|f| f.path == inside) => continue,
+                _ => unique_path(&mut taken, EXTERNAL_DIR, &file_name(&path)),
+            },
+        };
+        manifest.files.push(BundleFile {
+            kind,
+            path: archive_path,
+            reference,
+            namespace,
+            size: bytes.len() as u64,
+            sha256: sha256_hex(&bytes),
+        });
+        contents.push(bytes);
+    }
+
+    let bundle_err = |reason: String| MailnirError::Bundle {
+        path: out_path.to_path_buf(),
+        reason,
+    };
+    let file = File::create(out_path).map_err(|source| MailnirError::Io {
+        path: out_path.to_path_buf(),
+        source,
+    })?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let manifest_json =
+        serde_json::to_vec_pretty(&manifest).map_err(|e| bundle_err(e.to_string()))?;
+    let entries = std::iter::once((MANIFEST_NAME, &manifest_json)).chain(
+        manifest
+            .files
+            .iter()
+            .map(|f| f.path.as_str())
+            .zip(&contents),
+    );
+    for (name, bytes) in entries {
+        zip.start_file(name, options)
+            .map_err(|e| bundle_err(e.to_string()))?;
+        zip.write_all(bytes).map_err(|source| MailnirError::Io {
+            path: out_path.to_path_buf(),
+            source,
+        })?;
+    }
+    zip.finish().map_err(|e| bundle_err(e.to_string()))?;
+    Ok(manifest)
+}
+
+/// Read the manifest of the bundle at `path` without extracting anything.
+pub fn read_manifest(path: &Path) -> crate::Result<BundleManifest> {
+    let mut archive = open_archive(path)?;
+    manifest_of(&mut archive, path)
+}
+
+/// Extract the bundle at `bundle_path` into `dest_dir`, verify checksums and
+/// point the template's stylesheet, translations and literal attachment
+/// paths at the extracted files. Fails without writing anything if a file
+/// would be overwritten.
+pub fn import_bundle(bundle_path: &Path, dest_dir: &Path) -> crate::Result<ImportReport> {
+    let bundle_err = |reason: String| MailnirError::Bundle {
+        path: bundle_path.to_path_buf(),
+        reason,
+    };
+    let mut archive = open_archive(bundle_path)?;
+    let manifest = manifest_of(&mut archive, bundle_path)?;
+
+    for file in &manifest.files {
+        if relative_inside(&file.path).as_deref() != Some(file.path.as_str()) {
+            return Err(bundle_err(format!(
+                "'{}' points outside the bundle",
+                file.path
+            )));
+        }
+        let target = dest_dir.join(&file.path);
+        if target.exists() {
+            return Err(bundle_err(format!("{} already exists", target.display())));
+        }
+    }
+    if !manifest.files.iter().any(|f| f.path == manifest.template) {
+        return Err(bundle_err(format!(
+            "manifest does not list the template '{}'",
+            manifest.template
+        )));
+    }
+
+    let mut report = ImportReport {
+        template_path: dest_dir.join(&manifest.template),
+        data_files: BTreeMap::new(),
+        rewritten: Vec::new(),
+        missing: manifest.missing.clone(),
+        corrupted: Vec::new(),
+        unresolved: Vec::new(),
+    };
+    let mut extracted: Vec<&BundleFile> = Vec::new();
+    for file in &manifest.files {
+        let mut bytes = Vec::new();
+        match archive.by_name(&file.path) {
+            Ok(mut entry) => {
+                entry
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| bundle_err(format!("reading '{}': {e}", file.path)))?;
+            }
+            Err(zip::result::ZipError::FileNotFound) => {
+                report.missing.push(MissingReference {
+                    kind: file.kind,
+                    reference: file.reference.clone(),
+                });
+                continue;
+            }
+            Err(e) => return Err(bundle_err(e.to_string())),
+        }
+        if sha256_hex(&bytes) != file.sha256 {
+            report.corrupted.push(file.path.clone());
+            continue;
+        }
+        let target = dest_dir.join(&file.path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|source| MailnirError::Io {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
+        std::fs::write(&target, &bytes).map_err(|source| MailnirError::Io {
+            path: target.clone(),
+            source,
+        })?;
+        if let (BundleFileKind::Data, Some(namespace)) = (file.kind, &file.namespace) {
+            report.data_files.insert(namespace.clone(), target);
+        }
+        extracted.push(file);
+    }
+
+    // A corrupted or absent template is already reported; nothing to rewrite.
+    if extracted.iter().any(|f| f.path == manifest.template) {
+        let template_path = report.template_path.clone();
+        rewrite_references(&template_path, &extracted, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Point the extracted template at moved files.
+fn rewrite_references(
+    template_path: &Path,
+    extracted: &[&BundleFile],
+    report: &mut ImportReport,
+) -> crate::Result<()> {
+    let moved: Vec<&BundleFile> = extracted
+        .iter()
+        .copied()
+        .filter(|f| {
+            matches!(
+                f.kind,
+                BundleFileKind::Stylesheet
+                    | BundleFileKind::Translation
+                    | BundleFileKind::Attachment
+            ) && relative_inside(&f.reference).as_deref() != Some(f.path.as_str())
+        })
+        .collect();
+    if moved.is_empty() {
+        return Ok(());
+    }
+    let content = std::fs::read_to_string(template_path).map_err(|source| MailnirError::Io {
+        path: template_path.to_path_buf(),
+        source,
+    })?;
+    let template = parse_template_str(&content)?;
+    let new_path = |kind: BundleFileKind, reference: &str| {
+        moved
+            .iter()
+            .find(|f| f.kind == kind && f.reference == reference)
+            .map(|f| f.path.clone())
+    };
+
+    let yaml_err = |source| MailnirError::TemplateParseYaml {
+        path: template_path.to_path_buf(),
+        source,
+    };
+    let mut edits: Vec<(&str, Option<serde_yaml::Value>)> = Vec::new();
+    let mut record = |kind, from: &str, to: &str| {
+        if !report
+            .rewritten
+            .iter()
+            .any(|r| r.kind == kind && r.from == from)
+        {
+            report.rewritten.push(PathRewrite {
+                kind,
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+    };
+
+    if let Some(stylesheet) = &template.stylesheet {
+        if let Some(to) = new_path(BundleFileKind::Stylesheet, stylesheet) {
+            record(BundleFileKind::Stylesheet, stylesheet, &to);
+            edits.push(("stylesheet", Some(serde_yaml::Value::String(to))));
+        }
+    }
+    if let Some(translations) = &template.translations {
+        let mut changed = false;
+        let updated: BTreeMap<&String, String> = translations
+            .iter()
+            .map(
+                |(locale, path)| match new_path(BundleFileKind::Translation, path) {
+                    Some(to) => {
+                        record(BundleFileKind::Translation, path, &to);
+                        changed = true;
+                        (locale, to)
+                    }
+                    None => (locale, path.clone()),
+                },
+            )
+            .collect();
+        if changed {
+            edits.push((
+                "translations",
+                Some(serde_yaml::to_value(&updated).map_err(yaml_err)?),
+            ));
+        }
+    }
+    if let Some(attachments) = &template.attachments {
+        let mut changed = false;
+        let mut rewrite = |text: &str| -> String {
+            text.lines()
+                .map(
+                    |line| match new_path(BundleFileKind::Attachment, line.trim()) {
+                        Some(to) => {
+                            record(BundleFileKind::Attachment, line.trim(), &to);
+                            changed = true;
+                            line.replace(line.trim(), &to)
+                        }
+                        None => line.to_string(),
+                    },
+                )
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let updated = match attachments {
+            Localized::Single(text) => Localized::Single(rewrite(text)),
+            Localized::ByLocale(map) => Localized::ByLocale(
+                map.iter()
+                    .map(|(locale, text)| (locale.clone(), rewrite(text)))
+                    .collect(),
+            ),
+        };
+        if changed {
+            edits.push((
+                "attachments",
+                Some(serde_yaml::to_value(&updated).map_err(yaml_err)?),
+            ));
+        }
+    }
+
+    for file in &moved {
+        if !report.rewritten.iter().any(|r| r.from == file.reference) {
+            report.unresolved.push(file.reference.clone());
+        }
+    }
+    if !edits.is_empty() {
+        let updated = edit_top_level_keys(&content, &edits)?;
+        std::fs::write(template_path, updated).map_err(|source| MailnirError::Io {
+            path: template_path.to_path_buf(),
+            source,
+        })?;
+    }
+    Ok(())
+}
+
+/// Files a template's messages carry, as paths relative to the template
+/// directory (or absolute), as written or rendered, without duplicates.
+#[derive(Default)]
+struct FileReferences {
+    attachments: Vec<String>,
+    inline_images: Vec<String>,
+}
+
+fn file_references(
+    template: &crate::template::Template,
+    template_dir: &Path,
+    sources: Option<&HashMap<String, Value>>,
+) -> FileReferences {
+    fn add(references: &mut Vec<String>, reference: String) {
+        if !references.contains(&reference) {
+            references.push(reference);
+        }
+    }
+    let mut references = FileReferences::default();
+    for (_, text) in template.attachments.iter().flat_map(Localized::variants) {
+        if !text.contains("{{") {
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .for_each(|l| add(&mut references.attachments, l.to_string()));
+        }
+    }
+    for reference in literal_inline_images(template) {
+        add(&mut references.inline_images, reference);
+    }
+    let Some(sources) = sources else {
+        return references;
+    };
+    let (Ok(env), Ok(contexts)) = (
+        Environment::load(template_dir),
+        build_contexts_lenient(template, sources),
+    ) else {
+        return references;
+    };
+    let relative = |path: &Path| {
+        let reference = path.strip_prefix(template_dir).unwrap_or(path);
+        reference.to_string_lossy().into_owned()
+    };
+    // Entries that fail to render contribute nothing; validation reports them.
+    let compiled = CompiledTemplate::new(template, template_dir, &env);
+    for context in contexts.into_iter().flatten() {
+        if let Ok(email) = compiled.render(&context) {
+            for path in email.attachments {
+                add(&mut references.attachments, relative(&path));
+            }
+            for image in email.inline_images {
+                add(&mut references.inline_images, relative(&image.path));
+            }
+        }
+    }
+    references
+}
+
+/// `reference` as a normalized `/`-separated path, if it is relative and
+/// stays inside its base directory.
+pub(crate) fn relative_inside(reference: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for component in Path::new(reference).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// `dir/name`, suffixed with a counter if already taken.
+fn unique_path(taken: &mut BTreeSet<String>, dir: &str, name: &str) -> String {
+    let (stem, extension) = match name.split_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name, String::new()),
+    };
+    let mut candidate = format!("{dir}/{name}");
+    let mut n = 2;
+    while !taken.insert(candidate.clone()) {
+        candidate = format!("{dir}/{stem}-{n}{extension}");
+        n += 1;
+    }
+    candidate
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "file".into())
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn open_archive(path: &Path) -> crate::Result<ZipArchive<File>> {
+    let file = File::open(path).map_err(|source| MailnirError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    ZipArchive::new(file).map_err(|e| MailnirError::Bundle {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    })
+}
+
+fn manifest_of(archive: &mut ZipArchive<File>, path: &Path) -> crate::Result<BundleManifest> {
+    let bundle_err = |reason: String| MailnirError::Bundle {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let mut json = String::new();
+    archive
+        .by_name(MANIFEST_NAME)
+        .map_err(|_| bundle_err(format!("no {MANIFEST_NAME}")))?
+        .read_to_string(&mut json)
+        .map_err(|e| bundle_err(e.to_string()))?;
+    let manifest: BundleManifest = serde_json::from_str(&json)
+        .map_err(|e| bundle_err(format!("invalid {MANIFEST_NAME}: {e}")))?;
+    if manifest.format > BUNDLE_FORMAT {
+        return Err(bundle_err(format!(
+            "bundle format {} is newer than supported ({BUNDLE_FORMAT})",
+            manifest.format
+        )));
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// `root/campaign/welcome.mailnir.yml` referencing files inside and
+    /// outside its directory.
+    fn campaign(root: &Path, extra: &str) -> PathBuf {
+        let template = root.join("campaign/welcome.mailnir.yml");
+        write(
+            &template,
+            &format!(
+                "sources:\n  r:\n    primary: true\nto: \"{{{{r.email}}}}\"\nsubject: Hi\nbody: \"{{{{t \\\"hello\\\"}}}}\"\nfallback_locale: de\nstylesheet: style.css\ntranslations:\n  de: ../shared/de.ftl\n{extra}"
+            ),
+        );
+        write(&root.join("campaign/style.css"), "p { color: red; }");
+        write(&root.join("shared/de.ftl"), "hello = Hallo\n");
+        template
+    }
+
+    #[test]
+    fn test_round_trip_rewrites_external_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let template = campaign(
+            root.path(),
+            "attachments: |\n  files/guide.pdf\n  ../common/terms.pdf\n",
+        );
+        write(&root.path().join("campaign/files/guide.pdf"), "guide");
+        write(&root.path().join("common/terms.pdf"), "terms");
+        write(
+            &root.path().join("campaign/welcome.mailnir.test.yml"),
+            "tests: []\n",
+        );
+        write(&root.path().join("campaign/.env"), "SECRET=1\n");
+        let data = root.path().join("lists/people.json");
+        write(&data, r#"[{"email": "ada@example.com"}]"#);
+
+        let bundle = root.path().join("welcome.mailnir.zip");
+        let manifest = export_bundle(
+            &template,
+            &bundle,
+            &ExportOptions {
+                data_files: &[("r".into(), data.clone())],
+                sources: None,
+            },
+        )
+        .unwrap();
+        let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "welcome.mailnir.yml",
+                "welcome.mailnir.test.yml",
+                "style.css",
+                "external/de.ftl",
+                "files/guide.pdf",
+                "external/terms.pdf",
+                "data/r/people.json",
+            ]
+        );
+        assert!(manifest.missing.is_empty());
+        assert_eq!(read_manifest(&bundle).unwrap(), manifest);
+
+        let dest = root.path().join("imported");
+        let report = import_bundle(&bundle, &dest).unwrap();
+        assert!(report.is_complete(), "{report:?}");
+        assert_eq!(report.data_files["r"], dest.join("data/r/people.json"));
+        assert!(!dest.join(".env").exists());
+
+        let imported = parse_template(&report.template_path).unwrap();
+        assert_eq!(imported.stylesheet.as_deref(), Some("style.css"));
+        assert_eq!(imported.translations.unwrap()["de"], "external/de.ftl");
+        assert_eq!(
+            imported.attachments,
+            Some(Localized::Single(
+                "files/guide.pdf\nexternal/terms.pdf".into()
+            ))
+        );
+        let froms: Vec<&str> = report.rewritten.iter().map(|r| r.from.as_str()).collect();
+        assert_eq!(froms, ["../shared/de.ftl", "../common/terms.pdf"]);
+    }
+
+    #[test]
+    fn test_rendered_attachments_bundled_with_sources() {
+        let root = tempfile::tempdir().unwrap();
+        let template = campaign(
+            root.path(),
+            "attachments: |\n  docs/{{r.id}}.pdf\n  {{r.shared}}\n",
+        );
+        write(&root.path().join("campaign/docs/7.pdf"), "seven");
+        write(&root.path().join("other/report.pdf"), "report");
+        let sources = HashMap::from([(
+            "r".to_string(),
+            json!([{"email": "a@example.com", "id": 7, "shared": "../other/report.pdf"}]),
+        )]);
+
+        let bundle = root.path().join("b.mailnir.zip");
+        let manifest = export_bundle(
+            &template,
+            &bundle,
+            &ExportOptions {
+                data_files: &[],
+                sources: Some(&sources),
+            },
+        )
+        .unwrap();
+        assert!(manifest.files.iter().any(|f| f.path == "docs/7.pdf"));
+        assert!(manifest
+            .files
+            .iter()
+            .any(|f| f.path == "external/report.pdf"));
+
+        let report = import_bundle(&bundle, &root.path().join("out")).unwrap();
+        assert_eq!(report.unresolved, ["../other/report.pdf"]);
+    }
+
+    #[test]
+    fn test_inline_images_bundled() {
+        let root = tempfile::tempdir().unwrap();
+        let template = campaign(root.path(), "");
+        let content = std::fs::read_to_string(&template).unwrap().replace(
+            "body: \"{{t \\\"hello\\\"}}\"",
+            "body: \"![Logo](img/logo.png) ![](img/{{r.id}}.png) ![](https://x.org/a.png)\"",
+        );
+        assert!(content.contains("img/logo.png"));
+        std::fs::write(&template, content).unwrap();
+        write(&root.path().join("campaign/img/logo.png"), "logo");
+        write(&root.path().join("campaign/img/7.png"), "seven");
+
+        let bundle = root.path().join("b.mailnir.zip");
+        let manifest = export_bundle(&template, &bundle, &ExportOptions::default()).unwrap();
+        let images: Vec<&str> = manifest
+            .files
+            .iter()
+            .filter(|f| f.kind == BundleFileKind::InlineImage)
+            .map(|f| f.path.as_str())
+            .collect();
+        assert_eq!(images, ["img/logo.png"]);
+        assert!(manifest.missing.is_empty(), "{:?}", manifest.missing);
+
+        let sources = HashMap::from([(
+            "r".to_string(),
+            json!([{"email": "a@example.com", "id": 7}]),
+        )]);
+        let bundle = root.path().join("c.mailnir.zip");
+        let manifest = export_bundle(
+            &template,
+            &bundle,
+            &ExportOptions {
+                data_files: &[],
+                sources: Some(&sources),
+            },
+        )
+        .unwrap();
+        let images: Vec<&str> = manifest
+            .files
+            .iter()
+            .filter(|f| f.kind == BundleFileKind::InlineImage)
+            .map(|f| f.path.as_str())
+            .collect();
+        assert_eq!(images, ["img/logo.png", "img/7.png"]);
+
+        let dest = root.path().join("out");
+        let report = import_bundle(&bundle, &dest).unwrap();
+        assert!(report.is_complete(), "{report:?}");
+        assert!(report
+            .rewritten
+            .iter()
+            .all(|r| r.kind != BundleFileKind::InlineImage));
+        assert_eq!(
+            std::fs::read_to_string(dest.join("img/7.png")).unwrap(),
+            "seven"
+        );
+    }
+
+    #[test]
+    fn test_missing_references_reported() {
+        let root = tempfile::tempdir().unwrap();
+        let template = campaign(root.path(), "attachments: gone.pdf\n");
+        std::fs::remove_file(root.path().join("campaign/style.css")).unwrap();
+
+        let bundle = root.path().join("b.mailnir.zip");
+        let manifest = export_bundle(&template, &bundle, &ExportOptions::default()).unwrap();
+        let missing: Vec<&str> = manifest
+            .missing
+            .iter()
+            .map(|m| m.reference.as_str())
+            .collect();
+        assert_eq!(missing, ["style.css", "gone.pdf"]);
+
+        let report = import_bundle(&bundle, &root.path().join("out")).unwrap();
+        assert_eq!(report.missing, manifest.missing);
+        assert!(!report.is_complete());
+    }
+
+    /// A bundle whose manifest is written by hand.
+    fn raw_bundle(path: &Path, manifest: &BundleManifest, files: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let options = SimpleFileOptions::default();
+        zip.start_file(MANIFEST_NAME, options).unwrap();
+        zip.write_all(&serde_json::to_vec(manifest).unwrap())
+            .unwrap();
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn entry(kind: BundleFileKind, path: &str, content: &str) -> BundleFile {
+        BundleFile {
+            kind,
+            path: path.into(),
+            reference: path.into(),
+            namespace: None,
+            size: content.len() as u64,
+            sha256: sha256_hex(content.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_import_checks_checksums_and_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let template = "sources:\n  r:\n    primary: true\nto: x\nsubject: s\nbody: b\nstylesheet: style.css\n";
+        let mut manifest = BundleManifest {
+            format: BUNDLE_FORMAT,
+            mailnir_version: "0".into(),
+            template: "t.mailnir.yml".into(),
+            files: vec![
+                entry(BundleFileKind::Template, "t.mailnir.yml", template),
+                entry(BundleFileKind::Stylesheet, "style.css", "p {}"),
+                entry(BundleFileKind::Attachment, "absent.pdf", "x"),
+            ],
+            missing: Vec::new(),
+        };
+        let bundle = root.path().join("b.mailnir.zip");
+        raw_bundle(
+            &bundle,
+            &manifest,
+            &[("t.mailnir.yml", template), ("style.css", "tampered")],
+        );
+        let dest = root.path().join("out");
+        let report = import_bundle(&bundle, &dest).unwrap();
+        assert_eq!(report.corrupted, ["style.css"]);
+        assert_eq!(report.missing[0].reference, "absent.pdf");
+        assert!(!dest.join("style.css").exists());
+
+        // Importing again would overwrite the template.
+        assert!(matches!(
+            import_bundle(&bundle, &dest),
+            Err(MailnirError::Bundle { .. })
+        ));
+
+        manifest.files[1].path = "../escape.css".into();
+        raw_bundle(&bundle, &manifest, &[("t.mailnir.yml", template)]);
+        let err = import_bundle(&bundle, &root.path().join("other")).unwrap_err();
+        assert!(err.to_string().contains("outside the bundle"), "{err}");
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -14,9 +14,13 @@ pub struct SourceSlot {
     pub namespace: String,
     pub is_primary: bool,
     pub has_join: bool,
-    pub join: HashMap<String, String>,
+    pub join: HashMap<String, mailnir_lib::template::JoinCondition>,
     pub is_many: bool,
     pub is_form: bool,
+    /// Declared form inputs (`fields:`), passed back unchanged on save.
+    pub fields: Option<Vec<mailnir_lib::template::FormField>>,
+    /// Per-row computed fields (`computed:`), passed back unchanged on save.
+    pub computed: Option<std::collections::BTreeMap<String, String>>,
 }
 
 /// Editable template field values, returned on parse and sent back on save.
@@ -25,9 +29,16 @@ pub struct TemplateFields {
     pub to: String,
     pub cc: Option<String>,
     pub bcc: Option<String>,
-    pub subject: String,
-    pub body: String,
-    pub attachments: Option<String>,
+    /// A string, or an object of locale tag → variant.
+    pub subject: mailnir_lib::template::Localized,
+    pub body: mailnir_lib::template::Localized,
+    /// Hand-written plain-text part, used instead of the text derived from HTML.
+    pub text_body: Option<mailnir_lib::template::Localized>,
+    /// Preview text shown after the subject; hidden in the HTML body.
+    pub preheader: Option<mailnir_lib::template::Localized>,
+    pub attachments: Option<mailnir_lib::template::Localized>,
+    pub locale_field: Option<String>,
+    pub fallback_locale: Option<String>,
     /// "markdown" | "html" | "text" | null (null means absent from YAML = default markdown)
     pub body_format: Option<String>,
     pub stylesheet: Option<String>,
@@ -39,6 +50,8 @@ pub struct TemplateInfo {
     pub path: String,
     pub sources: Vec<SourceSlot>,
     pub fields: TemplateFields,
+    /// Set when the file uses an older format and was upgraded in memory.
+    pub migration: Option<mailnir_lib::template::MigrationReport>,
 }
 
 /// Source configuration sent from the frontend for new templates.
@@ -46,9 +59,11 @@ pub struct TemplateInfo {
 pub struct SourceSpec {
     pub namespace: String,
     pub primary: Option<bool>,
-    pub join: Option<HashMap<String, String>>,
+    pub join: Option<HashMap<String, mailnir_lib::template::JoinCondition>>,
     pub many: Option<bool>,
     pub form: Option<bool>,
+    pub fields: Option<Vec<mailnir_lib::template::FormField>>,
+    pub computed: Option<std::collections::BTreeMap<String, String>>,
 }
 
 /// Patch payload for save_template — mirrors TemplateFields.
@@ -57,9 +72,13 @@ pub struct TemplatePatch {
     pub to: String,
     pub cc: Option<String>,
     pub bcc: Option<String>,
-    pub subject: String,
-    pub body: String,
-    pub attachments: Option<String>,
+    pub subject: mailnir_lib::template::Localized,
+    pub body: mailnir_lib::template::Localized,
+    pub text_body: Option<mailnir_lib::template::Localized>,
+    pub preheader: Option<mailnir_lib::template::Localized>,
+    pub attachments: Option<mailnir_lib::template::Localized>,
+    pub locale_field: Option<String>,
+    pub fallback_locale: Option<String>,
     pub body_format: Option<String>,
     pub stylesheet: Option<String>,
     pub style: Option<String>,
@@ -80,7 +99,8 @@ pub struct SourceFileSpec {
     pub path: String,
     pub separator: Option<String>,
     pub encoding: Option<String>,
-    pub form_data: Option<HashMap<String, String>>,
+    /// Typed form values keyed by field name (strings, numbers, booleans, lists).
+    pub form_data: Option<HashMap<String, Value>>,
 }
 
 /// Per-entry summary for the preview validation report.
@@ -96,6 +116,8 @@ pub struct PreviewEntryStatus {
 pub struct PreviewValidation {
     pub entry_count: usize,
     pub entries: Vec<PreviewEntryStatus>,
+    /// Template-wide problems (join keys or fields missing from the loaded data).
+    pub template_issues: Vec<String>,
 }
 
 /// One fully rendered email for preview.
@@ -143,6 +165,28 @@ impl Default for SendState {
     }
 }
 
+/// Managed state holding the cancel flag of the running preview validation.
+#[derive(Default)]
+pub struct ValidateState {
+    current: Mutex<Arc<AtomicBool>>,
+}
+
+impl ValidateState {
+    /// Cancel the running validation, if any, and return a flag for a new one.
+    fn start(&self) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        current.store(true, Ordering::SeqCst);
+        *current = Arc::clone(&flag);
+        flag
+    }
+
+    fn cancel(&self) {
+        let current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        current.store(true, Ordering::SeqCst);
+    }
+}
+
 // ── Commands ──────────────────────────────────────────────────────────────────
 
 /// Return the app version string from `git describe`.
@@ -157,7 +201,8 @@ pub fn get_version_info() -> String {
 #[tauri::command]
 pub fn parse_template_cmd(path: String) -> Result<TemplateInfo, String> {
     let p = Path::new(&path);
-    let template = mailnir_lib::template::parse_template(p).map_err(|e| e.to_string())?;
+    let (template, migration) =
+        mailnir_lib::template::parse_template_with_report(p).map_err(|e| e.to_string())?;
     mailnir_lib::template::validate_sources(&template).map_err(|e| e.to_string())?;
 
     let mut sources: Vec<SourceSlot> = template
@@ -172,6 +217,8 @@ pub fn parse_template_cmd(path: String) -> Result<TemplateInfo, String> {
                 join,
                 is_many: cfg.many == Some(true),
                 is_form: cfg.form == Some(true),
+                fields: cfg.fields.clone(),
+                computed: cfg.computed.clone(),
             }
         })
         .collect();
@@ -188,7 +235,11 @@ pub fn parse_template_cmd(path: String) -> Result<TemplateInfo, String> {
         bcc: template.bcc.clone(),
         subject: template.subject.clone(),
         body: template.body.clone(),
+        text_body: template.text_body.clone(),
+        preheader: template.preheader.clone(),
         attachments: template.attachments.clone(),
+        locale_field: template.locale_field.clone(),
+        fallback_locale: template.fallback_locale.clone(),
         body_format: template.body_format.as_ref().map(|f| match f {
             mailnir_lib::template::BodyFormat::Markdown => "markdown".to_string(),
             mailnir_lib::template::BodyFormat::Html => "html".to_string(),
@@ -202,9 +253,76 @@ pub fn parse_template_cmd(path: String) -> Result<TemplateInfo, String> {
         path,
         sources,
         fields,
+        migration,
     })
 }
 
+/// Upgrade an older template file to the current format on disk.
+///
+/// Returns what was changed, or null if the file was already current.
+#[tauri::command]
+pub fn migrate_template_file(
+    path: String,
+) -> Result<Option<mailnir_lib::template::MigrationReport>, String> {
+    mailnir_lib::template::migrate_template_file(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+/// Write the JSON Schema for the current template format to `path`.
+#[tauri::command]
+pub fn export_template_schema(path: String) -> Result<(), String> {
+    let schema = serde_json::to_string_pretty(&mailnir_lib::template::json_schema())
+        .map_err(|e| e.to_string())?;
+    std::fs::write(&path, schema + "\n").map_err(|e| e.to_string())
+}
+
+/// Write a `.mailnir.zip` bundle of the template at `template_path` to
+/// `out_path`. File sources are included when `include_data` is set; when
+/// every source is loaded, Handlebars-built attachment paths are bundled too.
+#[tauri::command]
+pub fn export_bundle(
+    template_path: String,
+    source_files: Vec<SourceFileSpec>,
+    include_data: bool,
+    out_path: String,
+) -> Result<mailnir_lib::bundle::BundleManifest, String> {
+    let data_files: Vec<(String, PathBuf)> = if include_data {
+        source_files
+            .iter()
+            .filter(|spec| spec.form_data.is_none() && !spec.path.is_empty())
+            .map(|spec| (spec.namespace.clone(), PathBuf::from(&spec.path)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let all_loaded = source_files
+        .iter()
+        .all(|spec| spec.form_data.is_some() || !spec.path.is_empty());
+    let sources = if all_loaded {
+        load_sources(&source_files).ok()
+    } else {
+        None
+    };
+    mailnir_lib::bundle::export_bundle(
+        Path::new(&template_path),
+        Path::new(&out_path),
+        &mailnir_lib::bundle::ExportOptions {
+            data_files: &data_files,
+            sources: sources.as_ref(),
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Extract the bundle at `bundle_path` into `dest_dir`.
+#[tauri::command]
+pub fn import_bundle(
+    bundle_path: String,
+    dest_dir: String,
+) -> Result<mailnir_lib::bundle::ImportReport, String> {
+    mailnir_lib::bundle::import_bundle(Path::new(&bundle_path), Path::new(&dest_dir))
+        .map_err(|e| e.to_string())
+}
+
 /// Load a CSV file with optional separator/encoding overrides and return a preview.
 ///
 /// When `separator` is `None`, the separator is auto-detected from the first line.
@@ -320,6 +438,9 @@ pub async fn test_smtp_connection(
     password: String,
 ) -> Result<(), String> {
     let creds = mailnir_lib::smtp::SmtpCredentials { username, password };
+    let profile = profile
+        .resolve_env(&mailnir_lib::env::Environment::from_process())
+        .map_err(|e| e.to_string())?;
     mailnir_lib::smtp::test_connection(&profile, &creds)
         .await
         .map_err(|e| e.to_string())
@@ -343,105 +464,223 @@ pub fn get_data_fields(path: String) -> Result<Vec<String>, String> {
     Ok(keys)
 }
 
-/// Infer form field names for a given namespace from template variable references.
+/// Form inputs for a namespace: the declared `fields:` schema, followed by any
+/// other field the template references (as optional text).
 ///
-/// Scans all template string fields for `{{namespace.field}}` patterns and
-/// returns a sorted, deduplicated list of field names. Uses the current editor
-/// state (TemplatePatch) so fields update as the user edits the template.
+/// Uses the current editor state (TemplatePatch) so fields update as the user
+/// edits the template.
 #[tauri::command]
 pub fn get_form_fields(
     template_path: String,
     fields: TemplatePatch,
     namespace: String,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<mailnir_lib::template::FormField>, String> {
+    let path = Path::new(&template_path);
+    let mut template = mailnir_lib::template::parse_template(path).map_err(|e| e.to_string())?;
+    apply_patch(&mut template, &fields);
+    Ok(mailnir_lib::template::form_schema(&template, &namespace))
+}
+
+/// List every data path the template references, with field, offset and usage.
+///
+/// Uses the current editor state (TemplatePatch) like `get_form_fields`; the
+/// editor uses it to offer fields that the template already refers to.
+#[tauri::command]
+pub fn get_template_variables(
+    template_path: String,
+    fields: TemplatePatch,
+) -> Result<Vec<mailnir_lib::template::VariableRef>, String> {
     let path = Path::new(&template_path);
     let mut template = mailnir_lib::template::parse_template(path).map_err(|e| e.to_string())?;
     apply_patch(&mut template, &fields);
-    Ok(mailnir_lib::template::infer_form_fields(
-        &template, &namespace,
-    ))
+    Ok(mailnir_lib::template::template_variables(&template))
 }
 
 /// Overwrite the editable fields of a template YAML file.
 ///
 /// When `sources` is `None`, the existing sources block is preserved verbatim.
-/// When `sources` is `Some(...)`, the sources block is rebuilt from the specs
-/// after validation.
+/// When `sources` is `Some(...)`, it is validated and only the sources whose
+/// configuration differs from the file are rewritten, each under `sources:`.
 ///
-/// Uses a serde_yaml::Value read-modify-write. YAML anchors are expanded on
-/// write (acceptable trade-off for Phase 7).
+/// Only keys whose value changed are rewritten; comments, key order, anchors
+/// and block-scalar style elsewhere in the file are left as they were. If
+/// the file's layout cannot be edited in place, saving fails unless
+/// `rewrite` is set, in which case the whole file is re-serialized.
 #[tauri::command]
 pub fn save_template(
     path: String,
     patch: TemplatePatch,
     sources: Option<Vec<SourceSpec>>,
+    rewrite: Option<bool>,
 ) -> Result<(), String> {
+    use mailnir_lib::template::{edit_nested_keys, edit_top_level_keys, rewrite_top_level_keys};
+    use mailnir_lib::MailnirError;
+    use serde_yaml::Value as Yaml;
+
     let content = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let mut doc: serde_yaml::Value = serde_yaml::from_str(&content).map_err(|e| e.to_string())?;
-    let map = doc
-        .as_mapping_mut()
-        .ok_or_else(|| "template root is not a YAML mapping".to_string())?;
 
-    // Rebuild sources block if provided.
+    let mut source_edits: Vec<(String, Option<Yaml>)> = Vec::new();
     if let Some(specs) = &sources {
         validate_source_specs(specs)?;
-        map.insert(
-            serde_yaml::Value::String("sources".into()),
-            serde_yaml::Value::Mapping(build_sources_yaml(specs)),
-        );
+        source_edits = changed_sources(&content, specs);
     }
-
-    // Helper: upsert a string key in the mapping.
-    macro_rules! set_str {
-        ($key:expr, $val:expr) => {
-            map.insert(
-                serde_yaml::Value::String($key.to_string()),
-                serde_yaml::Value::String($val),
-            );
+    let source_edits: Vec<(&str, Option<Yaml>)> = source_edits
+        .iter()
+        .map(|(namespace, value)| (namespace.as_str(), value.clone()))
+        .collect();
+    // The whole `sources` value after the edits, for layouts that cannot be
+    // edited entry by entry.
+    let mut all_sources = serde_yaml::from_str::<Yaml>(&content)
+        .ok()
+        .and_then(|doc| doc.get("sources")?.as_mapping().cloned())
+        .unwrap_or_default();
+    for (namespace, value) in &source_edits {
+        let key = Yaml::String(namespace.to_string());
+        match value {
+            Some(value) => all_sources.insert(key, value.clone()),
+            None => all_sources.remove(&key),
         };
     }
-    // Helper: set an optional key; remove the key when the value is None or empty.
-    macro_rules! set_opt {
-        ($key:expr, $val:expr) => {
-            match $val {
-                Some(v) if !v.is_empty() => {
-                    map.insert(
-                        serde_yaml::Value::String($key.to_string()),
-                        serde_yaml::Value::String(v),
-                    );
-                }
-                _ => {
-                    map.remove(serde_yaml::Value::String($key.to_string()));
-                }
+
+    let mut edits: Vec<(&str, Option<Yaml>)> = Vec::new();
+
+    // Required keys are always written; optional keys are removed when empty.
+    let opt = |v: Option<String>| v.filter(|s| !s.is_empty()).map(Yaml::String);
+    let localized =
+        |v: &mailnir_lib::template::Localized| serde_yaml::to_value(v).map_err(|e| e.to_string());
+    edits.push(("to", Some(Yaml::String(patch.to))));
+    edits.push(("subject", Some(localized(&patch.subject)?)));
+    edits.push(("body", Some(localized(&patch.body)?)));
+    edits.push((
+        "text_body",
+        patch
+            .text_body
+            .as_ref()
+            .filter(|t| !t.is_empty())
+            .map(localized)
+            .transpose()?,
+    ));
+    edits.push((
+        "preheader",
+        patch
+            .preheader
+            .as_ref()
+            .filter(|p| !p.is_empty())
+            .map(localized)
+            .transpose()?,
+    ));
+    edits.push(("cc", opt(patch.cc)));
+    edits.push(("bcc", opt(patch.bcc)));
+    edits.push((
+        "attachments",
+        patch
+            .attachments
+            .as_ref()
+            .filter(|a| !a.is_empty())
+            .map(localized)
+            .transpose()?,
+    ));
+    edits.push(("locale_field", opt(patch.locale_field)));
+    edits.push(("fallback_locale", opt(patch.fallback_locale)));
+    edits.push(("stylesheet", opt(patch.stylesheet)));
+    edits.push(("style", opt(patch.style)));
+    edits.push((
+        "body_format",
+        patch
+            .body_format
+            .filter(|f| matches!(f.as_str(), "markdown" | "html" | "text"))
+            .map(Yaml::String),
+    ));
+
+    let edited = if source_edits.is_empty() {
+        edit_top_level_keys(&content, &edits)
+    } else {
+        match edit_nested_keys(&content, "sources", &source_edits) {
+            Ok(text) => Ok(text),
+            // e.g. a flow-style `sources:`; only that entry is rewritten.
+            Err(MailnirError::TemplateEdit { .. }) => edit_top_level_keys(
+                &content,
+                &[("sources", Some(Yaml::Mapping(all_sources.clone())))],
+            ),
+            Err(e) => Err(e),
+        }
+        .and_then(|text| edit_top_level_keys(&text, &edits))
+    };
+    let yaml_out = match edited {
+        Ok(text) => text,
+        Err(MailnirError::TemplateEdit { .. }) if rewrite == Some(true) => {
+            if !source_edits.is_empty() {
+                edits.push(("sources", Some(Yaml::Mapping(all_sources))));
             }
-        };
-    }
+            rewrite_top_level_keys(&content, &edits).map_err(|e| e.to_string())?
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    std::fs::write(&path, yaml_out).map_err(|e| e.to_string())?;
+    Ok(())
+}
 
-    set_str!("to", patch.to);
-    set_str!("subject", patch.subject);
-    set_str!("body", patch.body);
-    set_opt!("cc", patch.cc);
-    set_opt!("bcc", patch.bcc);
-    set_opt!("attachments", patch.attachments);
-    set_opt!("stylesheet", patch.stylesheet);
-    set_opt!("style", patch.style);
+/// Edits under `sources:` that make the file's sources match `specs`: a
+/// rebuilt entry for each source whose configuration differs (or that is
+/// new) and a removal for each source no longer listed. Sources that only
+/// differ in layout or defaults are left alone, and rebuilt ones keep their
+/// `x-` extension keys, which the editor does not manage.
+fn changed_sources(
+    content: &str,
+    specs: &[SourceSpec],
+) -> Vec<(String, Option<serde_yaml::Value>)> {
+    use mailnir_lib::template::SourceConfig;
+
+    let current: HashMap<String, SourceConfig> = mailnir_lib::template::parse_template_str(content)
+        .map(|t| t.sources)
+        .unwrap_or_default();
+    let normalized = |c: &SourceConfig| SourceConfig {
+        primary: c.primary.filter(|&p| p),
+        join: c.join.clone().filter(|j| !j.is_empty()),
+        many: c.many.filter(|&m| m),
+        form: c.form.filter(|&f| f),
+        fields: c.fields.clone().filter(|f| !f.is_empty()),
+        computed: c.computed.clone().filter(|c| !c.is_empty()),
+    };
 
-    // body_format: write "markdown"/"html"/"text" if present; remove key otherwise.
-    match patch.body_format.as_deref() {
-        Some("markdown") | Some("html") | Some("text") => {
-            map.insert(
-                serde_yaml::Value::String("body_format".to_string()),
-                serde_yaml::Value::String(patch.body_format.unwrap()),
-            );
+    let raw = serde_yaml::from_str::<serde_yaml::Value>(content).ok();
+    let raw_sources = raw
+        .as_ref()
+        .and_then(|doc| doc.get("sources")?.as_mapping());
+
+    let mut edits = Vec::new();
+    for spec in specs {
+        let unchanged = current
+            .get(&spec.namespace)
+            .is_some_and(|c| normalized(c) == normalized(&source_config(spec)));
+        if unchanged {
+            continue;
         }
-        _ => {
-            map.remove(serde_yaml::Value::String("body_format".to_string()));
+        let mut cfg = source_yaml(spec);
+        let extensions = raw_sources
+            .and_then(|sources| sources.get(&spec.namespace)?.as_mapping())
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| key.as_str().is_some_and(|k| k.starts_with("x-")));
+        for (key, value) in extensions {
+            cfg.insert(key.clone(), value.clone());
         }
+        edits.push((
+            spec.namespace.clone(),
+            Some(serde_yaml::Value::Mapping(cfg)),
+        ));
     }
-
-    let yaml_out = serde_yaml::to_string(&doc).map_err(|e| e.to_string())?;
-    std::fs::write(&path, yaml_out).map_err(|e| e.to_string())?;
-    Ok(())
+    let mut removed: Vec<&String> = current
+        .keys()
+        .filter(|namespace| !specs.iter().any(|s| &s.namespace == *namespace))
+        .collect();
+    removed.sort();
+    edits.extend(
+        removed
+            .into_iter()
+            .map(|namespace| (namespace.clone(), None)),
+    );
+    edits
 }
 
 /// Create a new template YAML file from scratch.
@@ -458,6 +697,10 @@ pub fn create_template(
     validate_source_specs(&sources)?;
 
     let mut doc = serde_yaml::Mapping::new();
+    doc.insert(
+        serde_yaml::Value::String("version".into()),
+        serde_yaml::Value::Number(mailnir_lib::template::CURRENT_VERSION.into()),
+    );
     doc.insert(
         serde_yaml::Value::String("sources".into()),
         serde_yaml::Value::Mapping(build_sources_yaml(&sources)),
@@ -468,13 +711,15 @@ pub fn create_template(
         serde_yaml::Value::String("to".into()),
         serde_yaml::Value::String(patch.to),
     );
+    let localized =
+        |v: &mailnir_lib::template::Localized| serde_yaml::to_value(v).map_err(|e| e.to_string());
     doc.insert(
         serde_yaml::Value::String("subject".into()),
-        serde_yaml::Value::String(patch.subject),
+        localized(&patch.subject)?,
     );
     doc.insert(
         serde_yaml::Value::String("body".into()),
-        serde_yaml::Value::String(patch.body),
+        localized(&patch.body)?,
     );
 
     // Optional fields.
@@ -490,9 +735,28 @@ pub fn create_template(
             }
         };
     }
+    if let Some(text_body) = patch.text_body.as_ref().filter(|t| !t.is_empty()) {
+        doc.insert(
+            serde_yaml::Value::String("text_body".into()),
+            localized(text_body)?,
+        );
+    }
+    if let Some(preheader) = patch.preheader.as_ref().filter(|p| !p.is_empty()) {
+        doc.insert(
+            serde_yaml::Value::String("preheader".into()),
+            localized(preheader)?,
+        );
+    }
     set_opt!("cc", patch.cc);
     set_opt!("bcc", patch.bcc);
-    set_opt!("attachments", patch.attachments);
+    if let Some(attachments) = patch.attachments.as_ref().filter(|a| !a.is_empty()) {
+        doc.insert(
+            serde_yaml::Value::String("attachments".into()),
+            localized(attachments)?,
+        );
+    }
+    set_opt!("locale_field", patch.locale_field);
+    set_opt!("fallback_locale", patch.fallback_locale);
     set_opt!("stylesheet", patch.stylesheet);
     set_opt!("style", patch.style);
 
@@ -514,21 +778,55 @@ pub fn create_template(
 /// Validate all entries for a template with the given field overrides and sources.
 ///
 /// Returns per-entry validation status without saving anything to disk.
+/// Entries are validated off the main thread, emitting `validate-progress`
+/// events; a newer call or [`cancel_validate`] aborts one still running.
 #[tauri::command]
-pub fn preview_validate(
+pub async fn preview_validate(
+    app: tauri::AppHandle,
+    validate_state: tauri::State<'_, ValidateState>,
     template_path: String,
     fields: TemplatePatch,
     source_files: Vec<SourceFileSpec>,
 ) -> Result<PreviewValidation, String> {
-    let path = Path::new(&template_path);
+    let cancel = validate_state.start();
+    tauri::async_runtime::spawn_blocking(move || {
+        preview_validate_inner(&app, &template_path, &fields, &source_files, cancel)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn preview_validate_inner(
+    app: &tauri::AppHandle,
+    template_path: &str,
+    fields: &TemplatePatch,
+    source_files: &[SourceFileSpec],
+    cancel: Arc<AtomicBool>,
+) -> Result<PreviewValidation, String> {
+    use tauri::Emitter;
+
+    let path = Path::new(template_path);
     let mut template = mailnir_lib::template::parse_template(path).map_err(|e| e.to_string())?;
-    apply_patch(&mut template, &fields);
+    apply_patch(&mut template, fields);
 
     let template_dir = path.parent().unwrap_or(Path::new("."));
-    let sources = load_sources(&source_files)?;
+    let sources = load_sources(source_files)?;
 
-    let report = mailnir_lib::validate::validate_all(&template, &sources, template_dir)
-        .map_err(|e| e.to_string())?;
+    let app_handle = app.clone();
+    let report = mailnir_lib::validate::validate_all_with_progress(
+        &template,
+        &sources,
+        template_dir,
+        Some(cancel),
+        Some(Arc::new(move |progress| {
+            let _ = app_handle.emit("validate-progress", &progress);
+        })),
+    )
+    .map_err(|e| e.to_string())?;
+    let template_issues = mailnir_lib::validate::check_references(&template, &sources)
+        .iter()
+        .map(format_reference_issue)
+        .collect();
 
     let entries: Vec<PreviewEntryStatus> = report
         .entries
@@ -543,9 +841,17 @@ pub fn preview_validate(
     Ok(PreviewValidation {
         entry_count: entries.len(),
         entries,
+        template_issues,
     })
 }
 
+/// Cancel a running preview validation; it returns a `cancelled` error.
+#[tauri::command]
+pub fn cancel_validate(validate_state: tauri::State<'_, ValidateState>) -> Result<(), String> {
+    validate_state.cancel();
+    Ok(())
+}
+
 /// Render a single email entry for preview with the given field overrides.
 ///
 /// Returns the fully rendered email without saving anything to disk.
@@ -642,8 +948,9 @@ async fn send_batch_inner(
     apply_patch(&mut template, fields);
     let template_dir = path.parent().unwrap_or(Path::new("."));
 
-    // 2. Load sources.
+    // 2. Load sources and the environment (`.env` next to the template).
     let sources = load_sources(source_files)?;
+    let env = mailnir_lib::env::Environment::load(template_dir).map_err(|e| e.to_string())?;
 
     // 3. Build contexts (lenient — join failures become per-entry errors).
     let all_contexts = mailnir_lib::join::build_contexts_lenient(&template, &sources)
@@ -655,10 +962,13 @@ async fn send_batch_inner(
         None => (0..all_contexts.len()).collect(),
     };
 
-    // 5. Render emails for the selected entries.
+    // 5. Render emails for the selected entries, in parallel.
+    let compiled = mailnir_lib::render::CompiledTemplate::new(&template, template_dir, &env);
     let mut emails: Vec<mailnir_lib::render::RenderedEmail> = Vec::with_capacity(indices.len());
     let mut index_map: Vec<usize> = Vec::with_capacity(indices.len());
     let mut pre_send_failures: Vec<SendResultEntry> = Vec::new();
+    let mut selected: Vec<(usize, &serde_json::Map<String, Value>)> =
+        Vec::with_capacity(indices.len());
 
     for &idx in &indices {
         let ctx_result = all_contexts
@@ -674,21 +984,24 @@ async fn send_batch_inner(
                     error: Some(e.to_string()),
                 });
             }
-            Ok(context) => {
-                match mailnir_lib::render::render_context(&template, context, template_dir) {
-                    Err(e) => {
-                        pre_send_failures.push(SendResultEntry {
-                            entry_index: idx,
-                            recipient: String::new(),
-                            success: false,
-                            error: Some(e.to_string()),
-                        });
-                    }
-                    Ok(rendered) => {
-                        index_map.push(idx);
-                        emails.push(rendered);
-                    }
-                }
+            Ok(context) => selected.push((idx, context)),
+        }
+    }
+
+    let contexts: Vec<_> = selected.iter().map(|&(_, context)| context).collect();
+    for (&(idx, _), result) in selected.iter().zip(compiled.render_all(&contexts)) {
+        match result {
+            Err(e) => {
+                pre_send_failures.push(SendResultEntry {
+                    entry_index: idx,
+                    recipient: String::new(),
+                    success: false,
+                    error: Some(e.to_string()),
+                });
+            }
+            Ok(rendered) => {
+                index_map.push(idx);
+                emails.push(rendered);
             }
         }
     }
@@ -700,7 +1013,8 @@ async fn send_batch_inner(
         .iter()
         .find(|p| p.name == profile_name)
         .ok_or_else(|| format!("profile '{profile_name}' not found"))?
-        .clone();
+        .resolve_env(&env)
+        .map_err(|e| e.to_string())?;
     let credentials =
         mailnir_lib::smtp::retrieve_credential(profile_name).map_err(|e| e.to_string())?;
 
@@ -754,6 +1068,55 @@ pub fn cancel_send(send_state: tauri::State<'_, SendState>) -> Result<(), String
     Ok(())
 }
 
+/// Write the rendered emails as `.eml` files in `destination`, or as one mbox
+/// file at `destination`, for review in a mail client.
+///
+/// Messages carry the `From` address of `profile_name` and the MIME structure
+/// and attachments `send_batch` would send, plus their `Bcc` header.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_emails(
+    app: tauri::AppHandle,
+    template_path: String,
+    fields: TemplatePatch,
+    source_files: Vec<SourceFileSpec>,
+    profile_name: String,
+    format: mailnir_lib::export::ExportFormat,
+    destination: String,
+    file_name: Option<String>,
+) -> Result<mailnir_lib::export::ExportReport, String> {
+    let profiles_path = smtp_profiles_path(&app)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = Path::new(&template_path);
+        let mut template =
+            mailnir_lib::template::parse_template(path).map_err(|e| e.to_string())?;
+        apply_patch(&mut template, &fields);
+        let template_dir = path.parent().unwrap_or(Path::new("."));
+        let sources = load_sources(&source_files)?;
+
+        let env = mailnir_lib::env::Environment::load(template_dir).map_err(|e| e.to_string())?;
+        let profiles =
+            mailnir_lib::smtp::load_profiles(&profiles_path).map_err(|e| e.to_string())?;
+        let profile = profiles
+            .iter()
+            .find(|p| p.name == profile_name)
+            .ok_or_else(|| format!("profile '{profile_name}' not found"))?
+            .resolve_env(&env)
+            .map_err(|e| e.to_string())?;
+
+        let options = mailnir_lib::export::ExportOptions {
+            format,
+            destination: PathBuf::from(destination),
+            file_name: file_name.filter(|f| !f.trim().is_empty()),
+            from: profile.from,
+        };
+        mailnir_lib::export::export_emails(&template, &sources, template_dir, &options)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 // ── Helpers ───────────────────────────────────────────────────────────────────
 
 fn smtp_profiles_path(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
@@ -777,7 +1140,7 @@ fn load_sources(specs: &[SourceFileSpec]) -> Result<HashMap<String, Value>, Stri
         let value = if let Some(form_data) = &spec.form_data {
             let obj: serde_json::Map<String, Value> = form_data
                 .iter()
-                .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                .map(|(k, v)| (k.clone(), v.clone()))
                 .collect();
             Value::Array(vec![Value::Object(obj)])
         } else {
@@ -804,48 +1167,78 @@ fn load_sources(specs: &[SourceFileSpec]) -> Result<HashMap<String, Value>, Stri
 
 /// Build a serde_yaml sources mapping from a list of SourceSpecs.
 fn build_sources_yaml(specs: &[SourceSpec]) -> serde_yaml::Mapping {
-    let mut sources_map = serde_yaml::Mapping::new();
-    for spec in specs {
-        let mut source_cfg = serde_yaml::Mapping::new();
-        if spec.primary == Some(true) {
-            source_cfg.insert(
-                serde_yaml::Value::String("primary".into()),
-                serde_yaml::Value::Bool(true),
-            );
-        }
-        if let Some(join) = &spec.join {
-            if !join.is_empty() {
-                let mut join_map = serde_yaml::Mapping::new();
-                for (k, v) in join {
-                    join_map.insert(
-                        serde_yaml::Value::String(k.clone()),
-                        serde_yaml::Value::String(v.clone()),
-                    );
-                }
-                source_cfg.insert(
-                    serde_yaml::Value::String("join".into()),
-                    serde_yaml::Value::Mapping(join_map),
+    specs
+        .iter()
+        .map(|spec| {
+            (
+                serde_yaml::Value::String(spec.namespace.clone()),
+                serde_yaml::Value::Mapping(source_yaml(spec)),
+            )
+        })
+        .collect()
+}
+
+/// One source's configuration, with defaults left out.
+fn source_yaml(spec: &SourceSpec) -> serde_yaml::Mapping {
+    let mut source_cfg = serde_yaml::Mapping::new();
+    if spec.primary == Some(true) {
+        source_cfg.insert(
+            serde_yaml::Value::String("primary".into()),
+            serde_yaml::Value::Bool(true),
+        );
+    }
+    if let Some(join) = &spec.join {
+        if !join.is_empty() {
+            let mut join_map = serde_yaml::Mapping::new();
+            for (k, v) in join {
+                // Equality shorthand serializes as a plain string, operators as a map.
+                join_map.insert(
+                    serde_yaml::Value::String(k.clone()),
+                    serde_yaml::to_value(v).expect("join condition is plain data"),
                 );
             }
-        }
-        if spec.many == Some(true) {
-            source_cfg.insert(
-                serde_yaml::Value::String("many".into()),
-                serde_yaml::Value::Bool(true),
-            );
-        }
-        if spec.form == Some(true) {
             source_cfg.insert(
-                serde_yaml::Value::String("form".into()),
-                serde_yaml::Value::Bool(true),
+                serde_yaml::Value::String("join".into()),
+                serde_yaml::Value::Mapping(join_map),
             );
         }
-        sources_map.insert(
-            serde_yaml::Value::String(spec.namespace.clone()),
-            serde_yaml::Value::Mapping(source_cfg),
+    }
+    if spec.many == Some(true) {
+        source_cfg.insert(
+            serde_yaml::Value::String("many".into()),
+            serde_yaml::Value::Bool(true),
+        );
+    }
+    if spec.form == Some(true) {
+        source_cfg.insert(
+            serde_yaml::Value::String("form".into()),
+            serde_yaml::Value::Bool(true),
+        );
+    }
+    if let Some(fields) = spec.fields.as_ref().filter(|f| !f.is_empty()) {
+        source_cfg.insert(
+            serde_yaml::Value::String("fields".into()),
+            serde_yaml::to_value(fields).expect("form fields are plain data"),
+        );
+    }
+    if let Some(computed) = spec.computed.as_ref().filter(|c| !c.is_empty()) {
+        source_cfg.insert(
+            serde_yaml::Value::String("computed".into()),
+            serde_yaml::to_value(computed).expect("computed fields are plain data"),
         );
     }
-    sources_map
+    source_cfg
+}
+
+fn source_config(spec: &SourceSpec) -> mailnir_lib::template::SourceConfig {
+    mailnir_lib::template::SourceConfig {
+        primary: spec.primary,
+        join: spec.join.clone(),
+        many: spec.many,
+        form: spec.form,
+        fields: spec.fields.clone(),
+        computed: spec.computed.clone(),
+    }
 }
 
 /// Validate source specs by building a temporary Template and calling validate_sources.
@@ -853,41 +1246,87 @@ fn validate_source_specs(specs: &[SourceSpec]) -> Result<(), String> {
     use mailnir_lib::template::{SourceConfig, Template};
     let sources: HashMap<String, SourceConfig> = specs
         .iter()
-        .map(|s| {
-            (
-                s.namespace.clone(),
-                SourceConfig {
-                    primary: s.primary,
-                    join: s.join.clone(),
-                    many: s.many,
-                    form: s.form,
-                },
-            )
-        })
+        .map(|s| (s.namespace.clone(), source_config(s)))
         .collect();
     let template = Template {
         sources,
+        vars: None,
         to: String::new(),
-        subject: String::new(),
-        body: String::new(),
+        subject: String::new().into(),
+        body: String::new().into(),
+        text_body: None,
+        preheader: None,
         cc: None,
         bcc: None,
         attachments: None,
+        locale_field: None,
+        fallback_locale: None,
+        translations: None,
+        tests: None,
         body_format: None,
+        markdown: None,
+        escape_html: None,
+        plain_text: None,
+        document: None,
         stylesheet: None,
         style: None,
+        css_mode: None,
+        version: None,
+        spans: Default::default(),
     };
     mailnir_lib::template::validate_sources(&template).map_err(|e| e.to_string())
 }
 
 /// Overlay current editor field values onto a parsed template.
 fn apply_patch(template: &mut mailnir_lib::template::Template, patch: &TemplatePatch) {
+    // Spans describe the file on disk; drop those of fields the editor changed.
+    let stale: Vec<&str> = [
+        ("to", Some(template.to.as_str()), Some(patch.to.as_str())),
+        ("cc", template.cc.as_deref(), patch.cc.as_deref()),
+        ("bcc", template.bcc.as_deref(), patch.bcc.as_deref()),
+    ]
+    .into_iter()
+    .filter(|(_, old, new)| old != new)
+    .map(|(field, _, _)| field)
+    .chain(
+        [
+            ("subject", Some(&template.subject), Some(&patch.subject)),
+            ("body", Some(&template.body), Some(&patch.body)),
+            (
+                "text_body",
+                template.text_body.as_ref(),
+                patch.text_body.as_ref(),
+            ),
+            (
+                "preheader",
+                template.preheader.as_ref(),
+                patch.preheader.as_ref(),
+            ),
+            (
+                "attachments",
+                template.attachments.as_ref(),
+                patch.attachments.as_ref(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(field, _, _)| field),
+    )
+    .collect();
+    for field in stale {
+        template.spans.remove(field);
+    }
+
     template.to = patch.to.clone();
     template.cc = patch.cc.clone();
     template.bcc = patch.bcc.clone();
     template.subject = patch.subject.clone();
     template.body = patch.body.clone();
+    template.text_body = patch.text_body.clone().filter(|t| !t.is_empty());
+    template.preheader = patch.preheader.clone().filter(|p| !p.is_empty());
     template.attachments = patch.attachments.clone();
+    template.locale_field = patch.locale_field.clone();
+    template.fallback_locale = patch.fallback_locale.clone();
     template.stylesheet = patch.stylesheet.clone();
     template.style = patch.style.clone();
     template.body_format = match patch.body_format.as_deref() {
@@ -903,9 +1342,22 @@ fn format_issue(issue: &mailnir_lib::ValidationIssue) -> String {
     use mailnir_lib::validate::JoinFailureDetail;
     use mailnir_lib::ValidationIssue;
     match issue {
-        ValidationIssue::UnresolvedVariable { field, reason } => {
-            format!("Unresolved variable in {field}: {reason}")
-        }
+        ValidationIssue::UnresolvedVariable {
+            field,
+            reason,
+            field_position,
+            file_position,
+        } => match (file_position, field_position) {
+            (Some(p), _) => format!(
+                "Unresolved variable in {field} (line {}, column {}): {reason}",
+                p.line, p.column
+            ),
+            (None, Some(p)) => format!(
+                "Unresolved variable in {field} ({field} line {}, column {}): {reason}",
+                p.line, p.column
+            ),
+            (None, None) => format!("Unresolved variable in {field}: {reason}"),
+        },
         ValidationIssue::JoinFailure { namespace, detail } => match detail {
             JoinFailureDetail::MissingMatch => {
                 format!("Join '{namespace}': no match found")
@@ -920,6 +1372,9 @@ fn format_issue(issue: &mailnir_lib::ValidationIssue) -> String {
         ValidationIssue::AttachmentNotFound { path } => {
             format!("Attachment not found: {}", path.display())
         }
+        ValidationIssue::InlineImageNotFound { path } => {
+            format!("Image not found: {}", path.display())
+        }
         ValidationIssue::RequiredFieldEmpty { field } => {
             format!("Required field empty: {field}")
         }
@@ -929,5 +1384,72 @@ fn format_issue(issue: &mailnir_lib::ValidationIssue) -> String {
         ValidationIssue::CssInlineError { reason } => {
             format!("CSS inlining error: {reason}")
         }
+        ValidationIssue::InvalidFormValue {
+            namespace,
+            field,
+            reason,
+        } => format!("Form '{namespace}' field '{field}' {reason}"),
+        ValidationIssue::UndefinedEnvVar { name } => {
+            format!("Environment variable '{name}' is not defined (process or .env)")
+        }
+        ValidationIssue::MissingLocaleVariant {
+            field,
+            locale: Some(locale),
+        } => format!("No '{field}' variant for locale '{locale}'"),
+        ValidationIssue::MissingLocaleVariant {
+            field,
+            locale: None,
+        } => format!("No locale for this entry and no fallback '{field}' variant"),
+        ValidationIssue::MissingTranslation {
+            locale: Some(locale),
+            id,
+        } => format!("No translation for '{id}' in locale '{locale}'"),
+        ValidationIssue::MissingTranslation { locale: None, id } => {
+            format!("No translation for '{id}' (entry has no locale and no fallback catalog)")
+        }
+        ValidationIssue::TranslationCatalogError { path, reason } => {
+            format!("Translation catalog {}: {reason}", path.display())
+        }
+    }
+}
+
+/// Convert a ReferenceIssue to a human-readable string.
+fn format_reference_issue(issue: &mailnir_lib::validate::ReferenceIssue) -> String {
+    use mailnir_lib::validate::ReferenceIssue;
+    let (message, suggestion) = match issue {
+        ReferenceIssue::UnknownJoinKey {
+            namespace,
+            join_key,
+            suggestion,
+        } => (
+            format!("Join '{namespace}': key '{join_key}' not found in '{namespace}' data"),
+            suggestion,
+        ),
+        ReferenceIssue::UnknownJoinRefField {
+            namespace,
+            ref_namespace,
+            ref_field,
+            suggestion,
+            ..
+        } => (
+            format!("Join '{namespace}': field '{ref_field}' not found in '{ref_namespace}' data"),
+            suggestion,
+        ),
+        ReferenceIssue::UnknownField {
+            namespace,
+            field,
+            suggestion,
+        } => (
+            format!("Field '{namespace}.{field}' not found in '{namespace}' data"),
+            suggestion,
+        ),
+        ReferenceIssue::UnknownVar { name, suggestion } => (
+            format!("Variable 'vars.{name}' is not declared"),
+            suggestion,
+        ),
+    };
+    match suggestion {
+        Some(s) => format!("{message} (did you mean '{s}'?)"),
+        None => message,
     }
 }
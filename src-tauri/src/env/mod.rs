@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// File next to a template whose `KEY=value` lines supplement the process
+/// environment.
+pub const DOTENV_FILE: &str = ".env";
+
+/// Environment variables visible to a template: `{{env.NAME}}` in template
+/// fields and `${NAME}` in SMTP profile fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+    values: HashMap<String, String>,
+}
+
+impl Environment {
+    /// The process environment only.
+    pub fn from_process() -> Self {
+        Environment {
+            values: std::env::vars().collect(),
+        }
+    }
+
+    /// `.env` in `template_dir` (if present), overridden by the process
+    /// environment.
+    pub fn load(template_dir: &Path) -> crate::Result<Self> {
+        let path = template_dir.join(DOTENV_FILE);
+        let mut values: HashMap<String, String> = match std::fs::read_to_string(&path) {
+            Ok(content) => parse_dotenv(&content).into_iter().collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(source) => return Err(crate::MailnirError::Io { path, source }),
+        };
+        values.extend(std::env::vars());
+        Ok(Environment { values })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Replace every `${NAME}` in `text`; `$$` is a literal `$`.
+    ///
+    /// On failure, returns the undefined names in order of appearance.
+    pub fn expand(&self, text: &str) -> Result<String, Vec<String>> {
+        let mut out = String::with_capacity(text.len());
+        let mut missing: Vec<String> = Vec::new();
+        let mut rest = text;
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            let after = &rest[i + 1..];
+            if let Some(tail) = after.strip_prefix('$') {
+                out.push('$');
+                rest = tail;
+            } else if let Some((name, tail)) = after
+                .strip_prefix('{')
+                .and_then(|a| a.split_once('}'))
+                .filter(|(name, _)| is_var_name(name))
+            {
+                match self.get(name) {
+                    Some(value) => out.push_str(value),
+                    None if !missing.iter().any(|m| m == name) => missing.push(name.to_string()),
+                    None => {}
+                }
+                rest = tail;
+            } else {
+                out.push('$');
+                rest = after;
+            }
+        }
+        out.push_str(rest);
+        if missing.is_empty() {
+            Ok(out)
+        } else {
+            Err(missing)
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Environment {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Environment {
+            values: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse `.env` content: `KEY=value` lines, optionally prefixed with
+/// `export`. Values may be single-quoted (literal) or double-quoted (with
+/// `\n`, `\t`, `\"` and `\\` escapes); unquoted values end at ` #`.
+/// Blank lines, comments and malformed lines are skipped.
+pub(crate) fn parse_dotenv(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            if !is_var_name(key) {
+                return None;
+            }
+            Some((key.to_string(), dotenv_value(value.trim())))
+        })
+        .collect()
+}
+
+fn dotenv_value(raw: &str) -> String {
+    if let Some(inner) = raw.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')) {
+        return inner.to_string();
+    }
+    if let Some(inner) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        }
+        return out;
+    }
+    match raw.find(" #") {
+        Some(i) => raw[..i].trim_end().to_string(),
+        None => raw.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dotenv() {
+        let parsed = parse_dotenv(
+            "# comment\n\nexport PORTAL=https://staging.example.com\nBCC = audit@example.com # team\nQUOTED=\"a\\nb\"\nRAW='x # y'\nnot a pair\n1BAD=x\n",
+        );
+        assert_eq!(
+            parsed,
+            vec![
+                ("PORTAL".into(), "https://staging.example.com".into()),
+                ("BCC".into(), "audit@example.com".into()),
+                ("QUOTED".into(), "a\nb".into()),
+                ("RAW".into(), "x # y".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let env: Environment = [("HOST", "smtp.example.com"), ("USER", "me")]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            env.expand("${USER}@${HOST}").unwrap(),
+            "me@smtp.example.com"
+        );
+        assert_eq!(env.expand("cost: $$5, $HOST").unwrap(), "cost: $5, $HOST");
+        assert_eq!(
+            env.expand("${A} ${HOST} ${B} ${A}").unwrap_err(),
+            vec!["A".to_string(), "B".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_load_process_overrides_dotenv() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(DOTENV_FILE),
+            "MAILNIR_TEST_DOTENV_ONLY=file\nPATH=from-file\n",
+        )
+        .unwrap();
+        let env = Environment::load(dir.path()).unwrap();
+        assert_eq!(env.get("MAILNIR_TEST_DOTENV_ONLY"), Some("file"));
+        assert_ne!(env.get("PATH"), Some("from-file"));
+    }
+
+    #[test]
+    fn test_load_without_dotenv() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Environment::load(dir.path()).is_ok());
+    }
+}
@@ -12,17 +12,83 @@ pub enum MailnirError {
         source: serde_yaml::Error,
     },
 
+    #[error("cannot edit the template in place ({reason}); rewriting it would drop comments and formatting")]
+    TemplateEdit { reason: String },
+
+    #[error("unsupported template version {version} (supported: 1 to {supported})")]
+    UnsupportedTemplateVersion { version: u64, supported: u32 },
+
+    #[error("'{namespace}' is reserved and cannot be used as a source name")]
+    ReservedNamespace { namespace: String },
+
+    #[error(
+        "computed field '{field}' in '{namespace}'{} failed: {reason}",
+        .row.map(|r| format!(" (row {})", r + 1)).unwrap_or_default()
+    )]
+    ComputedField {
+        namespace: String,
+        field: String,
+        /// Zero-based row index; `None` when the expression itself is invalid.
+        row: Option<usize>,
+        reason: String,
+    },
+
+    #[error("undefined environment variable{} {} in {location}",
+        if .names.len() == 1 { "" } else { "s" },
+        .names.join(", ")
+    )]
+    UndefinedEnvVar {
+        names: Vec<String>,
+        /// `template` or `SMTP profile '<name>'`.
+        location: String,
+    },
+
+    #[error(
+        "'{field}' has no variant for {} and no fallback_locale variant",
+        .locale.as_deref().map(|l| format!("locale '{l}'")).unwrap_or_else(|| "an entry without a locale".into())
+    )]
+    MissingLocaleVariant {
+        field: String,
+        /// The entry's locale, or `None` if `locale_field` gave none.
+        locale: Option<String>,
+    },
+
+    #[error("invalid body settings: {reason}")]
+    InvalidBodyConfig { reason: String },
+
+    #[error("invalid locale settings: {reason}")]
+    InvalidLocaleConfig { reason: String },
+
+    #[error("translation catalog {path}: {reason}")]
+    TranslationCatalog {
+        path: std::path::PathBuf,
+        reason: String,
+    },
+
+    #[error(
+        "no translation for {} in {}",
+        .ids.iter().map(|id| format!("'{id}'")).collect::<Vec<_>>().join(", "),
+        .locale.as_deref().map(|l| format!("locale '{l}'")).unwrap_or_else(|| "an entry without a locale".into())
+    )]
+    MissingTranslation {
+        /// Message ids passed to `{{t}}`, in order of first use.
+        ids: Vec<String>,
+        /// Locale of the catalog used, or the entry's locale if none matched.
+        locale: Option<String>,
+    },
+
     #[error("no source has primary: true")]
     NoPrimarySource,
 
     #[error("multiple sources declare primary: true: {namespaces:?}")]
     MultiplePrimarySource { namespaces: Vec<String> },
 
-    #[error("join in '{namespace}' key '{join_key}' has invalid ref '{ref_value}' (must be namespace.field)")]
+    #[error("join in '{namespace}' key '{join_key}' has invalid ref '{ref_value}': {reason}")]
     InvalidJoinRef {
         namespace: String,
         join_key: String,
         ref_value: String,
+        reason: String,
     },
 
     #[error("join in '{namespace}' references unknown namespace '{ref_namespace}'")]
@@ -35,6 +101,27 @@ pub enum MailnirError {
     #[error("source '{namespace}' joins on itself")]
     SelfJoin { namespace: String },
 
+    #[error(
+        "unknown key '{key}' in {location}{}{}",
+        .position.map(|p| format!(" at line {}, column {}", p.line, p.column)).unwrap_or_default(),
+        .suggestion.as_ref().map(|s| format!(" (did you mean '{s}'?)")).unwrap_or_default()
+    )]
+    UnknownKey {
+        key: String,
+        /// `template` for top-level keys, `sources.<namespace>` for source keys.
+        location: String,
+        position: Option<crate::template::TextPosition>,
+        /// Closest valid key, if any is near enough to be a typo.
+        suggestion: Option<String>,
+    },
+
+    #[error("form field '{field}' in '{namespace}' is invalid: {reason}")]
+    InvalidFormField {
+        namespace: String,
+        field: String,
+        reason: String,
+    },
+
     #[error("unsupported file format: '{extension}'")]
     UnsupportedFormat { extension: String },
 
@@ -87,7 +174,14 @@ pub enum MailnirError {
     },
 
     #[error("Handlebars render error in field '{field}': {reason}")]
-    HandlebarsRender { field: String, reason: String },
+    HandlebarsRender {
+        field: String,
+        reason: String,
+        /// Position within the field's string, when Handlebars reports one.
+        field_position: Option<crate::template::TextPosition>,
+        /// The same position in the template file, when the field's span is known.
+        file_position: Option<crate::template::TextPosition>,
+    },
 
     #[error("CSS inlining error: {reason}")]
     CssInline { reason: String },
@@ -95,6 +189,15 @@ pub enum MailnirError {
     #[error("stylesheet file not found: {path}")]
     StylesheetNotFound { path: std::path::PathBuf },
 
+    #[error("bundle {path}: {reason}")]
+    Bundle {
+        path: std::path::PathBuf,
+        reason: String,
+    },
+
+    #[error("{path} already exists")]
+    FileExists { path: std::path::PathBuf },
+
     #[error("profile JSON error in {path}: {source}")]
     ProfileJson {
         path: std::path::PathBuf,
@@ -109,4 +212,24 @@ pub enum MailnirError {
 
     #[error("keyring error: {reason}")]
     Keyring { reason: String },
+
+    #[error("cancelled")]
+    Cancelled,
+}
+
+impl MailnirError {
+    /// Line and column in the template file the error points at, if known.
+    pub fn file_position(&self) -> Option<crate::template::TextPosition> {
+        match self {
+            MailnirError::TemplateParseYaml { source, .. } => {
+                source.location().map(|l| crate::template::TextPosition {
+                    line: l.line(),
+                    column: l.column(),
+                })
+            }
+            MailnirError::HandlebarsRender { file_position, .. } => *file_position,
+            MailnirError::UnknownKey { position, .. } => *position,
+            _ => None,
+        }
+    }
 }
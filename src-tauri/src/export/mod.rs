@@ -0,0 +1,498 @@
+//! Writing rendered emails to disk, as one `.eml` file per entry or a single
+//! mbox, for review in a mail client before sending.
+//!
+//! Messages are built by [`smtp::build_message_keeping_bcc`], so each file
+//! has the MIME structure and attachments the entry would be sent with, plus
+//! its `Bcc` header.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use handlebars::{BlockContext, Context, RenderContext, Renderable, StringOutput};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::env::Environment;
+use crate::join::build_contexts_lenient;
+use crate::render::{make_handlebars, CompiledTemplate};
+use crate::smtp;
+use crate::template::Template;
+use crate::MailnirError;
+
+/// `.eml` file name used when no pattern is given.
+pub const DEFAULT_FILE_NAME: &str = "{{@index}}.eml";
+
+/// How exported messages are laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// One RFC 5322 message per entry in a directory.
+    Eml,
+    /// Every message in one mbox file (mboxrd quoting).
+    Mbox,
+}
+
+/// Where and how to export.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Directory for `.eml` files, or the mbox file.
+    pub destination: PathBuf,
+    /// Handlebars pattern for `.eml` file names, rendered against each entry's
+    /// context; `{{@index}}` is the entry index. Defaults to [`DEFAULT_FILE_NAME`].
+    pub file_name: Option<String>,
+    /// `From` address, as the sending profile would set it.
+    pub from: String,
+}
+
+/// One exported entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedEntry {
+    pub entry_index: usize,
+    /// The entry's `.eml` file, or the mbox it was appended to.
+    pub path: PathBuf,
+}
+
+/// An entry that could not be exported.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportFailure {
+    pub entry_index: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportReport {
+    /// In entry order.
+    pub exported: Vec<ExportedEntry>,
+    pub failures: Vec<ExportFailure>,
+}
+
+/// Render every primary entry and write the messages to `options.destination`.
+///
+/// Entries that fail to join, render or build are listed in the report's
+/// failures and skipped. Returns `Err` for structural problems (no primary
+/// source, an invalid file name pattern), for `.eml` files or an mbox that
+/// already exist (nothing is written then) and for files that cannot be
+/// written.
+pub fn export_emails(
+    template: &Template,
+    sources: &HashMap<String, Value>,
+    template_dir: &Path,
+    options: &ExportOptions,
+) -> crate::Result<ExportReport> {
+    let contexts = build_contexts_lenient(template, sources)?;
+    let env = Environment::load(template_dir)?;
+    let compiled = CompiledTemplate::new(template, template_dir, &env);
+    let file_name = options.file_name.as_deref().unwrap_or(DEFAULT_FILE_NAME);
+    let file_name =
+        handlebars::Template::compile(file_name).map_err(|e| MailnirError::HandlebarsRender {
+            field: "file_name".into(),
+            reason: e.to_string(),
+            field_position: e
+                .pos()
+                .map(|(line, column)| crate::template::TextPosition { line, column }),
+            file_position: None,
+        })?;
+
+    let mut report = ExportReport {
+        exported: Vec::new(),
+        failures: Vec::new(),
+    };
+    let mut entries: Vec<(usize, &Map<String, Value>)> = Vec::with_capacity(contexts.len());
+    for (entry_index, context) in contexts.iter().enumerate() {
+        match context {
+            Ok(context) => entries.push((entry_index, context)),
+            Err(e) => report.failures.push(ExportFailure {
+                entry_index,
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    // Where each entry goes; `.eml` names come from the entry's context.
+    let mut targets: Vec<(usize, &Map<String, Value>, PathBuf)> = Vec::new();
+    let mut used_names = HashSet::new();
+    for (entry_index, context) in entries {
+        let path = match options.format {
+            ExportFormat::Eml => match render_file_name(&file_name, context, entry_index) {
+                Ok(name) => {
+                    options
+                        .destination
+                        .join(unique_name(&name, entry_index, &mut used_names))
+                }
+                Err(e) => {
+                    report.failures.push(ExportFailure {
+                        entry_index,
+                        reason: e.to_string(),
+                    });
+                    continue;
+                }
+            },
+            ExportFormat::Mbox => options.destination.clone(),
+        };
+        targets.push((entry_index, context, path));
+    }
+
+    // Refuse to overwrite earlier exports, as importing a bundle does.
+    if let Some((_, _, path)) = targets.iter().find(|(_, _, path)| path.exists()) {
+        return Err(MailnirError::FileExists { path: path.clone() });
+    }
+    if options.format == ExportFormat::Eml {
+        create_dir(&options.destination)?;
+    }
+    // Created with the first message, so failing every entry leaves no file.
+    let mut mbox: Option<BufWriter<File>> = None;
+
+    let contexts: Vec<_> = targets.iter().map(|&(_, context, _)| context).collect();
+    let rendered = compiled.render_all(&contexts);
+    for ((entry_index, _, path), email) in targets.into_iter().zip(rendered) {
+        let message = email
+            .and_then(|email| smtp::build_message_keeping_bcc(&email, &options.from, entry_index));
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                report.failures.push(ExportFailure {
+                    entry_index,
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        match options.format {
+            ExportFormat::Mbox => {
+                let mbox = match &mut mbox {
+                    Some(mbox) => mbox,
+                    None => mbox.insert(BufWriter::new(create_new(&path)?)),
+                };
+                let sender = message
+                    .envelope()
+                    .from()
+                    .map_or_else(|| "MAILER-DAEMON".to_string(), ToString::to_string);
+                append_mbox(mbox, &sender, SystemTime::now(), &message.formatted())
+                    .map_err(|e| io_err(&path, e))?;
+            }
+            ExportFormat::Eml => write(&path, &message.formatted())?,
+        }
+        report.exported.push(ExportedEntry { entry_index, path });
+    }
+    if let Some(mut mbox) = mbox {
+        mbox.flush().map_err(|e| io_err(&options.destination, e))?;
+    }
+
+    report.failures.sort_by_key(|f| f.entry_index);
+    Ok(report)
+}
+
+/// The entry's file name: the rendered pattern with path separators and
+/// characters that are invalid on common file systems replaced by `_`, and
+/// `.eml` appended if missing.
+fn render_file_name(
+    pattern: &handlebars::Template,
+    context: &Map<String, Value>,
+    entry_index: usize,
+) -> crate::Result<String> {
+    let hbs = make_handlebars();
+    let data = Context::from(Value::Object(context.clone()));
+    let mut rc = RenderContext::new(None);
+    let mut block = BlockContext::new();
+    block.set_local_var("index", Value::from(entry_index));
+    rc.push_block(block);
+    let mut out = StringOutput::new();
+    let rendered = pattern
+        .render(&hbs, &data, &mut rc, &mut out)
+        .and_then(|()| out.into_string().map_err(handlebars::RenderError::from))
+        .map_err(|e| MailnirError::HandlebarsRender {
+            field: "file_name".into(),
+            reason: e.to_string(),
+            field_position: None,
+            file_position: None,
+        })?;
+    let mut name: String = rendered
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    name = name.trim_matches('.').to_string();
+    if name.is_empty() {
+        name = entry_index.to_string();
+    }
+    if !name.to_ascii_lowercase().ends_with(".eml") {
+        name.push_str(".eml");
+    }
+    Ok(name)
+}
+
+/// `name`, or `name` with the entry index appended to its stem if an earlier
+/// entry already took it.
+fn unique_name(name: &str, entry_index: usize, used: &mut HashSet<String>) -> String {
+    let mut candidate = name.to_string();
+    if used.contains(&candidate.to_lowercase()) {
+        let stem = &name[..name.len() - ".eml".len()];
+        candidate = format!("{stem}-{entry_index}.eml");
+        let mut n = 2;
+        while used.contains(&candidate.to_lowercase()) {
+            candidate = format!("{stem}-{entry_index}-{n}.eml");
+            n += 1;
+        }
+    }
+    used.insert(candidate.to_lowercase());
+    candidate
+}
+
+/// Append one message in mboxrd format: a `From ` separator line, the
+/// message with LF line endings and `>`-quoted `From ` lines, and a blank line.
+fn append_mbox(
+    mbox: &mut impl Write,
+    sender: &str,
+    date: SystemTime,
+    message: &[u8],
+) -> std::io::Result<()> {
+    writeln!(mbox, "From {sender} {}", asctime(date))?;
+    let message = String::from_utf8_lossy(message);
+    for line in message.split_terminator('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim_start_matches('>').starts_with("From ") {
+            mbox.write_all(b">")?;
+        }
+        writeln!(mbox, "{line}")?;
+    }
+    writeln!(mbox)
+}
+
+/// `date` in UTC as C's `asctime`, e.g. `Thu Jan  1 00:00:00 1970`.
+fn asctime(date: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = date.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = secs / 86_400;
+    let (hour, minute, second) = (secs % 86_400 / 3600, secs % 3600 / 60, secs % 60);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{} {} {day:>2} {hour:02}:{minute:02}:{second:02} {year}",
+        DAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+    )
+}
+
+fn io_err(path: &Path, source: std::io::Error) -> MailnirError {
+    MailnirError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+fn create_dir(path: &Path) -> crate::Result<()> {
+    std::fs::create_dir_all(path).map_err(|e| io_err(path, e))
+}
+
+/// A new, empty file at `path`, creating its directory.
+fn create_new(path: &Path) -> crate::Result<File> {
+    if let Some(parent) = path.parent() {
+        create_dir(parent)?;
+    }
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => MailnirError::FileExists {
+                path: path.to_path_buf(),
+            },
+            _ => io_err(path, e),
+        })
+}
+
+fn write(path: &Path, bytes: &[u8]) -> crate::Result<()> {
+    std::fs::write(path, bytes).map_err(|e| io_err(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::template::parse_template_str;
+
+    fn sources(rows: Value) -> HashMap<String, Value> {
+        [("p".to_string(), rows)].into_iter().collect()
+    }
+
+    fn options(format: ExportFormat, destination: PathBuf) -> ExportOptions {
+        ExportOptions {
+            format,
+            destination,
+            file_name: Some("{{p.name}}.eml".into()),
+            from: "sender@example.com".into(),
+        }
+    }
+
+    #[test]
+    fn test_eml_per_entry_with_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.pdf"), b"%PDF-fake").unwrap();
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: '{{p.email}}'\nbcc: audit@example.com\nsubject: Hi\nbody: '**{{p.name}}**'\nattachments: a.pdf",
+        )
+        .unwrap();
+        let rows = json!([
+            {"name": "Ann/Lee", "email": "ann@example.com"},
+            {"name": "Bo", "email": "not an address"},
+            {"name": "Ann/Lee", "email": "ann2@example.com"},
+        ]);
+        let out = dir.path().join("out");
+        let report = export_emails(
+            &t,
+            &sources(rows.clone()),
+            dir.path(),
+            &options(ExportFormat::Eml, out.clone()),
+        )
+        .unwrap();
+
+        let names: Vec<_> = report
+            .exported
+            .iter()
+            .map(|e| (e.entry_index, e.path.file_name().unwrap().to_str().unwrap()))
+            .collect();
+        assert_eq!(names, vec![(0, "Ann_Lee.eml"), (2, "Ann_Lee-2.eml")]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].entry_index, 1);
+
+        let raw = std::fs::read_to_string(out.join("Ann_Lee.eml")).unwrap();
+        assert!(raw.contains("\r\nBcc: audit@example.com\r\n"), "{raw}");
+        assert!(raw.contains("multipart/mixed"), "{raw}");
+        assert!(raw.contains("multipart/alternative"), "{raw}");
+        assert!(raw.contains("filename=\"a.pdf\""), "{raw}");
+
+        // Exporting again refuses to overwrite and writes nothing.
+        std::fs::write(out.join("Ann_Lee-2.eml"), "kept").unwrap();
+        std::fs::remove_file(out.join("Ann_Lee.eml")).unwrap();
+        let err = export_emails(
+            &t,
+            &sources(rows),
+            dir.path(),
+            &options(ExportFormat::Eml, out.clone()),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, MailnirError::FileExists { ref path } if path == &out.join("Ann_Lee-2.eml"))
+        );
+        assert!(!out.join("Ann_Lee.eml").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.join("Ann_Lee-2.eml")).unwrap(),
+            "kept"
+        );
+    }
+
+    #[test]
+    fn test_invalid_file_name_pattern_rejected() {
+        let t =
+            parse_template_str("sources:\n  p: {primary: true}\nto: a@b.com\nsubject: s\nbody: b")
+                .unwrap();
+        let mut options = options(ExportFormat::Eml, PathBuf::from("unused"));
+        options.file_name = Some("{{#if}}".into());
+        let err = export_emails(&t, &sources(json!([{}])), Path::new("."), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            MailnirError::HandlebarsRender { ref field, .. } if field == "file_name"
+        ));
+    }
+
+    #[test]
+    fn test_mbox_holds_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: '{{p.email}}'\nsubject: Hi\nbody: \"{{p.name}}\\nFrom here on\"\nbody_format: text",
+        )
+        .unwrap();
+        let rows = json!([{"name": "Ann", "email": "a@x.com"}, {"name": "Bo", "email": "b@x.com"}]);
+        let path = dir.path().join("review/out.mbox");
+        let report = export_emails(
+            &t,
+            &sources(rows),
+            dir.path(),
+            &options(ExportFormat::Mbox, path.clone()),
+        )
+        .unwrap();
+        assert_eq!(report.exported.len(), 2);
+        assert!(report.exported.iter().all(|e| e.path == path));
+
+        let mbox = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(mbox.matches("\nFrom sender@example.com ").count(), 1);
+        assert!(mbox.starts_with("From sender@example.com "));
+        assert_eq!(mbox.matches("\n>From here on\n").count(), 2, "{mbox}");
+        assert!(!mbox.contains('\r'));
+
+        // An existing mailbox is left alone.
+        let err = export_emails(
+            &t,
+            &sources(json!([{"name": "Cy", "email": "c@x.com"}])),
+            dir.path(),
+            &options(ExportFormat::Mbox, path.clone()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, MailnirError::FileExists { path: ref p } if p == &path));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), mbox);
+    }
+
+    #[test]
+    fn test_mbox_not_created_when_every_entry_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: '{{p.email}}'\nsubject: Hi\nbody: b\nbody_format: text",
+        )
+        .unwrap();
+        let path = dir.path().join("review/out.mbox");
+        let report = export_emails(
+            &t,
+            &sources(json!([{"email": "not an address"}])),
+            dir.path(),
+            &options(ExportFormat::Mbox, path.clone()),
+        )
+        .unwrap();
+        assert!(report.exported.is_empty());
+        assert_eq!(report.failures.len(), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_file_name_uses_index() {
+        let pattern = handlebars::Template::compile("{{@index}}-{{p.id}}").unwrap();
+        let context: Map<String, Value> = [("p".to_string(), json!({"id": "x:1"}))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            render_file_name(&pattern, &context, 7).unwrap(),
+            "7-x_1.eml"
+        );
+        let mut used = HashSet::new();
+        assert_eq!(unique_name("a.eml", 0, &mut used), "a.eml");
+        assert_eq!(unique_name("A.eml", 3, &mut used), "A-3.eml");
+    }
+
+    #[test]
+    fn test_asctime() {
+        assert_eq!(asctime(UNIX_EPOCH), "Thu Jan  1 00:00:00 1970");
+        let date = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(asctime(date), "Thu Feb 29 12:34:56 2024");
+    }
+}
@@ -0,0 +1,440 @@
+//! Translation catalogs behind the `{{t "id" name=...}}` helper.
+//!
+//! A template lists one catalog per locale under `translations:`; each entry
+//! renders with the catalog chosen for its locale (see `locale_field`).
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError,
+    RenderErrorReason, Renderable, StringOutput,
+};
+use serde_json::{Map, Value};
+
+use crate::render::make_handlebars;
+use crate::template::{select_locale, Template};
+use crate::MailnirError;
+
+mod plural;
+mod po;
+
+use po::{PoCatalog, PoMessage};
+
+/// Name of the translation helper.
+pub const HELPER_NAME: &str = "t";
+
+/// The hash argument that picks a gettext plural form.
+pub const COUNT_ARG: &str = "count";
+
+/// Catalog file formats, by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogFormat {
+    /// Project Fluent (`.ftl`); arguments are Fluent variables.
+    Fluent,
+    /// GNU gettext (`.po`); messages are Handlebars over the arguments.
+    Gettext,
+}
+
+impl CatalogFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ftl" => Some(CatalogFormat::Fluent),
+            "po" => Some(CatalogFormat::Gettext),
+            _ => None,
+        }
+    }
+}
+
+/// A loaded catalog for one locale.
+pub(crate) enum Catalog {
+    Fluent(Box<FluentBundle<FluentResource>>),
+    Gettext(Box<GettextCatalog>),
+}
+
+/// A `.po` catalog whose translations are compiled once, when it is loaded.
+pub(crate) struct GettextCatalog {
+    messages: PoCatalog,
+    /// Translation source → its compiled template.
+    compiled: HashMap<String, handlebars::Template>,
+    /// Registry every translation renders with.
+    hbs: Handlebars<'static>,
+}
+
+impl GettextCatalog {
+    fn new(messages: PoCatalog) -> Result<Self, String> {
+        let mut compiled = HashMap::new();
+        for (id, message) in &messages.messages {
+            let forms = match message {
+                PoMessage::Singular(text) => std::slice::from_ref(text),
+                PoMessage::Plural(forms) => forms.as_slice(),
+            };
+            for source in forms {
+                if !compiled.contains_key(source) {
+                    let template = handlebars::Template::compile(source)
+                        .map_err(|e| format!("message '{id}': {e}"))?;
+                    compiled.insert(source.clone(), template);
+                }
+            }
+        }
+        Ok(GettextCatalog {
+            messages,
+            compiled,
+            hbs: make_handlebars(),
+        })
+    }
+
+    fn translate(&self, id: &str, args: &Map<String, Value>) -> Result<Option<String>, String> {
+        let count = args.get(COUNT_ARG).and_then(count_value);
+        let Some(template) = self
+            .messages
+            .get(id, count)
+            .and_then(|source| self.compiled.get(source))
+        else {
+            return Ok(None);
+        };
+        let data = Context::wraps(args).map_err(|e| e.to_string())?;
+        let mut rc = RenderContext::new(None);
+        let mut out = StringOutput::new();
+        template
+            .render(&self.hbs, &data, &mut rc, &mut out)
+            .and_then(|()| out.into_string().map_err(RenderError::from))
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Catalog {
+    /// Load the catalog at `path` for `locale`; the locale drives Fluent's
+    /// plural categories, while `.po` files carry their own `Plural-Forms`.
+    pub fn load(path: &Path, locale: &str) -> crate::Result<Self> {
+        let failed = |reason: String| MailnirError::TranslationCatalog {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let format = CatalogFormat::from_path(path)
+            .ok_or_else(|| failed("expected a .ftl or .po file".into()))?;
+        let content = std::fs::read_to_string(path).map_err(|source| MailnirError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        match format {
+            CatalogFormat::Fluent => {
+                let resource = FluentResource::try_new(content).map_err(|(_, errors)| {
+                    failed(
+                        errors
+                            .iter()
+                            .map(|e| e.to_string())
+                            .collect::<Vec<_>>()
+                            .join("; "),
+                    )
+                })?;
+                let langid = locale
+                    .replace('_', "-")
+                    .parse()
+                    .map_err(|_| failed(format!("'{locale}' is not a valid locale tag")))?;
+                let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+                bundle.set_use_isolating(false);
+                bundle
+                    .add_resource(resource)
+                    .map_err(|errors| failed(errors[0].to_string()))?;
+                Ok(Catalog::Fluent(Box::new(bundle)))
+            }
+            CatalogFormat::Gettext => po::parse_po(&content)
+                .and_then(GettextCatalog::new)
+                .map(|catalog| Catalog::Gettext(Box::new(catalog)))
+                .map_err(failed),
+        }
+    }
+
+    /// Translate `id` with `args`. `Ok(None)` means the catalog has no such
+    /// message; `Err` is a formatting failure such as a missing argument.
+    pub fn translate(&self, id: &str, args: &Map<String, Value>) -> Result<Option<String>, String> {
+        match self {
+            Catalog::Fluent(bundle) => {
+                // `id.attr` addresses a message attribute.
+                let (message_id, attribute) = match id.split_once('.') {
+                    Some((message, attribute)) => (message, Some(attribute)),
+                    None => (id, None),
+                };
+                let Some(message) = bundle.get_message(message_id) else {
+                    return Ok(None);
+                };
+                let pattern = match attribute {
+                    Some(attribute) => message.get_attribute(attribute).map(|a| a.value()),
+                    None => message.value(),
+                };
+                let Some(pattern) = pattern else {
+                    return Ok(None);
+                };
+                let fluent_args: FluentArgs = args
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), fluent_value(value)))
+                    .collect();
+                let mut errors = Vec::new();
+                let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+                match errors.first() {
+                    Some(error) => Err(error.to_string()),
+                    None => Ok(Some(text.into_owned())),
+                }
+            }
+            Catalog::Gettext(catalog) => catalog.translate(id, args),
+        }
+    }
+}
+
+fn fluent_value(value: &Value) -> FluentValue<'static> {
+    match value {
+        Value::Number(n) => n.as_f64().map_or(FluentValue::None, FluentValue::from),
+        Value::String(s) => FluentValue::from(s.clone()),
+        Value::Null => FluentValue::None,
+        other => FluentValue::from(other.to_string()),
+    }
+}
+
+fn count_value(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64().or_else(|| n.as_f64().map(|f| f.abs() as u64)),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Catalogs of one template, each loaded on first use and shared by every
+/// entry with that locale.
+pub(crate) struct Catalogs {
+    template_dir: PathBuf,
+    /// Locale tag → catalog. Failed loads are not kept, so each entry
+    /// reports the error.
+    loaded: Mutex<HashMap<String, Arc<Catalog>>>,
+}
+
+impl Catalogs {
+    pub fn new(template_dir: &Path) -> Self {
+        Catalogs {
+            template_dir: template_dir.to_path_buf(),
+            loaded: Mutex::default(),
+        }
+    }
+
+    fn get(&self, tag: &str, path: &str) -> crate::Result<Arc<Catalog>> {
+        if let Some(catalog) = self.loaded.lock().ok().and_then(|l| l.get(tag).cloned()) {
+            return Ok(catalog);
+        }
+        let catalog = Arc::new(Catalog::load(&catalog_path(&self.template_dir, path), tag)?);
+        if let Ok(mut loaded) = self.loaded.lock() {
+            loaded.insert(tag.to_string(), Arc::clone(&catalog));
+        }
+        Ok(catalog)
+    }
+}
+
+/// The catalog chosen for one entry, plus the message ids it lacked.
+pub(crate) struct Translator {
+    /// Locale of the chosen catalog, or the entry's locale if none matched.
+    pub locale: Option<String>,
+    catalog: Option<Arc<Catalog>>,
+    missing: Mutex<Vec<String>>,
+}
+
+impl Translator {
+    /// Pick the catalog matching `locale` (or the template's fallback).
+    /// `None` when the template declares no translations.
+    pub fn for_entry(
+        template: &Template,
+        catalogs: &Catalogs,
+        locale: Option<&str>,
+    ) -> crate::Result<Option<Arc<Self>>> {
+        let Some(translations) = &template.translations else {
+            return Ok(None);
+        };
+        let selected = select_locale(translations, locale, template.fallback_locale.as_deref());
+        let translator = match selected {
+            Some((tag, path)) => Translator {
+                locale: Some(tag.to_string()),
+                catalog: Some(catalogs.get(tag, path)?),
+                missing: Mutex::default(),
+            },
+            None => Translator {
+                locale: locale.map(str::to_string),
+                catalog: None,
+                missing: Mutex::default(),
+            },
+        };
+        Ok(Some(Arc::new(translator)))
+    }
+
+    /// Register the `t` helper on `hbs`.
+    pub fn register(self: &Arc<Self>, hbs: &mut Handlebars<'_>) {
+        hbs.register_helper(HELPER_NAME, Box::new(TranslateHelper(Arc::clone(self))));
+    }
+
+    /// Message ids used so far that the catalog lacked, in order of first use.
+    pub fn missing(&self) -> Vec<String> {
+        self.missing.lock().map(|m| m.clone()).unwrap_or_default()
+    }
+
+    fn record_missing(&self, id: &str) {
+        if let Ok(mut missing) = self.missing.lock() {
+            if !missing.iter().any(|m| m == id) {
+                missing.push(id.to_string());
+            }
+        }
+    }
+}
+
+/// A catalog path from `translations:`, relative to the template directory.
+pub fn catalog_path(template_dir: &Path, path: &str) -> PathBuf {
+    template_dir.join(path)
+}
+
+struct TranslateHelper(Arc<Translator>);
+
+impl HelperDef for TranslateHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let id = h.param(0).and_then(|p| p.value().as_str()).ok_or_else(|| {
+            RenderErrorReason::Other(format!(
+                "{HELPER_NAME} expects a message id string as its first parameter"
+            ))
+        })?;
+        // Messages are trusted markup; only the data passed in is escaped.
+        let escape = (!rc.is_disable_escape()).then(|| r.get_escape_fn());
+        let args: Map<String, Value> = h
+            .hash()
+            .iter()
+            .map(|(name, value)| {
+                let value = match (value.value(), escape) {
+                    (Value::String(s), Some(escape)) => Value::String(escape(s)),
+                    (value, _) => value.clone(),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        let translated = match &self.0.catalog {
+            Some(catalog) => catalog
+                .translate(id, &args)
+                .map_err(|reason| RenderErrorReason::Other(format!("message '{id}': {reason}")))?,
+            None => None,
+        };
+        match translated {
+            Some(text) => out.write(&text)?,
+            None => {
+                // Keep rendering so every missing id of the entry is reported.
+                self.0.record_missing(id);
+                out.write(id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    fn write_catalog(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_fluent_plurals_follow_locale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_catalog(
+            dir.path(),
+            "pl.ftl",
+            "files = { $count ->\n    [one] { $count } plik\n    [few] { $count } pliki\n   *[many] { $count } plików\n}\ngreeting = Cześć { $name }\n    .title = Powitanie\n",
+        );
+        let catalog = Catalog::load(&path, "pl").unwrap();
+        let files = |n: u64| {
+            catalog
+                .translate("files", &args(json!({ "count": n })))
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(files(1), "1 plik");
+        assert_eq!(files(3), "3 pliki");
+        assert_eq!(files(5), "5 plików");
+        assert_eq!(
+            catalog
+                .translate("greeting", &args(json!({"name": "Ada"})))
+                .unwrap()
+                .as_deref(),
+            Some("Cześć Ada")
+        );
+        assert_eq!(
+            catalog
+                .translate("greeting.title", &Map::new())
+                .unwrap()
+                .as_deref(),
+            Some("Powitanie")
+        );
+        assert_eq!(catalog.translate("nope", &Map::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_gettext_messages_render_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_catalog(
+            dir.path(),
+            "de.po",
+            "msgid \"\"\nmsgstr \"Plural-Forms: nplurals=2; plural=n != 1;\\n\"\n\nmsgid \"greeting\"\nmsgstr \"Hallo {{name}}\"\n\nmsgid \"files\"\nmsgid_plural \"files\"\nmsgstr[0] \"eine Datei\"\nmsgstr[1] \"{{count}} Dateien\"\n",
+        );
+        let catalog = Catalog::load(&path, "de").unwrap();
+        assert_eq!(
+            catalog
+                .translate("greeting", &args(json!({"name": "Ada"})))
+                .unwrap()
+                .as_deref(),
+            Some("Hallo Ada")
+        );
+        assert_eq!(
+            catalog
+                .translate("files", &args(json!({"count": "4"})))
+                .unwrap()
+                .as_deref(),
+            Some("4 Dateien")
+        );
+    }
+
+    #[test]
+    fn test_invalid_catalogs_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let txt = write_catalog(dir.path(), "de.txt", "x");
+        assert!(matches!(
+            Catalog::load(&txt, "de"),
+            Err(MailnirError::TranslationCatalog { .. })
+        ));
+        let ftl = write_catalog(dir.path(), "de.ftl", "= broken\n");
+        assert!(matches!(
+            Catalog::load(&ftl, "de"),
+            Err(MailnirError::TranslationCatalog { .. })
+        ));
+        // gettext translations are compiled when the catalog loads.
+        let po = write_catalog(
+            dir.path(),
+            "de.po",
+            "msgid \"hi\"\nmsgstr \"Hallo {{#if}}\"\n",
+        );
+        assert!(matches!(
+            Catalog::load(&po, "de"),
+            Err(MailnirError::TranslationCatalog { ref reason, .. }) if reason.contains("'hi'")
+        ));
+    }
+}
@@ -0,0 +1,335 @@
+/// A gettext `Plural-Forms` rule: `nplurals=N; plural=EXPR;`.
+///
+/// `EXPR` is the C subset gettext allows (`n`, integer literals, parentheses
+/// and the operators `! * / % + - < <= > >= == != && || ?:`) and yields the
+/// `msgstr[i]` index for a count.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PluralRule {
+    pub nplurals: usize,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    N,
+    Int(u64),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl Op {
+    /// Binding power; higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Mul | Op::Div | Op::Rem => 6,
+            Op::Add | Op::Sub => 5,
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => 4,
+            Op::Eq | Op::Ne => 3,
+            Op::And => 2,
+            Op::Or => 1,
+        }
+    }
+}
+
+impl Default for PluralRule {
+    /// The Germanic rule gettext assumes without a header: `n != 1`.
+    fn default() -> Self {
+        PluralRule {
+            nplurals: 2,
+            expr: Expr::Binary(Op::Ne, Box::new(Expr::N), Box::new(Expr::Int(1))),
+        }
+    }
+}
+
+impl PluralRule {
+    /// Parse the value of a `Plural-Forms` header.
+    pub fn parse(header: &str) -> Result<Self, String> {
+        let mut nplurals = None;
+        let mut plural = None;
+        for part in header.split(';') {
+            let Some((key, value)) = part.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                "nplurals" => {
+                    nplurals = Some(
+                        value
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| format!("invalid nplurals '{}'", value.trim()))?,
+                    )
+                }
+                "plural" => plural = Some(value.trim()),
+                _ => {}
+            }
+        }
+        let nplurals = nplurals.ok_or("missing nplurals")?;
+        let plural = plural.ok_or("missing plural expression")?;
+        let tokens = tokenize(plural)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.conditional()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("unexpected input in plural expression '{plural}'"));
+        }
+        Ok(PluralRule { nplurals, expr })
+    }
+
+    /// The plural form index for `n`, clamped to `nplurals - 1`.
+    pub fn index(&self, n: u64) -> usize {
+        let index = eval(&self.expr, n).unwrap_or(0);
+        (index as usize).min(self.nplurals.saturating_sub(1))
+    }
+}
+
+/// `None` on division by zero.
+fn eval(expr: &Expr, n: u64) -> Option<u64> {
+    Some(match expr {
+        Expr::N => n,
+        Expr::Int(i) => *i,
+        Expr::Not(e) => u64::from(eval(e, n)? == 0),
+        Expr::Cond(c, a, b) => {
+            if eval(c, n)? != 0 {
+                eval(a, n)?
+            } else {
+                eval(b, n)?
+            }
+        }
+        Expr::Binary(op, a, b) => {
+            let a = eval(a, n)?;
+            // && and || short-circuit like C.
+            match op {
+                Op::And if a == 0 => return Some(0),
+                Op::Or if a != 0 => return Some(1),
+                _ => {}
+            }
+            let b = eval(b, n)?;
+            match op {
+                Op::Mul => a.wrapping_mul(b),
+                Op::Div => a.checked_div(b)?,
+                Op::Rem => a.checked_rem(b)?,
+                Op::Add => a.wrapping_add(b),
+                Op::Sub => a.wrapping_sub(b),
+                Op::Lt => u64::from(a < b),
+                Op::Le => u64::from(a <= b),
+                Op::Gt => u64::from(a > b),
+                Op::Ge => u64::from(a >= b),
+                Op::Eq => u64::from(a == b),
+                Op::Ne => u64::from(a != b),
+                Op::And | Op::Or => u64::from(b != 0),
+            }
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    N,
+    Int(u64),
+    Op(Op),
+    Not,
+    Question,
+    Colon,
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let two = |second: char| next == Some(second);
+        let token = match c {
+            ' ' | '\t' | '\n' | '\r' => continue,
+            'n' => Token::N,
+            '0'..='9' => {
+                let mut end = i + 1;
+                while let Some(&(j, d)) = chars.peek() {
+                    if !d.is_ascii_digit() {
+                        break;
+                    }
+                    end = j + 1;
+                    chars.next();
+                }
+                Token::Int(
+                    input[i..end]
+                        .parse()
+                        .map_err(|_| format!("number too large in '{input}'"))?,
+                )
+            }
+            '*' => Token::Op(Op::Mul),
+            '/' => Token::Op(Op::Div),
+            '%' => Token::Op(Op::Rem),
+            '+' => Token::Op(Op::Add),
+            '-' => Token::Op(Op::Sub),
+            '?' => Token::Question,
+            ':' => Token::Colon,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '<' if two('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if two('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '=' if two('=') => Token::Op(Op::Eq),
+            '!' if two('=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '&' if two('&') => Token::Op(Op::And),
+            '|' if two('|') => Token::Op(Op::Or),
+            other => {
+                return Err(format!(
+                    "unexpected '{other}' in plural expression '{input}'"
+                ))
+            }
+        };
+        if matches!(
+            token,
+            Token::Op(Op::Le | Op::Ge | Op::Eq | Op::Ne | Op::And | Op::Or)
+        ) {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected {token:?} in plural expression"))
+        }
+    }
+
+    /// `binary ('?' conditional ':' conditional)?`, right-associative.
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let cond = self.binary(1)?;
+        if self.peek() != Some(&Token::Question) {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.conditional()?;
+        self.expect(Token::Colon)?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// Precedence climbing over the left-associative binary operators.
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(op)) = self.peek().cloned() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::N) => {
+                self.pos += 1;
+                Ok(Expr::N)
+            }
+            Some(Token::Int(i)) => {
+                self.pos += 1;
+                Ok(Expr::Int(i))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let inner = self.conditional()?;
+                self.expect(Token::Close)?;
+                Ok(inner)
+            }
+            _ => Err("incomplete plural expression".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(header: &str, counts: &[u64]) -> Vec<usize> {
+        let rule = PluralRule::parse(header).unwrap();
+        counts.iter().map(|&n| rule.index(n)).collect()
+    }
+
+    #[test]
+    fn test_default_rule() {
+        let rule = PluralRule::default();
+        assert_eq!([0, 1, 2].map(|n| rule.index(n)), [1, 0, 1]);
+    }
+
+    #[test]
+    fn test_polish_rule() {
+        let header = "nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);";
+        assert_eq!(
+            indices(header, &[1, 2, 4, 5, 12, 22, 25, 112]),
+            [0, 1, 1, 2, 2, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    fn test_arabic_rule() {
+        let header = "nplurals=6; plural=n==0 ? 0 : n==1 ? 1 : n==2 ? 2 : n%100>=3 && n%100<=10 ? 3 : n%100>=11 ? 4 : 5;";
+        assert_eq!(indices(header, &[0, 1, 2, 3, 11, 100]), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_precedence_and_associativity() {
+        // 10 - 4 - 3 == 3 is true only with left associativity.
+        assert_eq!(
+            indices("nplurals=2; plural=10 - 4 - 3 == n;", &[3, 9]),
+            [1, 0]
+        );
+        assert_eq!(indices("nplurals=9; plural=1 + 2 * n;", &[3]), [7]);
+        assert_eq!(indices("nplurals=2; plural=!(n % 2);", &[3, 4]), [0, 1]);
+    }
+
+    #[test]
+    fn test_index_clamped_and_errors() {
+        assert_eq!(indices("nplurals=2; plural=n;", &[5]), [1]);
+        assert!(PluralRule::parse("nplurals=2; plural=n ==;").is_err());
+        assert!(PluralRule::parse("plural=n != 1;").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use super::plural::PluralRule;
+
+/// Translations from a gettext `.po` file, keyed by `msgid`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PoCatalog {
+    pub plural: PluralRule,
+    pub messages: HashMap<String, PoMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PoMessage {
+    Singular(String),
+    /// `msgstr[0]`, `msgstr[1]`, … in the order the plural rule indexes them.
+    Plural(Vec<String>),
+}
+
+impl PoCatalog {
+    /// The translation of `id`; `count` picks the plural form.
+    pub fn get(&self, id: &str, count: Option<u64>) -> Option<&str> {
+        match self.messages.get(id)? {
+            PoMessage::Singular(text) => Some(text),
+            PoMessage::Plural(forms) => {
+                let index = self.plural.index(count.unwrap_or(1));
+                forms.get(index).or(forms.first()).map(String::as_str)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entry {
+    context: Option<String>,
+    id: Option<String>,
+    plural_id: Option<String>,
+    strs: Vec<(usize, String)>,
+    fuzzy: bool,
+}
+
+/// Which string a continuation line (`"..."`) appends to.
+#[derive(Clone, Copy)]
+enum Target {
+    Context,
+    Id,
+    PluralId,
+    Str(usize),
+}
+
+/// Parse `.po` content. Fuzzy, obsolete (`#~`), context-qualified and
+/// untranslated entries are left out; the header's `Plural-Forms` sets the
+/// plural rule.
+pub(crate) fn parse_po(content: &str) -> Result<PoCatalog, String> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut entry = Entry::default();
+    let mut target: Option<Target> = None;
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let at = |reason: &str| format!("line {}: {reason}", number + 1);
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(flags) = line.strip_prefix("#,") {
+            if entry.id.is_some() {
+                entries.push(std::mem::take(&mut entry));
+            }
+            entry.fuzzy |= flags.split(',').any(|f| f.trim() == "fuzzy");
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('"') {
+            let text = unquote(line).ok_or_else(|| at("malformed string"))?;
+            let slot = match target.ok_or_else(|| at("string outside an entry"))? {
+                Target::Context => entry.context.get_or_insert_with(String::new),
+                Target::Id => entry.id.get_or_insert_with(String::new),
+                Target::PluralId => entry.plural_id.get_or_insert_with(String::new),
+                Target::Str(_) => match entry.strs.last_mut() {
+                    Some((_, text)) => text,
+                    None => return Err(at("string outside an entry")),
+                },
+            };
+            slot.push_str(&text);
+            continue;
+        }
+
+        let (keyword, rest) = line
+            .split_once(|c: char| c.is_whitespace())
+            .ok_or_else(|| at("expected keyword and string"))?;
+        let text = unquote(rest.trim()).ok_or_else(|| at("malformed string"))?;
+        let next = match keyword {
+            "msgctxt" => Target::Context,
+            "msgid" => Target::Id,
+            "msgid_plural" => Target::PluralId,
+            "msgstr" => Target::Str(0),
+            _ => match keyword
+                .strip_prefix("msgstr[")
+                .and_then(|k| k.strip_suffix(']'))
+                .and_then(|k| k.parse::<usize>().ok())
+            {
+                Some(index) => Target::Str(index),
+                None => return Err(at(&format!("unknown keyword '{keyword}'"))),
+            },
+        };
+        // A new msgctxt/msgid after a msgstr starts the next entry.
+        if matches!(next, Target::Context | Target::Id) && !entry.strs.is_empty() {
+            entries.push(std::mem::take(&mut entry));
+        }
+        match next {
+            Target::Context => entry.context = Some(text),
+            Target::Id => entry.id = Some(text),
+            Target::PluralId => entry.plural_id = Some(text),
+            Target::Str(index) => entry.strs.push((index, text)),
+        }
+        target = Some(next);
+    }
+    if entry.id.is_some() {
+        entries.push(entry);
+    }
+
+    let mut catalog = PoCatalog::default();
+    for entry in entries {
+        let Some(id) = entry.id else { continue };
+        if id.is_empty() {
+            let header = entry.strs.first().map(|(_, s)| s.as_str()).unwrap_or("");
+            if let Some(rule) = header.lines().find_map(|l| l.strip_prefix("Plural-Forms:")) {
+                catalog.plural = PluralRule::parse(rule)?;
+            }
+            continue;
+        }
+        if entry.fuzzy || entry.context.is_some() {
+            continue;
+        }
+        let message = if entry.plural_id.is_some() {
+            let mut strs = entry.strs;
+            strs.sort_by_key(|(index, _)| *index);
+            let forms: Vec<String> = strs.into_iter().map(|(_, s)| s).collect();
+            if forms.iter().all(String::is_empty) {
+                continue;
+            }
+            PoMessage::Plural(forms)
+        } else {
+            match entry.strs.into_iter().next() {
+                Some((_, text)) if !text.is_empty() => PoMessage::Singular(text),
+                _ => continue,
+            }
+        };
+        catalog.messages.insert(id, message);
+    }
+    Ok(catalog)
+}
+
+/// The contents of a C-style `"..."` literal.
+fn unquote(literal: &str) -> Option<String> {
+    let inner = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            other => out.push(other),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PL: &str = r#"
+msgid ""
+msgstr ""
+"Language: pl\n"
+"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);\n"
+
+# translator comment
+msgid "greeting"
+msgstr "Cześć {{name}}"
+
+msgid "files"
+msgid_plural "files"
+msgstr[0] "{{count}} plik"
+msgstr[1] "{{count}} pliki"
+msgstr[2] "{{count}} plików"
+
+#, fuzzy
+msgid "draft"
+msgstr "Szkic"
+
+msgid "untranslated"
+msgstr ""
+
+msgid "multi"
+msgstr ""
+"line one\n"
+"line two"
+"#;
+
+    #[test]
+    fn test_parse_po_messages() {
+        let catalog = parse_po(PL).unwrap();
+        assert_eq!(catalog.get("greeting", None), Some("Cześć {{name}}"));
+        assert_eq!(catalog.get("multi", None), Some("line one\nline two"));
+        assert_eq!(catalog.get("draft", None), None);
+        assert_eq!(catalog.get("untranslated", None), None);
+    }
+
+    #[test]
+    fn test_po_plural_forms_follow_header() {
+        let catalog = parse_po(PL).unwrap();
+        let form = |n| catalog.get("files", Some(n)).unwrap();
+        assert_eq!(form(1), "{{count}} plik");
+        assert_eq!(form(3), "{{count}} pliki");
+        assert_eq!(form(5), "{{count}} plików");
+        assert_eq!(form(22), "{{count}} pliki");
+    }
+
+    #[test]
+    fn test_parse_po_errors_name_line() {
+        let err = parse_po("msgid \"a\"\nmsgstr \"b\nbogus \"c\"\n").unwrap_err();
+        assert!(err.starts_with("line 2"), "{err}");
+    }
+}
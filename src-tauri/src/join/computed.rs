@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::render::make_handlebars;
+use crate::template::{Template, VARS_NAMESPACE};
+use crate::MailnirError;
+
+/// Add each source's `computed:` fields to its loaded rows.
+///
+/// Expressions see the row's own fields plus `vars`, not other computed
+/// fields, so declaration order does not matter. Results are strings and
+/// replace a loaded field of the same name. Borrows `sources` unchanged when
+/// no source declares computed fields.
+pub(crate) fn with_computed<'a>(
+    template: &Template,
+    sources: &'a HashMap<String, Value>,
+) -> crate::Result<Cow<'a, HashMap<String, Value>>> {
+    let mut namespaces: Vec<(&String, _)> = template
+        .sources
+        .iter()
+        .filter_map(|(ns, cfg)| Some((ns, cfg.computed.as_ref().filter(|c| !c.is_empty())?)))
+        .filter(|(ns, _)| sources.contains_key(ns.as_str()))
+        .collect();
+    if namespaces.is_empty() {
+        return Ok(Cow::Borrowed(sources));
+    }
+    namespaces.sort_by_key(|(ns, _)| ns.as_str());
+
+    let vars = template
+        .vars
+        .as_ref()
+        .map(|vars| Value::Object(vars.iter().map(|(k, v)| (k.clone(), v.clone())).collect()));
+
+    let mut hbs = make_handlebars();
+    let mut updated = sources.clone();
+    for (namespace, computed) in namespaces {
+        let failed =
+            |field: &str, row: Option<usize>, reason: String| MailnirError::ComputedField {
+                namespace: namespace.clone(),
+                field: field.to_string(),
+                row,
+                reason,
+            };
+        for (field, expression) in computed {
+            hbs.register_template_string(field, expression)
+                .map_err(|e| failed(field, None, e.to_string()))?;
+        }
+
+        let rows: Vec<&mut Value> = match updated.get_mut(namespace.as_str()) {
+            Some(Value::Array(rows)) => rows.iter_mut().collect(),
+            Some(single) => vec![single],
+            None => continue,
+        };
+        for (index, row) in rows.into_iter().enumerate() {
+            let Value::Object(fields) = row else { continue };
+            let mut scope: Map<String, Value> = fields.clone();
+            if let Some(vars) = &vars {
+                scope.insert(VARS_NAMESPACE.to_string(), vars.clone());
+            }
+            for field in computed.keys() {
+                let value = hbs
+                    .render(field, &scope)
+                    .map_err(|e| failed(field, Some(index), e.to_string()))?;
+                fields.insert(field.clone(), Value::String(value));
+            }
+        }
+        hbs.clear_templates();
+    }
+    Ok(Cow::Owned(updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn template(yaml: &str) -> Template {
+        crate::template::parse_template_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_no_computed_borrows() {
+        let t = template("sources:\n  p: {primary: true}\nto: a\nsubject: b\nbody: c");
+        let sources = HashMap::from([("p".to_string(), json!([{"a": 1}]))]);
+        assert!(matches!(
+            with_computed(&t, &sources).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_computed_fields_added_per_row() {
+        let t = template(
+            "vars:\n  term: Fall\nsources:\n  p:\n    primary: true\n    computed:\n      full_name: \"{{first}} {{last}}\"\n      label: \"{{vars.term}}-{{id}}\"\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = HashMap::from([(
+            "p".to_string(),
+            json!([{"id": 1, "first": "Ada", "last": "Lovelace"}, {"id": 2, "first": "Alan", "last": "Turing"}]),
+        )]);
+        let out = with_computed(&t, &sources).unwrap();
+        assert_eq!(out["p"][0]["full_name"], json!("Ada Lovelace"));
+        assert_eq!(out["p"][1]["label"], json!("Fall-2"));
+    }
+
+    #[test]
+    fn test_computed_on_single_object_source() {
+        let t = template(
+            "sources:\n  p: {primary: true}\n  f:\n    form: true\n    computed:\n      greeting: \"Hi {{name}}\"\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = HashMap::from([
+            ("p".to_string(), json!([])),
+            ("f".to_string(), json!({"name": "Bo"})),
+        ]);
+        let out = with_computed(&t, &sources).unwrap();
+        assert_eq!(out["f"]["greeting"], json!("Hi Bo"));
+    }
+
+    #[test]
+    fn test_missing_row_field_reports_row() {
+        let t = template(
+            "sources:\n  p:\n    primary: true\n    computed:\n      full_name: \"{{first}} {{last}}\"\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = HashMap::from([(
+            "p".to_string(),
+            json!([{"first": "A", "last": "B"}, {"first": "C"}]),
+        )]);
+        let err = with_computed(&t, &sources).unwrap_err();
+        assert!(matches!(
+            err,
+            MailnirError::ComputedField { ref field, row: Some(1), .. } if field == "full_name"
+        ));
+    }
+}
@@ -1,16 +1,23 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 
 use serde_json::{Map, Value};
 
-use crate::template::{SourceConfig, Template};
+use crate::template::{JoinClause, JoinOp, Template, VARS_NAMESPACE};
 use crate::MailnirError;
 
+mod computed;
+
 /// Build one merged context per primary source entry.
 ///
 /// Each context is a JSON object keyed by namespace name:
 /// - primary namespace → the entry object
 /// - global namespaces → the full source array
 /// - secondary namespaces → matched object (1:1) or array of objects (1:N)
+/// - `vars` → the template's static `vars:` map, when declared
+///
+/// Rows gain their source's `computed:` fields before any join runs.
 pub fn build_contexts(
     template: &Template,
     sources: &HashMap<String, Value>,
@@ -30,6 +37,37 @@ pub fn build_contexts_lenient(
     template: &Template,
     sources: &HashMap<String, Value>,
 ) -> crate::Result<Vec<crate::Result<Map<String, Value>>>> {
+    build_contexts_traced(template, sources, None)
+}
+
+/// Record of which secondary rows were joined during a run and which
+/// lookups found nothing. Used to build join diagnostics.
+#[derive(Debug, Default)]
+pub(crate) struct JoinTrace {
+    /// Secondary namespace → one flag per row, set once the row matched any entry.
+    pub used_rows: HashMap<String, Vec<bool>>,
+    /// 1:1 lookups that found no row, in entry order.
+    pub missing: Vec<MissingLookup>,
+}
+
+/// A 1:1 join lookup that matched no secondary row.
+#[derive(Debug)]
+pub(crate) struct MissingLookup {
+    pub entry_index: usize,
+    pub namespace: String,
+    /// Join key → value the secondary row was expected to have.
+    pub expected: BTreeMap<String, Value>,
+}
+
+/// Like [`build_contexts_lenient`], additionally recording row usage and
+/// missing lookups into `trace` when given.
+pub(crate) fn build_contexts_traced(
+    template: &Template,
+    sources: &HashMap<String, Value>,
+    mut trace: Option<&mut JoinTrace>,
+) -> crate::Result<Vec<crate::Result<Map<String, Value>>>> {
+    let sources = computed::with_computed(template, sources)?;
+    let sources: &HashMap<String, Value> = &sources;
     let primary_name = template
         .sources
         .iter()
@@ -54,28 +92,68 @@ pub fn build_contexts_lenient(
         .map(|(name, _)| name.as_str())
         .collect();
 
-    let secondary_sources_cfg: Vec<(&str, &SourceConfig)> = template
+    // Pre-validate secondary source shapes and join operators — structural
+    // failure, not per-entry.
+    let secondary_sources: Vec<Secondary> = template
         .sources
         .iter()
-        .filter(|(name, cfg)| name.as_str() != primary_name && cfg.join.is_some())
-        .map(|(name, cfg)| (name.as_str(), cfg))
-        .collect();
-
-    // Pre-validate secondary source shapes — structural failure, not per-entry.
-    let secondary_sources: Vec<(&str, &SourceConfig, &[Value])> = secondary_sources_cfg
-        .iter()
-        .map(|&(ns_name, ns_cfg)| {
-            let array = sources
+        .filter(|(name, _)| name.as_str() != primary_name)
+        .filter_map(|(name, cfg)| Some((name.as_str(), cfg, cfg.join.as_ref()?)))
+        .map(|(ns_name, ns_cfg, join_map)| {
+            let rows = sources
                 .get(ns_name)
                 .and_then(Value::as_array)
                 .ok_or_else(|| MailnirError::InvalidDataShape {
                     path: std::path::PathBuf::from(ns_name),
                     message: "secondary source must be an array".into(),
                 })?;
-            Ok((ns_name, ns_cfg, array.as_slice()))
+            let predicates = join_map
+                .iter()
+                .map(|(join_key, condition)| {
+                    let clauses =
+                        condition
+                            .clauses()
+                            .map_err(|reason| MailnirError::InvalidJoinRef {
+                                namespace: ns_name.to_string(),
+                                join_key: join_key.clone(),
+                                ref_value: condition.to_string(),
+                                reason,
+                            })?;
+                    Ok((join_key.as_str(), clauses))
+                })
+                .collect::<crate::Result<_>>()?;
+            Ok(Secondary {
+                name: ns_name,
+                many: ns_cfg.many == Some(true),
+                predicates,
+                rows: rows.as_slice(),
+            })
         })
         .collect::<crate::Result<_>>()?;
 
+    if let Some(trace) = trace.as_deref_mut() {
+        for secondary in &secondary_sources {
+            trace.used_rows.insert(
+                secondary.name.to_string(),
+                vec![false; secondary.rows.len()],
+            );
+        }
+    }
+
+    // Namespaces identical in every context: `vars` and global sources.
+    let mut shared: Map<String, Value> = Map::new();
+    if let Some(vars) = &template.vars {
+        shared.insert(
+            VARS_NAMESPACE.to_string(),
+            Value::Object(vars.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        );
+    }
+    for &global_name in &global_names {
+        if let Some(data) = sources.get(global_name) {
+            shared.insert(global_name.to_string(), data.clone());
+        }
+    }
+
     let mut results = Vec::with_capacity(primary_array.len());
 
     for (entry_index, primary_entry) in primary_array.iter().enumerate() {
@@ -83,15 +161,24 @@ pub fn build_contexts_lenient(
             primary_name,
             primary_entry,
             entry_index,
-            &global_names,
+            &shared,
             &secondary_sources,
-            sources,
+            trace.as_deref_mut(),
         ));
     }
 
     Ok(results)
 }
 
+/// A joined namespace with its rows and parsed join predicates.
+struct Secondary<'a> {
+    name: &'a str,
+    many: bool,
+    /// Join key → clauses the row's value under that key must satisfy.
+    predicates: Vec<(&'a str, Vec<JoinClause<'a>>)>,
+    rows: &'a [Value],
+}
+
 /// Build a merged context for a single primary source entry.
 ///
 /// Returns `Ok(context)` or `Err(JoinMissingMatch | JoinAmbiguousMatch)`.
@@ -100,29 +187,46 @@ fn build_single_context(
     primary_name: &str,
     primary_entry: &Value,
     entry_index: usize,
-    global_names: &[&str],
-    secondary_sources: &[(&str, &SourceConfig, &[Value])],
-    sources: &HashMap<String, Value>,
+    shared: &Map<String, Value>,
+    secondary_sources: &[Secondary],
+    mut trace: Option<&mut JoinTrace>,
 ) -> crate::Result<Map<String, Value>> {
-    let mut ctx: Map<String, Value> = Map::new();
+    let mut ctx: Map<String, Value> = shared.clone();
 
     ctx.insert(primary_name.to_string(), primary_entry.clone());
 
-    for &global_name in global_names {
-        if let Some(data) = sources.get(global_name) {
-            ctx.insert(global_name.to_string(), data.clone());
-        }
-    }
-
-    for &(ns_name, ns_cfg, secondary_array) in secondary_sources {
-        let join_map = ns_cfg.join.as_ref().expect("secondary always has join");
+    for secondary in secondary_sources {
+        let ns_name = secondary.name;
 
-        let matches: Vec<&Value> = secondary_array
+        let matched_indices: Vec<usize> = secondary
+            .rows
             .iter()
-            .filter(|row| predicates_match(row, join_map, &ctx))
+            .enumerate()
+            .filter(|(_, row)| predicates_match(row, &secondary.predicates, &ctx))
+            .map(|(i, _)| i)
+            .collect();
+
+        if let Some(trace) = trace.as_deref_mut() {
+            if let Some(used) = trace.used_rows.get_mut(ns_name) {
+                for &i in &matched_indices {
+                    used[i] = true;
+                }
+            }
+            if matched_indices.is_empty() && !secondary.many {
+                trace.missing.push(MissingLookup {
+                    entry_index,
+                    namespace: ns_name.to_string(),
+                    expected: expected_keys(&secondary.predicates, &ctx),
+                });
+            }
+        }
+
+        let matches: Vec<&Value> = matched_indices
+            .into_iter()
+            .map(|i| &secondary.rows[i])
             .collect();
 
-        if ns_cfg.many == Some(true) {
+        if secondary.many {
             ctx.insert(
                 ns_name.to_string(),
                 Value::Array(matches.into_iter().cloned().collect()),
@@ -154,22 +258,105 @@ fn build_single_context(
 
 /// Returns true if all join predicates hold for `row` against `ctx`.
 ///
-/// Each predicate: `row[join_key] == ctx[ref_ns][ref_field]`
+/// Each clause: `row[join_key] <op> ctx[ref_ns][ref_field]...`. A missing row
+/// key or unresolvable ref never matches.
 fn predicates_match(
     row: &Value,
-    join_map: &HashMap<String, String>,
+    predicates: &[(&str, Vec<JoinClause>)],
     ctx: &Map<String, Value>,
 ) -> bool {
-    join_map.iter().all(|(join_key, ref_value)| {
-        let Some((ref_ns, ref_field)) = ref_value.split_once('.') else {
+    predicates.iter().all(|(join_key, clauses)| {
+        let Some(actual) = row.get(join_key) else {
             return false;
         };
-        let expected = ctx.get(ref_ns).and_then(|ns| ns.get(ref_field));
-        let actual = row.get(join_key);
-        matches!((expected, actual), (Some(e), Some(a)) if e == a)
+        clauses.iter().all(|clause| {
+            let operands: Option<Vec<&Value>> =
+                clause.refs.iter().map(|r| resolve_ref(r, ctx)).collect();
+            operands.is_some_and(|operands| clause_holds(clause.op, actual, &operands))
+        })
     })
 }
 
+fn resolve_ref<'a>(ref_value: &str, ctx: &'a Map<String, Value>) -> Option<&'a Value> {
+    let (ref_ns, ref_field) = ref_value.split_once('.')?;
+    ctx.get(ref_ns)?.get(ref_field)
+}
+
+/// Evaluate one operator. `operands` has two values for `between`, one otherwise.
+fn clause_holds(op: JoinOp, actual: &Value, operands: &[&Value]) -> bool {
+    let at_least = |bound: &Value| compare_values(actual, bound).is_some_and(Ordering::is_ge);
+    let at_most = |bound: &Value| compare_values(actual, bound).is_some_and(Ordering::is_le);
+    match (op, operands) {
+        (JoinOp::Eq, [expected]) => actual == *expected,
+        (JoinOp::In, [expected]) => expected
+            .as_array()
+            .is_some_and(|items| items.contains(actual)),
+        (JoinOp::Contains, [expected]) => actual
+            .as_array()
+            .is_some_and(|items| items.contains(expected)),
+        (JoinOp::StartsWith, [prefix]) => match (scalar_text(actual), scalar_text(prefix)) {
+            (Some(a), Some(p)) => a.starts_with(p.as_ref()),
+            _ => false,
+        },
+        (JoinOp::Gte, [bound]) => at_least(bound),
+        (JoinOp::Lte, [bound]) => at_most(bound),
+        (JoinOp::Between, [low, high]) => at_least(low) && at_most(high),
+        _ => false,
+    }
+}
+
+/// Order two scalar values: numerically when both are numbers (or numeric
+/// strings, as loaded from CSV), otherwise as strings. Other types don't compare.
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    let as_number = |v: &Value| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    if let (Some(x), Some(y)) = (as_number(a), as_number(b)) {
+        return x.partial_cmp(&y);
+    }
+    match (a, b) {
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+/// Text form of a string or number, for prefix matching.
+fn scalar_text(v: &Value) -> Option<Cow<'_, str>> {
+    match v {
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        Value::Number(n) => Some(Cow::Owned(n.to_string())),
+        _ => None,
+    }
+}
+
+/// Resolve the values each join key is compared against for the current context.
+///
+/// Keys with several operands (e.g. `between`) map to an array. Unresolvable
+/// references map to `null`.
+fn expected_keys(
+    predicates: &[(&str, Vec<JoinClause>)],
+    ctx: &Map<String, Value>,
+) -> BTreeMap<String, Value> {
+    predicates
+        .iter()
+        .map(|(join_key, clauses)| {
+            let mut values: Vec<Value> = clauses
+                .iter()
+                .flat_map(|clause| clause.refs.iter())
+                .map(|r| resolve_ref(r, ctx).cloned().unwrap_or(Value::Null))
+                .collect();
+            let expected = if values.len() == 1 {
+                values.remove(0)
+            } else {
+                Value::Array(values)
+            };
+            (join_key.to_string(), expected)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -342,6 +529,134 @@ mod tests {
         ));
     }
 
+    // --- join operator tests ---
+
+    #[test]
+    fn test_contains_join_on_array_column() {
+        let t = make_template(
+            "sources:\n  classes: {primary: true}\n  students:\n    join:\n      courses: {contains: classes.id}\n    many: true\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[
+            ("classes", json!([{"id": "M1"}, {"id": "S2"}])),
+            (
+                "students",
+                json!([
+                    {"name": "Alice", "courses": ["M1", "S2"]},
+                    {"name": "Bob", "courses": ["S2"]},
+                    {"name": "Carol", "courses": "M1"},
+                ]),
+            ),
+        ]);
+
+        let ctxs = build_contexts(&t, &sources).expect("should succeed");
+        let names = |i: usize| -> Vec<Value> {
+            ctxs[i]["students"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|s| s["name"].clone())
+                .collect()
+        };
+        // A non-array column never "contains" anything.
+        assert_eq!(names(0), vec![json!("Alice")]);
+        assert_eq!(names(1), vec![json!("Alice"), json!("Bob")]);
+    }
+
+    #[test]
+    fn test_in_join_on_array_reference() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  rooms:\n    join:\n      id: {in: p.room_ids}\n    many: true\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[
+            ("p", json!([{"room_ids": [2, 3]}])),
+            ("rooms", json!([{"id": 1}, {"id": 2}, {"id": 3}])),
+        ]);
+
+        let ctxs = build_contexts(&t, &sources).expect("should succeed");
+        assert_eq!(ctxs[0]["rooms"], json!([{"id": 2}, {"id": 3}]));
+    }
+
+    #[test]
+    fn test_range_join_with_gte_lte() {
+        // valid_from <= classes.date <= valid_until, dates as ISO strings.
+        let t = make_template(
+            "sources:\n  classes: {primary: true}\n  rates:\n    join:\n      valid_from: {lte: classes.date}\n      valid_until: {gte: classes.date}\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[
+            (
+                "classes",
+                json!([{"date": "2024-03-15"}, {"date": "2024-09-01"}]),
+            ),
+            (
+                "rates",
+                json!([
+                    {"valid_from": "2024-01-01", "valid_until": "2024-06-30", "rate": "spring"},
+                    {"valid_from": "2024-07-01", "valid_until": "2024-12-31", "rate": "fall"},
+                ]),
+            ),
+        ]);
+
+        let ctxs = build_contexts(&t, &sources).expect("should succeed");
+        assert_eq!(ctxs[0]["rates"]["rate"], json!("spring"));
+        assert_eq!(ctxs[1]["rates"]["rate"], json!("fall"));
+    }
+
+    #[test]
+    fn test_between_join_compares_numeric_strings_numerically() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  grades:\n    join:\n      score: {between: [p.min, p.max]}\n    many: true\nto: a\nsubject: b\nbody: c",
+        );
+        // CSV-style string values: "9" must sort below "10".
+        let sources = make_sources(&[
+            ("p", json!([{"min": "5", "max": "10"}])),
+            (
+                "grades",
+                json!([{"score": "9"}, {"score": "10"}, {"score": "11"}, {"score": 7}]),
+            ),
+        ]);
+
+        let ctxs = build_contexts(&t, &sources).expect("should succeed");
+        assert_eq!(
+            ctxs[0]["grades"],
+            json!([{"score": "9"}, {"score": "10"}, {"score": 7}])
+        );
+    }
+
+    #[test]
+    fn test_starts_with_join() {
+        let t = make_template(
+            "sources:\n  dept: {primary: true}\n  courses:\n    join:\n      code: {starts_with: dept.prefix}\n    many: true\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[
+            ("dept", json!([{"prefix": "MA"}])),
+            (
+                "courses",
+                json!([{"code": "MA101"}, {"code": "PH101"}, {"code": "MA202"}]),
+            ),
+        ]);
+
+        let ctxs = build_contexts(&t, &sources).expect("should succeed");
+        assert_eq!(
+            ctxs[0]["courses"],
+            json!([{"code": "MA101"}, {"code": "MA202"}])
+        );
+    }
+
+    #[test]
+    fn test_malformed_operator_is_structural_error() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  s:\n    join:\n      id: {near: p.id}\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[("p", json!([{"id": 1}])), ("s", json!([{"id": 1}]))]);
+
+        let err = build_contexts_lenient(&t, &sources).expect_err("outer should fail");
+        assert!(matches!(
+            err,
+            MailnirError::InvalidJoinRef { namespace, join_key, .. }
+            if namespace == "s" && join_key == "id"
+        ));
+    }
+
     // --- build_contexts_lenient tests ---
 
     #[test]
@@ -403,4 +718,38 @@ mod tests {
         assert!(results[0].is_ok());
         assert!(results[1].is_ok());
     }
+
+    #[test]
+    fn test_vars_in_context_and_joins() {
+        let t = make_template(
+            "vars:\n  term: Fall\nsources:\n  classes: {primary: true}\n  rooms:\n    join:\n      term: vars.term\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[
+            ("classes", json!([{"id": 1}])),
+            (
+                "rooms",
+                json!([{"term": "Spring", "room": "A"}, {"term": "Fall", "room": "B"}]),
+            ),
+        ]);
+        let ctxs = build_contexts(&t, &sources).unwrap();
+        assert_eq!(ctxs[0]["vars"]["term"], json!("Fall"));
+        assert_eq!(ctxs[0]["rooms"]["room"], json!("B"));
+    }
+
+    #[test]
+    fn test_join_on_computed_field() {
+        let t = make_template(
+            "sources:\n  people:\n    primary: true\n    computed:\n      full_name: \"{{first}} {{last}}\"\n  badges:\n    join:\n      holder: people.full_name\nto: a\nsubject: b\nbody: c",
+        );
+        let sources = make_sources(&[
+            ("people", json!([{"first": "Ada", "last": "Lovelace"}])),
+            (
+                "badges",
+                json!([{"holder": "Ada Lovelace", "code": 7}, {"holder": "Alan Turing", "code": 8}]),
+            ),
+        ]);
+        let ctxs = build_contexts(&t, &sources).unwrap();
+        assert_eq!(ctxs[0]["people"]["full_name"], json!("Ada Lovelace"));
+        assert_eq!(ctxs[0]["badges"]["code"], json!(7));
+    }
 }
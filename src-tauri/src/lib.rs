@@ -1,11 +1,19 @@
+pub mod bundle;
 pub mod data;
+pub mod env;
 pub mod error;
+pub mod export;
+pub mod i18n;
 pub mod join;
 pub mod render;
 pub mod smtp;
+pub mod snapshot;
+mod suggest;
 pub mod template;
 pub mod validate;
 
 pub use error::MailnirError;
-pub use validate::{EntryResult, JoinFailureDetail, ValidationIssue, ValidationReport};
+pub use validate::{
+    EntryResult, JoinDiagnostics, JoinFailureDetail, ValidationIssue, ValidationReport,
+};
 pub type Result<T> = std::result::Result<T, MailnirError>;
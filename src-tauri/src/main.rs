@@ -12,9 +12,14 @@ fn main() {
             Ok(())
         })
         .manage(commands::SendState::default())
+        .manage(commands::ValidateState::default())
         .invoke_handler(tauri::generate_handler![
             commands::get_version_info,
             commands::parse_template_cmd,
+            commands::migrate_template_file,
+            commands::export_template_schema,
+            commands::export_bundle,
+            commands::import_bundle,
             commands::preview_csv,
             commands::get_smtp_profiles,
             commands::save_smtp_profiles,
@@ -23,12 +28,15 @@ fn main() {
             commands::test_smtp_connection,
             commands::get_data_fields,
             commands::get_form_fields,
+            commands::get_template_variables,
             commands::save_template,
             commands::create_template,
             commands::preview_validate,
+            commands::cancel_validate,
             commands::preview_render_entry,
             commands::send_batch,
             commands::cancel_send,
+            commands::export_emails,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
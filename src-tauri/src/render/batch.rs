@@ -0,0 +1,145 @@
+//! Rendering many entries at once over a pool of worker threads.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use serde_json::{Map, Value};
+
+use super::{CompiledTemplate, RenderedEmail};
+
+/// Apply `work` to every item on up to one worker per available core.
+///
+/// Results come back in `items` order whatever order the workers finish
+/// in. `on_done` runs on the calling thread as each item completes, with the
+/// number completed so far. Returns `None` if `cancel` was set before every
+/// item was taken; items already started still finish.
+pub(crate) fn map_parallel<T, R, W, D>(
+    items: &[T],
+    cancel: Option<&AtomicBool>,
+    work: W,
+    mut on_done: D,
+) -> Option<Vec<R>>
+where
+    T: Sync,
+    R: Send,
+    W: Fn(usize, &T) -> R + Sync,
+    D: FnMut(usize, usize, &R),
+{
+    let cancelled = || cancel.is_some_and(|c| c.load(Ordering::Relaxed));
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len());
+    let mut results: Vec<Option<R>> = std::iter::repeat_with(|| None).take(items.len()).collect();
+    let mut completed = 0;
+
+    if workers <= 1 {
+        for (index, item) in items.iter().enumerate() {
+            if cancelled() {
+                return None;
+            }
+            let result = work(index, item);
+            completed += 1;
+            on_done(completed, index, &result);
+            results[index] = Some(result);
+        }
+        return Some(results.into_iter().flatten().collect());
+    }
+
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (next, work) = (&next, &work);
+            scope.spawn(move || loop {
+                if cancelled() {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                if tx.send((index, work(index, item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+        for (index, result) in rx {
+            completed += 1;
+            on_done(completed, index, &result);
+            results[index] = Some(result);
+        }
+    });
+
+    if completed < items.len() {
+        return None;
+    }
+    Some(results.into_iter().flatten().collect())
+}
+
+impl CompiledTemplate<'_> {
+    /// Render every context in parallel, returning results in input order.
+    pub fn render_all(
+        &self,
+        contexts: &[&Map<String, Value>],
+    ) -> Vec<crate::Result<RenderedEmail>> {
+        map_parallel(
+            contexts,
+            None,
+            |_, context| self.render(context),
+            |_, _, _| {},
+        )
+        .expect("rendering without a cancel flag always completes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let items: Vec<u64> = (0..200).collect();
+        let mut seen = Vec::new();
+        let results = map_parallel(
+            &items,
+            None,
+            |index, item| {
+                // Later items finish first.
+                std::thread::sleep(std::time::Duration::from_micros(200 - item));
+                (index, item * 2)
+            },
+            |completed, index, _| seen.push((completed, index)),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            (0..200).map(|i| (i as usize, i * 2)).collect::<Vec<_>>()
+        );
+        let counts: Vec<usize> = seen.iter().map(|(completed, _)| *completed).collect();
+        assert_eq!(counts, (1..=200).collect::<Vec<_>>());
+        let mut indices: Vec<usize> = seen.iter().map(|(_, index)| *index).collect();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cancel_stops_taking_items() {
+        let items = vec![(); 1000];
+        let cancel = AtomicBool::new(false);
+        let done = AtomicUsize::new(0);
+        let results = map_parallel(
+            &items,
+            Some(&cancel),
+            |_, _| {
+                if done.fetch_add(1, Ordering::Relaxed) == 10 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            },
+            |_, _, _| {},
+        );
+        assert!(results.is_none());
+        assert!(done.load(Ordering::Relaxed) < items.len());
+    }
+}
@@ -0,0 +1,410 @@
+//! A template prepared once per run and rendered for many contexts.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use handlebars::{Context, Handlebars, RenderContext, RenderError, Renderable, StringOutput};
+use serde_json::{Map, Value};
+
+use super::css::{insert_style, split_stylesheet};
+use super::document::{is_document, wrap_document, DocumentParts};
+use super::images::embed_local_images;
+use super::{
+    effective_body_format, entry_locale, env_values, html_to_text, make_handlebars,
+    markdown_to_html, render_error, resolve_css, split_attachments, MarkdownValues, RenderedEmail,
+};
+use crate::env::Environment;
+use crate::i18n::{Catalogs, Translator};
+use crate::template::{BodyFormat, CssMode, Localized, Template, ENV_NAMESPACE};
+use crate::MailnirError;
+
+/// A [`Template`] with every field parsed, its CSS resolved and an inliner
+/// configured, so rendering an entry only does per-entry work.
+///
+/// Problems found while compiling — a Handlebars syntax error, a missing
+/// stylesheet or `env` variable, an unreadable catalog — are reported by
+/// [`render`](Self::render) for every entry, exactly as [`super::render_context`]
+/// would report them.
+pub struct CompiledTemplate<'t> {
+    template: &'t Template,
+    template_dir: PathBuf,
+    /// Field name (`subject`, or `subject.de` for a variant) → parsed source,
+    /// or the source itself if it does not parse.
+    fields: HashMap<String, Result<handlebars::Template, &'t str>>,
+    /// The `env` context object, or the names the environment lacks.
+    env: Result<Option<Map<String, Value>>, Vec<String>>,
+    css: CompiledCss,
+    catalogs: Catalogs,
+}
+
+enum CompiledCss {
+    None,
+    Ready {
+        /// Configured with the inlineable rules; `None` when there are none.
+        inliner: Option<css_inline::CSSInliner<'static>>,
+        /// Rules kept in a `<style>` element.
+        style: Option<String>,
+    },
+    /// Resolving the stylesheet failed; it is resolved again per entry so
+    /// each one reports the error.
+    Failed,
+}
+
+impl<'t> CompiledTemplate<'t> {
+    /// Prepare `template`, whose relative paths resolve against `template_dir`.
+    pub fn new(template: &'t Template, template_dir: &Path, env: &Environment) -> Self {
+        let mut fields = HashMap::new();
+        for (name, source) in [
+            ("to", Some(template.to.as_str())),
+            ("cc", template.cc.as_deref()),
+            ("bcc", template.bcc.as_deref()),
+        ] {
+            if let Some(source) = source {
+                fields.insert(name.to_string(), compile(source));
+            }
+        }
+        for (name, field) in template.localized_fields() {
+            for (variant, source) in field.variants() {
+                let key = match variant {
+                    Some(variant) => format!("{name}.{variant}"),
+                    None => name.to_string(),
+                };
+                fields.insert(key, compile(source));
+            }
+        }
+
+        let css = match resolve_css(template, template_dir) {
+            Ok(None) => CompiledCss::None,
+            Ok(Some(css)) => compile_css(&css, template.css_mode.unwrap_or_default()),
+            Err(_) => CompiledCss::Failed,
+        };
+
+        CompiledTemplate {
+            template,
+            template_dir: template_dir.to_path_buf(),
+            fields,
+            env: env_values(template, env),
+            css,
+            catalogs: Catalogs::new(template_dir),
+        }
+    }
+
+    pub fn template(&self) -> &'t Template {
+        self.template
+    }
+
+    /// Render one merged context, an entry of `build_contexts()` output.
+    pub fn render(&self, context: &Map<String, Value>) -> crate::Result<RenderedEmail> {
+        let template = self.template;
+        let mut context = context.clone();
+        match &self.env {
+            Ok(None) => {}
+            Ok(Some(values)) => {
+                context.insert(ENV_NAMESPACE.to_string(), Value::Object(values.clone()));
+            }
+            Err(missing) => {
+                return Err(MailnirError::UndefinedEnvVar {
+                    names: missing.clone(),
+                    location: "template".into(),
+                })
+            }
+        }
+        let mut hbs = make_handlebars();
+
+        let locale = entry_locale(template, &context);
+        let locale = locale.as_deref();
+        let translator = Translator::for_entry(template, &self.catalogs, locale)?;
+        if let Some(translator) = &translator {
+            translator.register(&mut hbs);
+        }
+        let data = Context::from(Value::Object(context));
+
+        let to = self.render_field(&hbs, &data, "to")?;
+        let subject = self.render_localized(&hbs, &data, "subject", &template.subject, locale)?;
+        let cc = template
+            .cc
+            .as_ref()
+            .map(|_| self.render_field(&hbs, &data, "cc"))
+            .transpose()?;
+        let bcc = template
+            .bcc
+            .as_ref()
+            .map(|_| self.render_field(&hbs, &data, "bcc"))
+            .transpose()?;
+
+        // Data in HTML and Markdown bodies is escaped; every other field is
+        // plain text.
+        let markdown_values = MarkdownValues::default();
+        if template.escape_html.unwrap_or(true) {
+            match effective_body_format(template) {
+                BodyFormat::Html => hbs.register_escape_fn(handlebars::html_escape),
+                BodyFormat::Markdown => hbs.register_escape_fn(markdown_values.escape_fn()),
+                BodyFormat::Text => {}
+            }
+        }
+        let rendered_body = self.render_localized(&hbs, &data, "body", &template.body, locale);
+        // The preheader is HTML whatever the body's format, so its data is
+        // HTML-escaped and its own text (`&zwnj;` padding) is kept.
+        if template.escape_html.unwrap_or(true) {
+            hbs.register_escape_fn(handlebars::html_escape);
+        }
+        let preheader = template
+            .preheader
+            .as_ref()
+            .map(|field| self.render_localized(&hbs, &data, "preheader", field, locale));
+        hbs.register_escape_fn(handlebars::no_escape);
+        let rendered_body = rendered_body?;
+        let preheader = preheader.transpose()?;
+        if let CompiledCss::Failed = self.css {
+            resolve_css(template, &self.template_dir)?;
+        }
+
+        let text_options = template.plain_text.clone().unwrap_or_default();
+        let mut inline_images = Vec::new();
+        let (html_body, mut text_body) = match effective_body_format(template) {
+            BodyFormat::Text => (None, rendered_body),
+            format => {
+                let fragment = match format {
+                    BodyFormat::Markdown => markdown_to_html(
+                        &rendered_body,
+                        &template.markdown.clone().unwrap_or_default(),
+                        &markdown_values.take(),
+                    ),
+                    _ => rendered_body,
+                };
+                // Derived from the fragment so the hidden preheader stays out of it.
+                let text = html_to_text(&fragment, &text_options);
+                let document = template.document.clone().unwrap_or_default();
+                let html = if document.enabled.unwrap_or(true) {
+                    wrap_document(
+                        &fragment,
+                        &DocumentParts {
+                            title: &subject,
+                            lang: document.lang.as_deref().or(locale),
+                            preheader: preheader.as_deref(),
+                            container: document.container.as_ref(),
+                        },
+                    )
+                } else {
+                    fragment
+                };
+                let html = self.apply_css(&html)?;
+                let (html, images) = embed_local_images(&html, &self.template_dir);
+                inline_images = images;
+                (Some(html), text)
+            }
+        };
+        if let Some(field) = &template.text_body {
+            text_body = self.render_localized(&hbs, &data, "text_body", field, locale)?;
+        }
+
+        let attachments = template
+            .attachments
+            .as_ref()
+            .map(|field| {
+                self.render_localized(&hbs, &data, "attachments", field, locale)
+                    .map(|s| split_attachments(&s, &self.template_dir))
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        if let Some(translator) = &translator {
+            let ids = translator.missing();
+            if !ids.is_empty() {
+                return Err(MailnirError::MissingTranslation {
+                    ids,
+                    locale: translator.locale.clone(),
+                });
+            }
+        }
+
+        Ok(RenderedEmail {
+            to,
+            cc,
+            bcc,
+            subject,
+            html_body,
+            text_body,
+            attachments,
+            inline_images,
+        })
+    }
+
+    /// Render the variant of a localized field chosen for `locale`.
+    ///
+    /// Errors in a variant name it as `field.locale`.
+    fn render_localized(
+        &self,
+        hbs: &Handlebars<'_>,
+        data: &Context,
+        field_name: &str,
+        field: &Localized,
+        locale: Option<&str>,
+    ) -> crate::Result<String> {
+        match field.select(locale, self.template.fallback_locale.as_deref()) {
+            Some((None, _)) => self.render_field(hbs, data, field_name),
+            Some((Some(variant), _)) => {
+                self.render_field(hbs, data, &format!("{field_name}.{variant}"))
+            }
+            None => Err(MailnirError::MissingLocaleVariant {
+                field: field_name.to_string(),
+                locale: locale.map(str::to_string),
+            }),
+        }
+    }
+
+    fn render_field(
+        &self,
+        hbs: &Handlebars<'_>,
+        data: &Context,
+        field_name: &str,
+    ) -> crate::Result<String> {
+        let compiled = self
+            .fields
+            .get(field_name)
+            .expect("every template field is compiled");
+        let result = match compiled {
+            Ok(tpl) => {
+                let mut rc = RenderContext::new(None);
+                let mut out = StringOutput::new();
+                tpl.render(hbs, data, &mut rc, &mut out)
+                    .and_then(|()| out.into_string().map_err(RenderError::from))
+            }
+            // Parse again for an error owned by this entry.
+            Err(source) => handlebars::Template::compile(source)
+                .map_err(RenderError::from)
+                .map(|_| String::new()),
+        };
+        result.map_err(|e| render_error(self.template, field_name, &e))
+    }
+
+    fn apply_css(&self, html: &str) -> crate::Result<String> {
+        let CompiledCss::Ready { inliner, style } = &self.css else {
+            return Ok(html.to_string());
+        };
+        let inlined = match inliner {
+            Some(inliner) => inline_css(inliner, html)?,
+            None => html.to_string(),
+        };
+        Ok(match style {
+            Some(style) => insert_style(&inlined, style),
+            None => inlined,
+        })
+    }
+}
+
+fn compile(source: &str) -> Result<handlebars::Template, &str> {
+    handlebars::Template::compile(source).map_err(|_| source)
+}
+
+fn compile_css(css: &str, mode: CssMode) -> CompiledCss {
+    let (inline, style) = match mode {
+        CssMode::Inline => (Some(css.to_string()), None),
+        CssMode::KeepLeftovers => {
+            let split = split_stylesheet(css);
+            (Some(split.inline), Some(split.leftover))
+        }
+        CssMode::None => (None, Some(css.to_string())),
+    };
+    let inliner = inline.filter(|css| !css.trim().is_empty()).map(|css| {
+        css_inline::CSSInliner::options()
+            .load_remote_stylesheets(false)
+            .extra_css(Some(css.into()))
+            .build()
+    });
+    CompiledCss::Ready {
+        inliner,
+        style: style.filter(|css| !css.trim().is_empty()),
+    }
+}
+
+fn inline_css(inliner: &css_inline::CSSInliner<'_>, html: &str) -> crate::Result<String> {
+    let failed = |e: css_inline::InlineError| MailnirError::CssInline {
+        reason: e.to_string(),
+    };
+    if is_document(html) {
+        return inliner.inline(html).map_err(failed);
+    }
+    // Wrap in a <div> because inline_fragment only processes the first
+    // top-level element when there are multiple siblings. The CSS itself is
+    // the inliner's `extra_css`.
+    let wrapped = format!("<div>{html}</div>");
+    let inlined = inliner.inline_fragment(&wrapped, "").map_err(failed)?;
+    // Strip the wrapper <div>…</div> (the opening tag may have gained
+    // inline styles if a CSS rule targets `div`).
+    let inner = if inlined.starts_with("<div") {
+        let start = inlined.find('>').map(|i| i + 1).unwrap_or(0);
+        let end = inlined.rfind("</div>").unwrap_or(inlined.len());
+        &inlined[start..end]
+    } else {
+        &inlined
+    };
+    Ok(inner.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::template::parse_template_str;
+
+    fn context(value: Value) -> Map<String, Value> {
+        [("p".to_string(), value)].into_iter().collect()
+    }
+
+    #[test]
+    fn test_files_read_once_per_run() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("style.css"), "h1 { color: red; }").unwrap();
+        std::fs::write(dir.path().join("en.ftl"), "hi = Hello { $name }\n").unwrap();
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\ntranslations: {en: en.ftl}\nstylesheet: style.css\nsubject: '{{t \"hi\" name=p.name}}'\nbody: '<h1>{{p.name}}</h1>'\nbody_format: html\ndocument: {enabled: false}",
+        )
+        .unwrap();
+        let compiled = CompiledTemplate::new(&t, dir.path(), &Environment::default());
+        let first = compiled
+            .render(&context(json!({"lang": "en", "name": "Ann"})))
+            .unwrap();
+        assert_eq!(first.subject, "Hello Ann");
+
+        // Later entries use what was loaded, not the files as they are now.
+        std::fs::remove_file(dir.path().join("style.css")).unwrap();
+        std::fs::write(dir.path().join("en.ftl"), "hi = Bye\n").unwrap();
+        let second = compiled
+            .render(&context(json!({"lang": "en", "name": "Bo"})))
+            .unwrap();
+        assert_eq!(second.subject, "Hello Bo");
+        assert_eq!(
+            second.html_body.as_deref(),
+            Some("<h1 style=\"color: red;\">Bo</h1>")
+        );
+    }
+
+    #[test]
+    fn test_compile_errors_reported_per_entry() {
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nsubject: \"ok {{#if x}}\"\nbody: b\nstylesheet: missing.css",
+        )
+        .unwrap();
+        let compiled = CompiledTemplate::new(&t, Path::new("."), &Environment::default());
+        for _ in 0..2 {
+            let err = compiled.render(&context(json!({}))).unwrap_err();
+            assert!(matches!(
+                err,
+                MailnirError::HandlebarsRender { ref field, .. } if field == "subject"
+            ));
+            assert_eq!(err.file_position().map(|p| p.line), Some(4));
+        }
+
+        let t = Template {
+            subject: "s".into(),
+            ..t
+        };
+        let compiled = CompiledTemplate::new(&t, Path::new("."), &Environment::default());
+        for _ in 0..2 {
+            let err = compiled.render(&context(json!({}))).unwrap_err();
+            assert!(matches!(err, MailnirError::StylesheetNotFound { .. }));
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Splitting a stylesheet into rules `css_inline` can apply as `style`
+//! attributes and rules that only work from a `<style>` element.
+
+/// Pseudo-classes `css_inline` matches against the static document.
+const STRUCTURAL_PSEUDO_CLASSES: &[&str] = &[
+    "empty",
+    "first-child",
+    "first-of-type",
+    "is",
+    "last-child",
+    "last-of-type",
+    "not",
+    "nth-child",
+    "nth-last-child",
+    "nth-last-of-type",
+    "nth-of-type",
+    "only-child",
+    "only-of-type",
+    "root",
+    "where",
+];
+
+/// A stylesheet divided by whether its rules can be inlined.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SplitCss {
+    pub inline: String,
+    /// At-rules (`@media`, `@font-face`, …) and rules for pseudo-elements or
+    /// dynamic states such as `:hover`.
+    pub leftover: String,
+}
+
+/// Split `css` rule by rule; a rule whose selector list mixes both kinds is
+/// divided between the two halves.
+pub(crate) fn split_stylesheet(css: &str) -> SplitCss {
+    let mut split = SplitCss::default();
+    let mut pos = 0;
+    loop {
+        pos = skip_trivia(css, pos);
+        if pos >= css.len() {
+            break;
+        }
+        let Some(open) = find_top_level(css, pos, &['{', ';']) else {
+            // Unterminated trailing text; nothing to inline.
+            push_rule(&mut split.leftover, css[pos..].trim());
+            break;
+        };
+        if css.as_bytes()[open] == b';' {
+            // Statement at-rule (`@import`, `@charset`) or a stray declaration.
+            push_rule(&mut split.leftover, &css[pos..=open]);
+            pos = open + 1;
+            continue;
+        }
+        let close = matching_brace(css, open);
+        let prelude = css[pos..open].trim();
+        let block = &css[open..close];
+        pos = close;
+
+        if prelude.starts_with('@') {
+            push_rule(&mut split.leftover, &format!("{prelude} {block}"));
+            continue;
+        }
+        let (inline, leftover): (Vec<&str>, Vec<&str>) = split_top_level(prelude, ',')
+            .into_iter()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .partition(|selector| is_inlineable(selector));
+        if !inline.is_empty() {
+            push_rule(&mut split.inline, &format!("{} {block}", inline.join(", ")));
+        }
+        if !leftover.is_empty() {
+            push_rule(
+                &mut split.leftover,
+                &format!("{} {block}", leftover.join(", ")),
+            );
+        }
+    }
+    split
+}
+
+/// Whether `css_inline` can match `selector` against the document: no
+/// pseudo-elements and only structural pseudo-classes.
+fn is_inlineable(selector: &str) -> bool {
+    let bytes = selector.as_bytes();
+    let mut i = 0;
+    let mut in_brackets = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'[' => in_brackets = true,
+            b']' => in_brackets = false,
+            b'"' | b'\'' => i = skip_string(selector, i) - 1,
+            b':' if !in_brackets => {
+                if bytes.get(i + 1) == Some(&b':') {
+                    return false;
+                }
+                let name: String = selector[i + 1..]
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                if !STRUCTURAL_PSEUDO_CLASSES.contains(&name.as_str()) {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    true
+}
+
+fn push_rule(out: &mut String, rule: &str) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(rule);
+}
+
+/// Skip whitespace and comments from `pos`.
+fn skip_trivia(css: &str, mut pos: usize) -> usize {
+    loop {
+        let rest = &css[pos..];
+        let trimmed = rest.trim_start();
+        pos += rest.len() - trimmed.len();
+        if trimmed.starts_with("/*") {
+            pos = trimmed.find("*/").map_or(css.len(), |end| pos + end + 2);
+        } else {
+            return pos;
+        }
+    }
+}
+
+/// End of the string literal starting at `start`, past its closing quote.
+fn skip_string(css: &str, start: usize) -> usize {
+    let quote = css.as_bytes()[start];
+    let bytes = css.as_bytes();
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b if b == quote => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    css.len()
+}
+
+/// Byte offset of the first of `targets` at nesting depth zero, outside
+/// strings and comments.
+fn find_top_level(css: &str, from: usize, targets: &[char]) -> Option<usize> {
+    let bytes = css.as_bytes();
+    let mut depth = 0usize;
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                i = skip_string(css, i);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_trivia(css, i);
+                continue;
+            }
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b if depth == 0 && targets.contains(&(b as char)) => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Offset just past the `}` closing the block opened at `open`.
+fn matching_brace(css: &str, open: usize) -> usize {
+    let bytes = css.as_bytes();
+    let mut depth = 0usize;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                i = skip_string(css, i);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_trivia(css, i);
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    css.len()
+}
+
+/// Split at `sep` outside parentheses, brackets and strings.
+fn split_top_level(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while let Some(at) = find_top_level(text, start, &[sep]) {
+        parts.push(&text[start..at]);
+        start = at + 1;
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Add a `<style>` element holding `css`: before `</head>` in a document,
+/// or ahead of a fragment.
+pub(crate) fn insert_style(html: &str, css: &str) -> String {
+    if css.trim().is_empty() {
+        return html.to_string();
+    }
+    let style = format!("<style>\n{}\n</style>\n", css.trim());
+    match html.to_ascii_lowercase().find("</head>") {
+        Some(at) => format!("{}{style}{}", &html[..at], &html[at..]),
+        None => format!("{style}{html}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_keeps_non_inlineable_rules() {
+        let css = "/* brand */ h1 { color: red; }\n\
+                   @import url(\"fonts.css\");\n\
+                   @media (max-width: 600px) { h1 { font-size: 18px; } p { margin: 0; } }\n\
+                   a, a:hover, p::first-line { color: blue; }\n\
+                   li:first-child, td:not(.x) { padding: 0; }\n\
+                   @font-face { font-family: \"A}b\"; src: url(a.woff); }\n\
+                   input[placeholder=\"a:b\"] { border: 0; }";
+        let split = split_stylesheet(css);
+        assert_eq!(
+            split.inline,
+            "h1 { color: red; }\n\
+             a { color: blue; }\n\
+             li:first-child, td:not(.x) { padding: 0; }\n\
+             input[placeholder=\"a:b\"] { border: 0; }"
+        );
+        assert_eq!(
+            split.leftover,
+            "@import url(\"fonts.css\");\n\
+             @media (max-width: 600px) { h1 { font-size: 18px; } p { margin: 0; } }\n\
+             a:hover, p::first-line { color: blue; }\n\
+             @font-face { font-family: \"A}b\"; src: url(a.woff); }"
+        );
+    }
+
+    #[test]
+    fn test_insert_style_into_head_or_fragment() {
+        assert_eq!(
+            insert_style(
+                "<html><HEAD><title>t</title></HEAD><body></body></html>",
+                "a:hover{}"
+            ),
+            "<html><HEAD><title>t</title><style>\na:hover{}\n</style>\n</HEAD><body></body></html>"
+        );
+        assert_eq!(
+            insert_style("<p>x</p>", " @media print { p {} } "),
+            "<style>\n@media print { p {} }\n</style>\n<p>x</p>"
+        );
+        assert_eq!(insert_style("<p>x</p>", "  "), "<p>x</p>");
+    }
+}
@@ -0,0 +1,184 @@
+//! The HTML document wrapped around rendered bodies: doctype, head, hidden
+//! preheader and an optional centered container.
+
+use std::fmt::Write as _;
+
+use crate::template::ContainerOptions;
+
+/// Container width when `container.width` is not set.
+const DEFAULT_CONTAINER_WIDTH: u32 = 600;
+
+/// Hides the preheader in the body while clients still use it as preview text.
+const PREHEADER_STYLE: &str = "display:none;font-size:1px;line-height:1px;max-height:0;max-width:0;opacity:0;overflow:hidden;mso-hide:all";
+
+/// Per-entry values of the wrapper.
+pub(crate) struct DocumentParts<'a> {
+    /// `<title>`; the rendered subject.
+    pub title: &'a str,
+    pub lang: Option<&'a str>,
+    /// HTML, with its data already escaped; inserted as it is.
+    pub preheader: Option<&'a str>,
+    pub container: Option<&'a ContainerOptions>,
+}
+
+/// Wrap `body` in a complete HTML document.
+///
+/// A body that is already a document is kept as it is, apart from the
+/// preheader, which is inserted after its `<body>` tag.
+pub(crate) fn wrap_document(body: &str, parts: &DocumentParts<'_>) -> String {
+    let preheader = parts
+        .preheader
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| format!("<div style=\"{PREHEADER_STYLE}\">{}</div>\n", p.trim()))
+        .unwrap_or_default();
+
+    if is_document(body) {
+        return match body_content_start(body) {
+            Some(at) if !preheader.is_empty() => {
+                format!("{}\n{preheader}{}", &body[..at], &body[at..])
+            }
+            _ => body.to_string(),
+        };
+    }
+
+    let mut out = String::from("<!DOCTYPE html>\n");
+    match parts.lang {
+        Some(lang) => {
+            let _ = writeln!(out, "<html lang=\"{}\">", escape_html(lang));
+        }
+        None => out.push_str("<html>\n"),
+    }
+    out.push_str("<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    let _ = writeln!(out, "<title>{}</title>", escape_html(parts.title));
+    out.push_str("</head>\n<body>\n");
+    out.push_str(&preheader);
+    match parts.container {
+        Some(container) => {
+            let width = container.width.unwrap_or(DEFAULT_CONTAINER_WIDTH);
+            let _ = write!(
+                out,
+                "<table role=\"presentation\" width=\"{width}\" align=\"center\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\" style=\"width:{width}px;max-width:100%;margin:0 auto\""
+            );
+            if let Some(class) = &container.class {
+                let _ = write!(out, " class=\"{}\"", escape_html(class));
+            }
+            out.push_str(">\n<tr>\n<td");
+            if let Some(style) = &container.style {
+                let _ = write!(out, " style=\"{}\"", escape_html(style));
+            }
+            let _ = write!(out, ">\n{}\n</td>\n</tr>\n</table>\n", body.trim_end());
+        }
+        None => {
+            out.push_str(body.trim_end());
+            out.push('\n');
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Whether `html` starts with a doctype or `<html>` rather than being a fragment.
+pub(crate) fn is_document(html: &str) -> bool {
+    let start: String = html
+        .trim_start()
+        .chars()
+        .take(9)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    start.starts_with("<!doctype") || start.starts_with("<html")
+}
+
+/// Byte offset just past the `<body …>` start tag.
+fn body_content_start(html: &str) -> Option<usize> {
+    let lower = html.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find("<body") {
+        let at = from + found;
+        let next = lower[at + 5..].chars().next();
+        if next.is_some_and(|c| c == '>' || c == '/' || c.is_ascii_whitespace()) {
+            return lower[at..].find('>').map(|end| at + end + 1);
+        }
+        from = at + 5;
+    }
+    None
+}
+
+/// Escape text for use in element content and quoted attribute values.
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts<'a>(title: &'a str, preheader: Option<&'a str>) -> DocumentParts<'a> {
+        DocumentParts {
+            title,
+            lang: Some("de"),
+            preheader,
+            container: None,
+        }
+    }
+
+    #[test]
+    fn test_fragment_gets_head_and_preheader() {
+        let html = wrap_document(
+            "<p>Hi</p>\n",
+            &parts("Tom & Jerry", Some("Read &lt;this&gt;&zwnj;&nbsp;")),
+        );
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n"));
+        assert!(html.contains("<meta charset=\"utf-8\">"));
+        assert!(html.contains("<meta name=\"viewport\""));
+        assert!(html.contains("<title>Tom &amp; Jerry</title>"));
+        let preheader = html.find("Read &lt;this&gt;&zwnj;&nbsp;</div>").unwrap();
+        assert!(preheader > html.find("<body>").unwrap());
+        assert!(preheader < html.find("<p>Hi</p>").unwrap());
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn test_container_wraps_body() {
+        let container = ContainerOptions {
+            width: Some(480),
+            class: Some("wrap".into()),
+            style: Some("padding:16px".into()),
+        };
+        let html = wrap_document(
+            "<p>Hi</p>",
+            &DocumentParts {
+                container: Some(&container),
+                ..parts("s", None)
+            },
+        );
+        assert!(html.contains(
+            "<table role=\"presentation\" width=\"480\" align=\"center\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\" style=\"width:480px;max-width:100%;margin:0 auto\" class=\"wrap\">"
+        ));
+        assert!(html.contains("<td style=\"padding:16px\">\n<p>Hi</p>\n</td>"));
+        assert!(!html.contains("display:none"));
+    }
+
+    #[test]
+    fn test_document_body_kept() {
+        let doc = "<!doctype html><html><head><title>Mine</title></head><body class=\"x\"><p>Hi</p></body></html>";
+        assert!(is_document(doc));
+        assert_eq!(wrap_document(doc, &parts("s", None)), doc);
+        let html = wrap_document(doc, &parts("s", Some("Soon")));
+        assert!(html.contains("<body class=\"x\">\n<div style=\""));
+        assert!(html.contains("Soon</div>\n<p>Hi</p>"));
+        assert!(!html.contains("<title>s</title>"));
+        assert!(!is_document("  <p>x</p>"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Local `<img>` references, sent as inline parts and referenced by `cid:` URL.
+
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::bundle::relative_inside;
+
+/// A local image embedded in the HTML part as `cid:<cid>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineImage {
+    /// Content-ID, without angle brackets.
+    pub cid: String,
+    /// Resolved file path; may not exist (see `ValidationIssue::InlineImageNotFound`).
+    pub path: PathBuf,
+}
+
+/// Rewrite the `src` of every `<img>` that names a local file to a `cid:`
+/// URL, and list the images to attach.
+///
+/// Only relative paths that stay inside `template_dir` are local; since the
+/// HTML may carry data values, absolute paths and `..` are never read. Every
+/// other `src` — URLs with a scheme (`https:`, `data:`, `cid:`, …),
+/// protocol-relative `//host` URLs, absolute and escaping paths — is left
+/// alone. The Content-ID is derived from the `src` as written, so it is the
+/// same on every machine and for every entry.
+pub(crate) fn embed_local_images(html: &str, template_dir: &Path) -> (String, Vec<InlineImage>) {
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut images: Vec<InlineImage> = Vec::new();
+    let mut copied = 0;
+    let mut from = 0;
+
+    while let Some(found) = lower[from..].find("<img") {
+        let tag_start = from + found;
+        let Some(tag_len) = lower[tag_start..].find('>') else {
+            break;
+        };
+        let tag_end = tag_start + tag_len;
+        from = tag_end;
+        let is_img = lower[tag_start + 4..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_whitespace() || c == '/' || c == '>');
+        if !is_img {
+            continue;
+        }
+        let Some((start, end)) = src_value(&lower[tag_start..tag_end]) else {
+            continue;
+        };
+        let (start, end) = (tag_start + start, tag_start + end);
+        let src = html[start..end].trim().replace("&amp;", "&");
+        if !is_local(&src) {
+            continue;
+        }
+        let Some(relative) = relative_inside(&src) else {
+            continue;
+        };
+
+        let cid = content_id(&src);
+        if !images.iter().any(|image| image.cid == cid) {
+            images.push(InlineImage {
+                cid: cid.clone(),
+                path: template_dir.join(relative),
+            });
+        }
+        out.push_str(&html[copied..start]);
+        out.push_str("cid:");
+        out.push_str(&cid);
+        copied = end;
+    }
+    out.push_str(&html[copied..]);
+    (out, images)
+}
+
+/// Byte range of the `src` attribute value within one `<img …` tag (without
+/// the closing `>`), excluding quotes.
+fn src_value(tag: &str) -> Option<(usize, usize)> {
+    let bytes = tag.as_bytes();
+    let mut i = 4;
+    while i < bytes.len() {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        let name_start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'/')
+        {
+            i += 1;
+        }
+        let name = &tag[name_start..i];
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if bytes.get(i) != Some(&b'=') {
+            if name.is_empty() {
+                i += 1;
+            }
+            continue;
+        }
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let (start, end) = match bytes.get(i) {
+            Some(&quote @ (b'"' | b'\'')) => {
+                let start = i + 1;
+                let end = tag[start..]
+                    .find(quote as char)
+                    .map_or(tag.len(), |n| start + n);
+                (start, end)
+            }
+            _ => {
+                let start = i;
+                let end = tag[start..]
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .map_or(tag.len(), |n| start + n);
+                (start, end)
+            }
+        };
+        if name == "src" {
+            return Some((start, end));
+        }
+        i = end + 1;
+    }
+    None
+}
+
+/// Whether `src` names a file rather than a URL.
+fn is_local(src: &str) -> bool {
+    if src.is_empty() || src.starts_with("//") || src.starts_with('#') {
+        return false;
+    }
+    let scheme_len = src
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+        .unwrap_or(src.len());
+    let has_scheme = scheme_len > 0
+        && src[..scheme_len].starts_with(|c: char| c.is_ascii_alphabetic())
+        && src[scheme_len..].starts_with(':');
+    !has_scheme
+}
+
+fn content_id(src: &str) -> String {
+    let digest = Sha256::digest(src.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    format!("{hex}@mailnir")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_images_rewritten_once() {
+        let html = "<p><img src=\"logo.png\" alt=\"Logo\"><IMG alt='x' SRC='img/a&amp;b.png'/>\
+                    <img src=\"logo.png\"></p>";
+        let (out, images) = embed_local_images(html, Path::new("/t"));
+        let logo = content_id("logo.png");
+        let ab = content_id("img/a&b.png");
+        assert_eq!(
+            out,
+            format!(
+                "<p><img src=\"cid:{logo}\" alt=\"Logo\"><IMG alt='x' SRC='cid:{ab}'/>\
+                 <img src=\"cid:{logo}\"></p>"
+            )
+        );
+        assert_eq!(
+            images,
+            vec![
+                InlineImage {
+                    cid: logo,
+                    path: PathBuf::from("/t/logo.png"),
+                },
+                InlineImage {
+                    cid: ab,
+                    path: PathBuf::from("/t/img/a&b.png"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_urls_left_alone() {
+        let html = "<img src=\"https://x.org/a.png\"><img src=\"data:image/png;base64,AA\">\
+                    <img src=\"cid:abc\"><img src=\"//cdn.x.org/a.png\"><img alt=\"no src\">\
+                    <imgx src=\"a.png\"><img data-src=\"a.png\" src=x.png>";
+        let (out, images) = embed_local_images(html, Path::new("/t"));
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].path, PathBuf::from("/t/x.png"));
+        assert!(out.ends_with(&format!(
+            "<img data-src=\"a.png\" src=cid:{}>",
+            images[0].cid
+        )));
+        assert!(out.starts_with("<img src=\"https://x.org/a.png\"><img src=\"data:"));
+        assert!(out.contains("<imgx src=\"a.png\">"));
+    }
+
+    #[test]
+    fn test_paths_outside_template_dir_left_alone() {
+        let html = "<img src=\"/etc/passwd\"><img src=\"../../../etc/hostname\">\
+                    <img src=\"img/../../x.png\"><img src=\"./img/ok.png\">";
+        let (out, images) = embed_local_images(html, Path::new("/t"));
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].path, PathBuf::from("/t/img/ok.png"));
+        assert!(out.starts_with(
+            "<img src=\"/etc/passwd\"><img src=\"../../../etc/hostname\"><img src=\"img/../../x.png\">"
+        ));
+    }
+}
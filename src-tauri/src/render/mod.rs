@@ -1,10 +1,26 @@
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use serde_json::{Map, Value};
 
-use crate::template::{BodyFormat, Template};
+use crate::env::Environment;
+use crate::template::{
+    infer_form_fields, BodyFormat, MarkdownOptions, Template, TextPosition, ENV_NAMESPACE,
+};
 use crate::MailnirError;
 
+mod batch;
+mod compiled;
+mod css;
+mod document;
+mod images;
+mod text;
+
+pub(crate) use batch::map_parallel;
+pub use compiled::CompiledTemplate;
+pub use images::InlineImage;
+pub use text::{html_to_text, DEFAULT_WRAP};
+
 /// The fully rendered output for one primary source row.
 #[derive(Debug, Clone)]
 pub struct RenderedEmail {
@@ -18,89 +34,118 @@ pub struct RenderedEmail {
     pub text_body: String,
     /// Resolved attachment file paths.
     pub attachments: Vec<PathBuf>,
+    /// Local images referenced by `cid:` URLs in `html_body`.
+    pub inline_images: Vec<InlineImage>,
 }
 
 /// Render one merged context against the template, producing a [`RenderedEmail`].
 ///
 /// `context` is one entry from `build_contexts()` output.
-/// `template_dir` is used to resolve relative `stylesheet` paths.
+/// `template_dir` is used to resolve relative `stylesheet` and `translations`
+/// paths and to find the `.env` file that supplements the process environment for `{{env.NAME}}`.
 pub fn render_context(
     template: &Template,
     context: &Map<String, Value>,
     template_dir: &Path,
 ) -> crate::Result<RenderedEmail> {
-    let hbs = make_handlebars();
-
-    let to = render_field(&hbs, "to", &template.to, context)?;
-    let subject = render_field(&hbs, "subject", &template.subject, context)?;
-    let cc = template
-        .cc
-        .as_deref()
-        .map(|s| render_field(&hbs, "cc", s, context))
-        .transpose()?;
-    let bcc = template
-        .bcc
-        .as_deref()
-        .map(|s| render_field(&hbs, "bcc", s, context))
-        .transpose()?;
-
-    let rendered_body = render_field(&hbs, "body", &template.body, context)?;
-    let css = resolve_css(template, template_dir)?;
-
-    let (html_body, text_body) = match effective_body_format(template) {
-        BodyFormat::Markdown => {
-            let html = markdown_to_html(&rendered_body);
-            let html = apply_css(&html, css.as_deref())?;
-            let text = strip_html(&html);
-            (Some(html), text)
-        }
-        BodyFormat::Html => {
-            let html = apply_css(&rendered_body, css.as_deref())?;
-            let text = strip_html(&html);
-            (Some(html), text)
-        }
-        BodyFormat::Text => (None, rendered_body),
-    };
+    let env = Environment::load(template_dir)?;
+    render_context_with_env(template, context, template_dir, &env)
+}
+
+/// Like [`render_context`], with an already loaded environment.
+///
+/// To render many contexts against one template, build a
+/// [`CompiledTemplate`] once instead.
+///
+/// Fails with `UndefinedEnvVar` before rendering if the template references
+/// an `env.NAME` that `env` does not define.
+pub fn render_context_with_env(
+    template: &Template,
+    context: &Map<String, Value>,
+    template_dir: &Path,
+    env: &Environment,
+) -> crate::Result<RenderedEmail> {
+    CompiledTemplate::new(template, template_dir, env).render(context)
+}
 
-    let attachments = template
-        .attachments
-        .as_deref()
-        .map(|tmpl| {
-            render_field(&hbs, "attachments", tmpl, context)
-                .map(|s| split_attachments(&s, template_dir))
+/// The entry's locale: the string (or number) at `locale_field`, a dotted
+/// `namespace.field` path into the context.
+fn entry_locale(template: &Template, context: &Map<String, Value>) -> Option<String> {
+    let path = template.locale_field.as_deref()?;
+    let mut segments = path.split('.');
+    let mut value = context.get(segments.next()?)?;
+    for segment in segments {
+        value = value.get(segment)?;
+    }
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// The `env` object holding the variables the template references, `None`
+/// if it references none, or the names `env` does not define.
+fn env_values(
+    template: &Template,
+    env: &Environment,
+) -> Result<Option<Map<String, Value>>, Vec<String>> {
+    let names = infer_form_fields(template, ENV_NAMESPACE);
+    if names.is_empty() {
+        return Ok(None);
+    }
+    let missing: Vec<String> = names
+        .iter()
+        .filter(|name| env.get(name).is_none())
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    let values: Map<String, Value> = names
+        .into_iter()
+        .filter_map(|name| {
+            let value = env.get(&name)?.to_string();
+            Some((name, Value::String(value)))
         })
-        .transpose()?
-        .unwrap_or_default();
-
-    Ok(RenderedEmail {
-        to,
-        cc,
-        bcc,
-        subject,
-        html_body,
-        text_body,
-        attachments,
-    })
+        .collect();
+    Ok(Some(values))
 }
 
-fn make_handlebars() -> handlebars::Handlebars<'static> {
+pub(crate) fn make_handlebars() -> handlebars::Handlebars<'static> {
     let mut hbs = handlebars::Handlebars::new();
     hbs.set_strict_mode(true);
     hbs.register_escape_fn(handlebars::no_escape);
     hbs
 }
 
-fn render_field(
-    hbs: &handlebars::Handlebars<'_>,
+/// `HandlebarsRender` for an error in `field_name`, located in the template
+/// file when the field's span is known.
+fn render_error(
+    template: &Template,
     field_name: &str,
-    template_str: &str,
-    context: &Map<String, Value>,
-) -> crate::Result<String> {
-    hbs.render_template(template_str, context)
-        .map_err(|e| MailnirError::HandlebarsRender {
-            field: field_name.to_string(),
-            reason: e.to_string(),
-        })
+    err: &handlebars::RenderError,
+) -> MailnirError {
+    let field_position = render_error_position(err);
+    MailnirError::HandlebarsRender {
+        field: field_name.to_string(),
+        reason: err.to_string(),
+        field_position,
+        file_position: field_position
+            .zip(template.spans.get(field_name))
+            .map(|(pos, span)| span.locate(pos)),
+    }
+}
+
+/// Line/column of a render error within the rendered string, including
+/// syntax errors wrapped from template compilation.
+fn render_error_position(err: &handlebars::RenderError) -> Option<TextPosition> {
+    let (line, column) = match (err.line_no, err.column_no, err.reason()) {
+        (Some(line), Some(column), _) => (line, column),
+        (_, _, handlebars::RenderErrorReason::TemplateError(te)) => te.pos()?,
+        _ => return None,
+    };
+    Some(TextPosition { line, column })
 }
 
 fn effective_body_format(template: &Template) -> &BodyFormat {
@@ -110,13 +155,135 @@ fn effective_body_format(template: &Template) -> &BodyFormat {
         .unwrap_or(&BodyFormat::Markdown)
 }
 
-fn markdown_to_html(markdown: &str) -> String {
+/// Brackets the index of a value held out of a Markdown body.
+const VALUE_OPEN: char = '\u{E000}';
+const VALUE_CLOSE: char = '\u{E001}';
+
+/// Data values interpolated into a Markdown body.
+///
+/// The escape fn swaps each value for a placeholder Markdown cannot
+/// interpret; [`markdown_to_html`] puts the values back as literal text, so
+/// a link, image or emphasis written in data is never rendered as such.
+#[derive(Clone, Default)]
+struct MarkdownValues(Arc<Mutex<Vec<String>>>);
+
+impl MarkdownValues {
+    fn escape_fn(&self) -> impl Fn(&str) -> String + Send + Sync + 'static {
+        let values = Arc::clone(&self.0);
+        move |value| {
+            let mut values = values.lock().unwrap_or_else(PoisonError::into_inner);
+            values.push(value.to_string());
+            format!("{VALUE_OPEN}{}{VALUE_CLOSE}", values.len() - 1)
+        }
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// `text` with each placeholder replaced by `escape` of its value.
+fn restore_values(text: &str, values: &[String], escape: fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(VALUE_OPEN) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + VALUE_OPEN.len_utf8()..];
+        let value = after.find(VALUE_CLOSE).and_then(|end| {
+            let index: usize = after[..end].parse().ok()?;
+            Some((values.get(index)?, end))
+        });
+        match value {
+            Some((value, end)) => {
+                out.push_str(&escape(value));
+                rest = &after[end + VALUE_CLOSE.len_utf8()..];
+            }
+            None => {
+                out.push(VALUE_OPEN);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escape text as comrak does: `&`, `<`, `>` and `"`.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Convert a Markdown body to HTML; `values` are the data values held out
+/// of it by [`MarkdownValues`].
+fn markdown_to_html(
+    markdown: &str,
+    markdown_options: &MarkdownOptions,
+    values: &[String],
+) -> String {
+    let on = |flag: Option<bool>| flag.unwrap_or(false);
     let mut options = comrak::Options::default();
-    options.extension.strikethrough = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.render.r#unsafe = true;
-    comrak::markdown_to_html(markdown, &options)
+    options.extension.strikethrough = markdown_options.strikethrough.unwrap_or(true);
+    options.extension.table = markdown_options.tables.unwrap_or(true);
+    options.extension.autolink = markdown_options.autolink.unwrap_or(true);
+    options.extension.footnotes = on(markdown_options.footnotes);
+    options.extension.tasklist = on(markdown_options.tasklists);
+    options.extension.header_ids = on(markdown_options.header_ids).then(String::new);
+    options.extension.description_lists = on(markdown_options.description_lists);
+    options.extension.superscript = on(markdown_options.superscript);
+    options.parse.smart = on(markdown_options.smart_punctuation);
+    options.render.hardbreaks = on(markdown_options.hard_breaks);
+    options.render.r#unsafe = !on(markdown_options.safe);
+
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &options);
+    // Link targets are percent-encoded when rendered, so restore them first.
+    for node in root.descendants() {
+        if let comrak::nodes::NodeValue::Link(link) | comrak::nodes::NodeValue::Image(link) =
+            &mut node.data_mut().value
+        {
+            link.url = restore_values(&link.url, values, str::to_string);
+            link.title = restore_values(&link.title, values, str::to_string);
+        }
+    }
+    let mut html = String::new();
+    comrak::format_html(root, &options, &mut html).expect("formatting into a String");
+    restore_values(&html, values, escape_text)
+}
+
+/// Local images the body names in its own text, relative to the template
+/// directory. Paths built with Handlebars are left out; only a render knows
+/// them.
+pub(crate) fn literal_inline_images(template: &Template) -> Vec<String> {
+    let format = effective_body_format(template);
+    let mut references: Vec<String> = Vec::new();
+    for (_, text) in template.body.variants() {
+        let html = match format {
+            BodyFormat::Text => continue,
+            BodyFormat::Markdown => {
+                markdown_to_html(text, &template.markdown.clone().unwrap_or_default(), &[])
+            }
+            BodyFormat::Html => text.to_string(),
+        };
+        let (_, images) = images::embed_local_images(&html, Path::new(""));
+        for image in images {
+            let reference = image.path.to_string_lossy().into_owned();
+            let templated = reference.contains("{{") || reference.contains("%7B%7B");
+            if !templated && !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+    }
+    references
 }
 
 fn resolve_css(template: &Template, template_dir: &Path) -> crate::Result<Option<String>> {
@@ -142,54 +309,6 @@ fn resolve_css(template: &Template, template_dir: &Path) -> crate::Result<Option
     Ok(None)
 }
 
-fn apply_css(html: &str, css: Option<&str>) -> crate::Result<String> {
-    let Some(css_str) = css else {
-        return Ok(html.to_string());
-    };
-    let inliner = css_inline::CSSInliner::options()
-        .load_remote_stylesheets(false)
-        .build();
-    // Wrap in a <div> because inline_fragment only processes the first
-    // top-level element when there are multiple siblings.
-    let wrapped = format!("<div>{html}</div>");
-    let inlined =
-        inliner
-            .inline_fragment(&wrapped, css_str)
-            .map_err(|e| MailnirError::CssInline {
-                reason: e.to_string(),
-            })?;
-    // Strip the wrapper <div>…</div> (the opening tag may have gained
-    // inline styles if a CSS rule targets `div`).
-    let inner = if inlined.starts_with("<div") {
-        let start = inlined.find('>').map(|i| i + 1).unwrap_or(0);
-        let end = inlined.rfind("</div>").unwrap_or(inlined.len());
-        &inlined[start..end]
-    } else {
-        &inlined
-    };
-    Ok(inner.to_string())
-}
-
-fn strip_html(html: &str) -> String {
-    let mut result = String::with_capacity(html.len());
-    let mut in_tag = false;
-    for ch in html.chars() {
-        match ch {
-            '<' => in_tag = true,
-            '>' => in_tag = false,
-            _ if !in_tag => result.push(ch),
-            _ => {}
-        }
-    }
-    result
-        .replace("&amp;", "&")
-        .replace("&lt;", "<")
-        .replace("&gt;", ">")
-        .replace("&nbsp;", " ")
-        .replace("&#39;", "'")
-        .replace("&quot;", "\"")
-}
-
 fn split_attachments(rendered: &str, template_dir: &Path) -> Vec<PathBuf> {
     rendered
         .lines()
@@ -207,7 +326,7 @@ mod tests {
     use serde_json::json;
 
     use super::*;
-    use crate::template::{parse_template_str, BodyFormat, SourceConfig};
+    use crate::template::{parse_template_str, BodyFormat, CssMode, SourceConfig};
 
     fn make_template(yaml: &str) -> Template {
         parse_template_str(yaml).expect("fixture must parse")
@@ -229,19 +348,35 @@ mod tests {
                 join: None,
                 many: None,
                 form: None,
+                fields: None,
+                computed: None,
             },
         );
         Template {
             sources,
+            vars: None,
             to: "x@example.com".to_string(),
             cc: None,
             bcc: None,
-            subject: "s".to_string(),
-            body: String::new(),
+            subject: "s".into(),
+            body: "".into(),
+            text_body: None,
+            preheader: None,
             attachments: None,
+            locale_field: None,
+            fallback_locale: None,
+            translations: None,
+            tests: None,
             body_format: None,
+            markdown: None,
+            escape_html: None,
+            plain_text: None,
+            document: None,
             stylesheet: None,
             style: None,
+            css_mode: None,
+            version: None,
+            spans: Default::default(),
         }
     }
 
@@ -275,10 +410,41 @@ mod tests {
         assert!(html.contains("<li>"), "expected <li> in: {html}");
     }
 
+    #[test]
+    fn test_markdown_options() {
+        let body =
+            "# Intro\n\n- [x] done\n\nSee[^1] ~~old~~ \"quoted\" <b>raw</b>\nnext\n\n[^1]: Note.\n";
+        let options = MarkdownOptions::default();
+        let html = markdown_to_html(body, &options, &[]);
+        assert!(html.contains("<del>old</del>"), "{html}");
+        assert!(html.contains("<b>raw</b>"), "{html}");
+        assert!(html.contains("<h1>Intro</h1>"), "{html}");
+        assert!(!html.contains("checkbox"), "{html}");
+
+        let options = MarkdownOptions {
+            strikethrough: Some(false),
+            footnotes: Some(true),
+            tasklists: Some(true),
+            header_ids: Some(true),
+            smart_punctuation: Some(true),
+            hard_breaks: Some(true),
+            safe: Some(true),
+            ..MarkdownOptions::default()
+        };
+        let html = markdown_to_html(body, &options, &[]);
+        assert!(html.contains("~~old~~"), "{html}");
+        assert!(html.contains("id=\"intro\""), "{html}");
+        assert!(html.contains("type=\"checkbox\""), "{html}");
+        assert!(html.contains("href=\"#fn-1\""), "{html}");
+        assert!(html.contains("\u{201c}quoted\u{201d}"), "{html}");
+        assert!(html.contains("<br />"), "{html}");
+        assert!(!html.contains("<b>raw</b>"), "{html}");
+    }
+
     #[test]
     fn test_css_inlining_from_style() {
         let t = Template {
-            body: "<h1>Hi</h1>".to_string(),
+            body: "<h1>Hi</h1>".into(),
             body_format: Some(BodyFormat::Html),
             style: Some("h1 { color: red; }".to_string()),
             ..minimal_template()
@@ -293,6 +459,43 @@ mod tests {
         assert!(html.contains("<h1"), "expected <h1 in: {html}");
     }
 
+    #[test]
+    fn test_css_mode_keep_leftovers_and_none() {
+        let render = |mode: CssMode| {
+            let t = Template {
+                body: "<h1>Hi</h1>".into(),
+                body_format: Some(BodyFormat::Html),
+                style: Some(
+                    "h1 { color: red; }\n@media (max-width: 600px) { h1 { color: blue; } }".into(),
+                ),
+                css_mode: Some(mode),
+                ..minimal_template()
+            };
+            render_context(&t, &make_context(&[]), Path::new("."))
+                .unwrap()
+                .html_body
+                .unwrap()
+        };
+
+        let html = render(CssMode::Inline);
+        assert!(html.contains("<h1 style=\"color: red;\">"), "{html}");
+        assert!(!html.contains("@media"), "{html}");
+
+        let html = render(CssMode::KeepLeftovers);
+        assert!(html.contains("<h1 style=\"color: red;\">"), "{html}");
+        let style = html
+            .find("<style>\n@media (max-width: 600px)")
+            .expect(&html);
+        assert!(style < html.find("</head>").unwrap(), "{html}");
+
+        let html = render(CssMode::None);
+        assert!(html.contains("<h1>Hi</h1>"), "{html}");
+        assert!(
+            html.contains("<style>\nh1 { color: red; }\n@media"),
+            "{html}"
+        );
+    }
+
     #[test]
     fn test_css_inlining_from_stylesheet_file() {
         let mut css_file = tempfile::NamedTempFile::new().unwrap();
@@ -300,7 +503,7 @@ mod tests {
         let css_dir = css_file.path().parent().unwrap();
         let css_filename = css_file.path().file_name().unwrap().to_str().unwrap();
         let t = Template {
-            body: "<h1>Test</h1>".to_string(),
+            body: "<h1>Test</h1>".into(),
             body_format: Some(BodyFormat::Html),
             stylesheet: Some(css_filename.to_string()),
             ..minimal_template()
@@ -314,6 +517,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_document_wraps_html_body() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: 'News for {{name}}'\npreheader: 'Hi {{name}}, this week'\nbody: 'Hello'\nstyle: 'p { color: red; }'\ndocument:\n  lang: en\n  container: {width: 480}\n",
+        );
+        let ctx = make_context(&[("name", json!("Ada"))]);
+        let email = render_context(&t, &ctx, Path::new(".")).unwrap();
+        let html = email.html_body.unwrap();
+        assert!(
+            html.starts_with("<!DOCTYPE html>"),
+            "missing doctype in: {html}"
+        );
+        assert!(
+            html.contains("<html lang=\"en\">"),
+            "missing lang in: {html}"
+        );
+        assert!(html.contains("<title>News for Ada</title>"), "{html}");
+        assert!(html.contains("Hi Ada, this week</div>"), "{html}");
+        assert!(html.contains("width=\"480\""), "{html}");
+        assert!(
+            html.contains("<p style=\"color: red;\">Hello</p>"),
+            "{html}"
+        );
+        assert_eq!(email.text_body, "Hello\n");
+    }
+
+    #[test]
+    fn test_preheader_escapes_only_data() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\npreheader: '{{name}} inside&zwnj;&nbsp;&zwnj;&nbsp;'\nbody: 'Hello'\n",
+        );
+        let ctx = make_context(&[("name", json!("<b>Tom & Jerry</b>"))]);
+        let html = render_context(&t, &ctx, Path::new("."))
+            .unwrap()
+            .html_body
+            .unwrap();
+        assert!(
+            html.contains(
+                ">&lt;b&gt;Tom &amp; Jerry&lt;/b&gt; inside&zwnj;&nbsp;&zwnj;&nbsp;</div>"
+            ),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn test_document_disabled_keeps_fragment() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\nbody: 'Hello'\ndocument: {enabled: false}\n",
+        );
+        let email = render_context(&t, &make_context(&[]), Path::new(".")).unwrap();
+        assert_eq!(email.html_body.unwrap(), "<p>Hello</p>\n");
+    }
+
+    #[test]
+    fn test_local_images_become_cid_references() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\nbody: '![Logo](img/logo.png) ![Remote](https://example.com/a.png)'\n",
+        );
+        let email = render_context(&t, &make_context(&[]), Path::new("/tpl")).unwrap();
+        assert_eq!(email.inline_images.len(), 1);
+        let image = &email.inline_images[0];
+        assert_eq!(image.path, Path::new("/tpl/img/logo.png"));
+        let html = email.html_body.unwrap();
+        assert!(
+            html.contains(&format!("src=\"cid:{}\"", image.cid)),
+            "{html}"
+        );
+        assert!(html.contains("src=\"https://example.com/a.png\""), "{html}");
+    }
+
     #[test]
     fn test_plaintext_fallback() {
         let t = make_template(
@@ -339,6 +612,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_text_body_replaces_derived_text() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\nbody: '[Portal]({{url}})'\ntext_body: |\n  Portal:\n  {{url}}\n",
+        );
+        let ctx = make_context(&[("url", json!("https://example.com"))]);
+        let email = render_context(&t, &ctx, Path::new(".")).unwrap();
+        assert!(email.html_body.unwrap().contains("<a href"));
+        assert_eq!(email.text_body, "Portal:\nhttps://example.com\n");
+    }
+
     #[test]
     fn test_html_format_skips_markdown() {
         let t = make_template(
@@ -360,7 +644,7 @@ mod tests {
     #[test]
     fn test_text_format_no_html() {
         let t = Template {
-            body: "Hello {{name}}".to_string(),
+            body: "Hello {{name}}".into(),
             body_format: Some(BodyFormat::Text),
             style: Some("h1 { color: red; }".to_string()),
             ..minimal_template()
@@ -413,6 +697,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_render_error_position_maps_to_file() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\nbody: |\n  Hi,\n  see {{missing_var}}\nbody_format: text",
+        );
+        let err = render_context(&t, &make_context(&[]), Path::new(".")).unwrap_err();
+        let MailnirError::HandlebarsRender {
+            field,
+            field_position,
+            file_position,
+            ..
+        } = &err
+        else {
+            panic!("expected HandlebarsRender, got: {err}");
+        };
+        assert_eq!(field, "body");
+        assert_eq!(field_position, &Some(TextPosition { line: 2, column: 5 }));
+        assert_eq!(file_position, &Some(TextPosition { line: 7, column: 7 }));
+        assert_eq!(err.file_position(), *file_position);
+    }
+
+    #[test]
+    fn test_syntax_error_position_maps_to_file() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: \"ok {{#if x}}\"\nbody: b\nbody_format: text",
+        );
+        let err = render_context(&t, &make_context(&[]), Path::new(".")).unwrap_err();
+        let position = err.file_position().expect("syntax errors have a position");
+        assert_eq!(position.line, 4);
+    }
+
     #[test]
     fn test_optional_fields_none() {
         let t = make_template(
@@ -433,4 +748,209 @@ mod tests {
         let email = render_context(&t, &ctx, Path::new(".")).unwrap();
         assert!(email.attachments.is_empty());
     }
+
+    #[test]
+    fn test_localized_variant_chosen_per_entry() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nfallback_locale: en\nsubject:\n  en: 'Hello {{p.name}}'\n  de: 'Hallo {{p.name}}'\nbody: b\nbody_format: text",
+        );
+        let render = |lang: Value| {
+            let ctx = make_context(&[("p", json!({"name": "Ada", "lang": lang}))]);
+            render_context(&t, &ctx, Path::new(".")).map(|e| e.subject)
+        };
+        assert_eq!(render(json!("de")).unwrap(), "Hallo Ada");
+        assert_eq!(render(json!("de_AT")).unwrap(), "Hallo Ada");
+        assert_eq!(render(json!("fr")).unwrap(), "Hello Ada");
+        assert_eq!(render(Value::Null).unwrap(), "Hello Ada");
+    }
+
+    #[test]
+    fn test_missing_locale_variant_error() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nsubject: s\nbody:\n  en: Hi\nbody_format: text",
+        );
+        let ctx = make_context(&[("p", json!({"lang": "fr"}))]);
+        let err = render_context(&t, &ctx, Path::new(".")).unwrap_err();
+        assert!(matches!(
+            err,
+            MailnirError::MissingLocaleVariant { ref field, locale: Some(ref l) }
+                if field == "body" && l == "fr"
+        ));
+    }
+
+    #[test]
+    fn test_error_in_variant_names_locale() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nsubject:\n  de: '{{p.missing}}'\nbody: b\nbody_format: text",
+        );
+        let ctx = make_context(&[("p", json!({"lang": "de"}))]);
+        let err = render_context(&t, &ctx, Path::new(".")).unwrap_err();
+        assert!(matches!(
+            err,
+            MailnirError::HandlebarsRender { ref field, .. } if field == "subject.de"
+        ));
+    }
+
+    #[test]
+    fn test_error_in_variant_located_in_file() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nsubject: {de: 'Hallo {{p.nmae}}'}\nbody:\n  de: |\n    Hallo\n    {{p.nmae}}\nbody_format: text",
+        );
+        let ctx = make_context(&[("p", json!({"lang": "de"}))]);
+        let err = render_context(&t, &ctx, Path::new(".")).unwrap_err();
+        assert_eq!(
+            err.file_position(),
+            Some(TextPosition {
+                line: 5,
+                column: 22
+            })
+        );
+
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nsubject: s\nbody:\n  de: |\n    Hallo\n    {{p.nmae}}\nbody_format: text",
+        );
+        let err = render_context(&t, &ctx, Path::new(".")).unwrap_err();
+        assert_eq!(
+            err.file_position(),
+            Some(TextPosition { line: 9, column: 5 })
+        );
+    }
+
+    #[test]
+    fn test_translate_helper_uses_entry_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("en.ftl"),
+            "files = { $count ->\n    [one] one file\n   *[other] { $count } files\n}\n",
+        )
+        .unwrap();
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\ntranslations: {en: en.ftl}\nsubject: '{{t \"files\" count=p.n}}'\nbody: b\nbody_format: text",
+        );
+        let render = |n: u64| {
+            let ctx = make_context(&[("p", json!({"lang": "en-GB", "n": n}))]);
+            render_context(&t, &ctx, dir.path()).unwrap().subject
+        };
+        assert_eq!(render(1), "one file");
+        assert_eq!(render(3), "3 files");
+
+        let ctx = make_context(&[("p", json!({"lang": "fr", "n": 1}))]);
+        let err = render_context(&t, &ctx, dir.path()).unwrap_err();
+        assert!(matches!(
+            err,
+            MailnirError::MissingTranslation { ref ids, locale: Some(ref l) }
+                if ids == &["files"] && l == "fr"
+        ));
+    }
+
+    #[test]
+    fn test_data_escaped_in_html_bodies_only() {
+        let yaml = |extra: &str| {
+            make_template(&format!(
+                "sources:\n  p: {{primary: true}}\nto: a@b.com\nsubject: 'Re: {{{{p.v}}}}'\nbody: '{{{{p.v}}}} {{{{{{p.v}}}}}}'\ndocument: {{enabled: false}}\n{extra}"
+            ))
+        };
+        let ctx = make_context(&[("p", json!({"v": "<b>A&B</b>"}))]);
+        let render = |t: &Template| render_context(t, &ctx, Path::new(".")).unwrap();
+
+        let email = render(&yaml(""));
+        assert_eq!(email.subject, "Re: <b>A&B</b>");
+        assert_eq!(
+            email.html_body.unwrap(),
+            "<p>&lt;b&gt;A&amp;B&lt;/b&gt; <b>A&amp;B</b></p>\n"
+        );
+
+        let email = render(&yaml("body_format: html"));
+        assert_eq!(
+            email.html_body.unwrap(),
+            "&lt;b&gt;A&amp;B&lt;/b&gt; <b>A&B</b>"
+        );
+
+        let email = render(&yaml("body_format: text"));
+        assert_eq!(email.text_body, "<b>A&B</b> <b>A&B</b>");
+
+        let email = render(&yaml("body_format: html\nescape_html: false"));
+        assert_eq!(email.html_body.unwrap(), "<b>A&B</b> <b>A&B</b>");
+    }
+
+    #[test]
+    fn test_markdown_in_data_renders_as_text() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nsubject: s\nbody: 'Hi {{p.name}}, see [your account]({{p.url}}).'\ndocument: {enabled: false}",
+        );
+        let ctx = make_context(&[(
+            "p",
+            json!({
+                "name": "[Reset password](https://phish) ![](https://tracker/px.gif) [x](javascript:alert(1)) **bold** <b>",
+                "url": "https://example.com/a b?x=\"1\"",
+            }),
+        )]);
+        let email = render_context(&t, &ctx, Path::new(".")).unwrap();
+        assert_eq!(
+            email.html_body.unwrap(),
+            "<p>Hi [Reset password](https://phish) ![](https://tracker/px.gif) [x](javascript:alert(1)) **bold** &lt;b&gt;, \
+             see <a href=\"https://example.com/a%20b?x=%221%22\">your account</a>.</p>\n"
+        );
+        assert!(!email.text_body.contains('\u{E000}'));
+    }
+
+    #[test]
+    fn test_markdown_escape_keeps_code_spans_readable() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nsubject: s\nbody: 'Code: `{{p.code}}`'\ndocument: {enabled: false}",
+        );
+        let ctx = make_context(&[("p", json!({"code": "it's a=b"}))]);
+        let email = render_context(&t, &ctx, Path::new(".")).unwrap();
+        assert_eq!(
+            email.html_body.unwrap(),
+            "<p>Code: <code>it's a=b</code></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_translation_args_escaped_in_html_body() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("en.ftl"), "hi = Hi <b>{ $name }</b>\n").unwrap();
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\ntranslations: {en: en.ftl}\nfallback_locale: en\nsubject: '{{t \"hi\" name=p.name}}'\nbody: '{{t \"hi\" name=p.name}} / {{{t \"hi\" name=p.name}}}'\nbody_format: html\ndocument: {enabled: false}",
+        );
+        let ctx = make_context(&[("p", json!({"name": "<i>Ann</i>"}))]);
+        let email = render_context(&t, &ctx, dir.path()).unwrap();
+        let html = email.html_body.unwrap();
+        let (escaped, raw) = html.split_once(" / ").unwrap();
+        assert!(
+            escaped.contains("<b>") && escaped.contains("&lt;i&gt;Ann"),
+            "{html}"
+        );
+        assert!(raw.contains("<i>Ann</i>"), "{html}");
+        assert!(email.subject.contains("<i>Ann</i>"), "{}", email.subject);
+    }
+
+    #[test]
+    fn test_env_values_rendered() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nbcc: '{{env.AUDIT}}'\nsubject: '{{#with env}}{{PORTAL}}{{/with}}'\nbody: b\nbody_format: text",
+        );
+        let env: Environment = [("AUDIT", "audit@example.com"), ("PORTAL", "https://x")]
+            .into_iter()
+            .collect();
+        let ctx = make_context(&[("p", json!({}))]);
+        let email = render_context_with_env(&t, &ctx, Path::new("."), &env).unwrap();
+        assert_eq!(email.bcc.as_deref(), Some("audit@example.com"));
+        assert_eq!(email.subject, "https://x");
+    }
+
+    #[test]
+    fn test_undefined_env_lists_every_name() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\nto: '{{env.B}}'\nsubject: '{{env.A}}'\nbody: b",
+        );
+        let ctx = make_context(&[("p", json!({}))]);
+        let err =
+            render_context_with_env(&t, &ctx, Path::new("."), &Environment::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            MailnirError::UndefinedEnvVar { ref names, .. } if names == &["A", "B"]
+        ));
+    }
 }
@@ -0,0 +1,706 @@
+//! HTML → plain text for the text alternative of HTML emails.
+//!
+//! Block elements become paragraphs, lists get bullets or numbers, data
+//! tables are laid out in columns and links keep their URL. Layout tables —
+//! a single column, or cells holding blocks — are transparent: their cells
+//! read like `<div>`s. Entities are decoded by the html5ever tokenizer;
+//! `<head>`, `<style>` and `<script>` are dropped.
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    BufferQueue, Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+};
+
+use crate::template::{LinkStyle, PlainTextOptions};
+
+/// Line width when the template sets none (RFC 5322's recommended limit).
+pub const DEFAULT_WRAP: usize = 78;
+
+/// Lines are never wrapped narrower than this, however deep the nesting.
+const MIN_WRAP: usize = 20;
+
+/// Convert `html` (a fragment or a full document) to wrapped plain text.
+pub fn html_to_text(html: &str, options: &PlainTextOptions) -> String {
+    let converter = Converter {
+        wrap: options.wrap.unwrap_or(DEFAULT_WRAP),
+        footnotes: options.links == Some(LinkStyle::Footnotes),
+        ..Converter::default()
+    };
+    let mut tokenizer = Tokenizer::new(converter, TokenizerOpts::default());
+    let mut input = BufferQueue::default();
+    input.push_back(StrTendril::from(html));
+    let _ = tokenizer.feed(&mut input);
+    tokenizer.end();
+    tokenizer.sink.finish()
+}
+
+#[derive(Default)]
+struct Converter {
+    /// 0 turns wrapping off.
+    wrap: usize,
+    footnotes: bool,
+    out: String,
+    /// A blank line is owed before the next block.
+    blank_pending: bool,
+    /// Inline text of the current block, whitespace collapsed; `\n` is a `<br>`.
+    inline: String,
+    /// Bullet for the first line of the current block (set by `<li>`).
+    bullet: Option<String>,
+    lists: Vec<List>,
+    quote_depth: usize,
+    /// Quote depth of the last emitted block.
+    last_quote_depth: usize,
+    pre_depth: usize,
+    skip_depth: usize,
+    /// Open `<a>`s: href and where their text starts in the current buffer.
+    links: Vec<(Option<String>, usize)>,
+    urls: Vec<String>,
+    table: Option<Table>,
+    /// A table read ahead until its end tag, to choose how to lay it out.
+    pending_table: Option<PendingTable>,
+}
+
+#[derive(Default)]
+struct PendingTable {
+    tokens: Vec<Pending>,
+    /// Open `<table>`s, including nested ones.
+    depth: usize,
+}
+
+enum Pending {
+    Tag(Tag),
+    Text(String),
+}
+
+struct List {
+    /// Next number of an `<ol>`; `None` for `<ul>`.
+    next: Option<u64>,
+    /// Width of the current item's bullet, which continuation lines indent by.
+    indent: usize,
+}
+
+#[derive(Default)]
+struct Table {
+    caption: Option<String>,
+    rows: Vec<Vec<String>>,
+    /// Whether the first row is all `<th>`.
+    header: bool,
+    cell: Option<String>,
+}
+
+impl TokenSink for Converter {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line: u64) -> TokenSinkResult<()> {
+        match token {
+            Token::TagToken(tag) => return self.feed_tag(tag),
+            Token::CharacterTokens(text) => self.feed_text(&text),
+            _ => {}
+        }
+        TokenSinkResult::Continue
+    }
+}
+
+impl Converter {
+    /// Handle a tag, holding back a table's tokens until it ends.
+    fn feed_tag(&mut self, tag: Tag) -> TokenSinkResult<()> {
+        let start = tag.kind == TagKind::StartTag;
+        if let Some(pending) = self.pending_table.as_mut() {
+            if &*tag.name == "table" {
+                if start {
+                    pending.depth += 1;
+                } else {
+                    pending.depth -= 1;
+                }
+            }
+            // The tokenizer still needs to know about raw text elements.
+            let result = match (&*tag.name, start) {
+                ("style", true) => TokenSinkResult::RawData(RawKind::Rawtext),
+                ("script", true) => TokenSinkResult::RawData(RawKind::ScriptData),
+                ("title", true) => TokenSinkResult::RawData(RawKind::Rcdata),
+                _ => TokenSinkResult::Continue,
+            };
+            pending.tokens.push(Pending::Tag(tag));
+            if pending.depth == 0 {
+                self.lay_out_table();
+            }
+            return result;
+        }
+        if &*tag.name == "table" && start && self.skip_depth == 0 && self.table.is_none() {
+            self.pending_table = Some(PendingTable {
+                tokens: vec![Pending::Tag(tag)],
+                depth: 1,
+            });
+            return TokenSinkResult::Continue;
+        }
+        self.tag(&tag)
+    }
+
+    fn feed_text(&mut self, text: &str) {
+        match self.pending_table.as_mut() {
+            Some(pending) => pending.tokens.push(Pending::Text(text.to_string())),
+            None => self.text(text),
+        }
+    }
+
+    /// Replay a read-ahead table: in columns if it is a data table, or with
+    /// its cells as ordinary blocks if it is used for layout.
+    fn lay_out_table(&mut self) {
+        let Some(pending) = self.pending_table.take() else {
+            return;
+        };
+        if is_data_table(&pending.tokens) {
+            for token in pending.tokens {
+                match token {
+                    Pending::Tag(tag) => {
+                        let _ = self.tag(&tag);
+                    }
+                    Pending::Text(text) => self.text(&text),
+                }
+            }
+        } else {
+            self.flush(false);
+            // Without an open data table, table parts only separate blocks;
+            // nested tables are read ahead and decided on their own.
+            for token in pending.tokens.into_iter().skip(1) {
+                match token {
+                    Pending::Tag(tag) => {
+                        let _ = self.feed_tag(tag);
+                    }
+                    Pending::Text(text) => self.feed_text(&text),
+                }
+            }
+            self.flush(false);
+        }
+    }
+
+    fn tag(&mut self, tag: &Tag) -> TokenSinkResult<()> {
+        let name: &str = &tag.name;
+        let start = tag.kind == TagKind::StartTag;
+        match name {
+            // `</head>` may be omitted, so `<body>` always ends skipping.
+            "body" => self.skip_depth = 0,
+            "head" | "style" | "script" | "title" | "template" => {
+                if start {
+                    self.skip_depth += 1;
+                    return match name {
+                        "style" => TokenSinkResult::RawData(RawKind::Rawtext),
+                        "script" => TokenSinkResult::RawData(RawKind::ScriptData),
+                        "title" => TokenSinkResult::RawData(RawKind::Rcdata),
+                        _ => TokenSinkResult::Continue,
+                    };
+                }
+                self.skip_depth = self.skip_depth.saturating_sub(1);
+            }
+            _ if self.skip_depth > 0 => {}
+            "a" if start => {
+                let href = attr(tag, "href");
+                let offset = self.buffer().len();
+                self.links.push((href, offset));
+            }
+            "a" => self.close_link(),
+            "img" => {
+                if let Some(alt) = attr(tag, "alt") {
+                    self.text(&alt);
+                }
+            }
+            "table" | "tr" | "td" | "th" | "thead" | "tbody" | "tfoot" | "caption" => {
+                self.table_tag(name, start)
+            }
+            "br" if self.table.is_some() => self.text(" "),
+            _ if self.table.is_some() => {}
+            "br" => self.inline.push('\n'),
+            "p" | "blockquote" | "ul" | "ol" | "pre" | "hr" | "dl" | "figure" => {
+                // A nested list continues its parent item without a blank line.
+                let nested_list =
+                    matches!(name, "ul" | "ol") && self.lists.len() > usize::from(!start);
+                self.flush(!nested_list);
+                match (name, start) {
+                    ("blockquote", true) => self.quote_depth += 1,
+                    ("blockquote", false) => self.quote_depth = self.quote_depth.saturating_sub(1),
+                    ("ul", true) => self.lists.push(List {
+                        next: None,
+                        indent: 2,
+                    }),
+                    ("ol", true) => {
+                        let first = attr(tag, "start").and_then(|s| s.trim().parse().ok());
+                        self.lists.push(List {
+                            next: Some(first.unwrap_or(1)),
+                            indent: 3,
+                        });
+                    }
+                    ("ul" | "ol", false) => {
+                        self.lists.pop();
+                    }
+                    ("pre", true) => self.pre_depth += 1,
+                    ("pre", false) => self.pre_depth = self.pre_depth.saturating_sub(1),
+                    ("hr", _) => {
+                        let width = if self.wrap == 0 { 40 } else { self.wrap };
+                        self.emit(vec!["-".repeat(width.min(DEFAULT_WRAP))]);
+                        self.blank_pending = true;
+                    }
+                    _ => {}
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if start {
+                    self.flush(true);
+                } else {
+                    let underline = match name {
+                        "h1" => Some('='),
+                        "h2" => Some('-'),
+                        _ => None,
+                    };
+                    self.flush_block(underline);
+                    self.blank_pending = true;
+                }
+            }
+            "li" => {
+                self.flush(false);
+                if start {
+                    let bullet = match self.lists.last_mut() {
+                        Some(List {
+                            next: Some(n),
+                            indent,
+                        }) => {
+                            let bullet = format!("{n}. ");
+                            *n += 1;
+                            *indent = bullet.len();
+                            bullet
+                        }
+                        _ => "- ".to_string(),
+                    };
+                    self.bullet = Some(bullet);
+                }
+            }
+            "div" | "section" | "article" | "header" | "footer" | "main" | "nav" | "aside"
+            | "address" | "center" | "dt" | "dd" | "figcaption" | "form" | "fieldset" => {
+                self.flush(false)
+            }
+            _ => {}
+        }
+        TokenSinkResult::Continue
+    }
+
+    fn table_tag(&mut self, name: &str, start: bool) {
+        if name == "table" {
+            if start {
+                self.flush(true);
+                self.table = Some(Table::default());
+            } else if let Some(table) = self.table.take() {
+                self.emit(table.lines());
+                self.blank_pending = true;
+            }
+            return;
+        }
+        let Some(table) = self.table.as_mut() else {
+            // Parts of a layout table (or stray ones) separate blocks.
+            self.flush(false);
+            return;
+        };
+        match (name, start) {
+            ("tr", true) => table.rows.push(Vec::new()),
+            ("td" | "th", true) => {
+                table.close_cell();
+                if table.rows.is_empty() {
+                    table.rows.push(Vec::new());
+                }
+                if table.rows.len() == 1 {
+                    let first_cell = table.rows[0].is_empty();
+                    table.header = name == "th" && (first_cell || table.header);
+                }
+                table.cell = Some(String::new());
+            }
+            ("td" | "th" | "tr", false) => table.close_cell(),
+            ("caption", true) => table.cell = Some(String::new()),
+            ("caption", false) => {
+                table.caption = table.cell.take().map(|c| c.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn close_link(&mut self) {
+        let Some((href, offset)) = self.links.pop() else {
+            return;
+        };
+        let footnotes = self.footnotes;
+        let text = self.buffer().get(offset..).unwrap_or("").trim().to_string();
+        let Some(url) = href.filter(|h| !h.is_empty() && !h.starts_with('#')) else {
+            return;
+        };
+        if url.starts_with("javascript:") {
+            return;
+        }
+        let shown = url.strip_prefix("mailto:").unwrap_or(&url);
+        if text.is_empty() {
+            self.text(shown);
+            return;
+        }
+        if text == shown || text == url {
+            return;
+        }
+        let reference = if footnotes {
+            let number = match self.urls.iter().position(|u| *u == url) {
+                Some(i) => i + 1,
+                None => {
+                    self.urls.push(url);
+                    self.urls.len()
+                }
+            };
+            format!("[{number}]")
+        } else {
+            format!(" ({url})")
+        };
+        let buffer = self.buffer();
+        if buffer.ends_with(' ') {
+            buffer.pop();
+        }
+        buffer.push_str(&reference);
+    }
+
+    /// The buffer inline text goes to: the current table cell, or the block.
+    fn buffer(&mut self) -> &mut String {
+        match self.table.as_mut() {
+            Some(Table {
+                cell: Some(cell), ..
+            }) => cell,
+            _ => &mut self.inline,
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        if self.table.is_some() {
+            if self.table.as_ref().is_some_and(|t| t.cell.is_none()) {
+                // Text between cells, e.g. a caption or indentation.
+                if text.trim().is_empty() {
+                    return;
+                }
+                if let Some(table) = self.table.as_mut() {
+                    table.cell = Some(String::new());
+                }
+            }
+        } else if self.pre_depth > 0 {
+            self.inline.push_str(text);
+            return;
+        }
+        let buffer = self.buffer();
+        for c in text.chars() {
+            // U+00A0 is whitespace to Rust but must not collapse or break.
+            if c.is_whitespace() && c != '\u{a0}' {
+                if !buffer.is_empty() && !buffer.ends_with([' ', '\n']) {
+                    buffer.push(' ');
+                }
+            } else {
+                buffer.push(c);
+            }
+        }
+    }
+
+    /// End the current block; `blank` asks for a blank line before the next.
+    fn flush(&mut self, blank: bool) {
+        self.flush_block(None);
+        self.blank_pending |= blank;
+    }
+
+    fn flush_block(&mut self, underline: Option<char>) {
+        let text = std::mem::take(&mut self.inline);
+        let mut lines: Vec<String> = if self.pre_depth > 0 {
+            let text = text.strip_prefix('\n').unwrap_or(&text).trim_end();
+            text.lines().map(str::to_string).collect()
+        } else {
+            let width = self.wrap_width();
+            text.split('\n')
+                .map(|line| line.trim().to_string())
+                .flat_map(|line| wrap(&line, width))
+                .collect()
+        };
+        while lines.first().is_some_and(|l| l.is_empty()) {
+            lines.remove(0);
+        }
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        if lines.is_empty() {
+            return;
+        }
+        if let Some(c) = underline {
+            let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+            lines.push(c.to_string().repeat(width));
+        }
+        self.emit(lines);
+    }
+
+    /// Room for text on one line after the quote and list prefix.
+    fn wrap_width(&self) -> usize {
+        if self.wrap == 0 {
+            return 0;
+        }
+        let prefix = 2 * self.quote_depth + self.lists.iter().map(|l| l.indent).sum::<usize>();
+        self.wrap.saturating_sub(prefix).max(MIN_WRAP)
+    }
+
+    /// Append `lines` as one block, with quote markers and list indentation.
+    fn emit(&mut self, lines: Vec<String>) {
+        let quote = "> ".repeat(self.quote_depth);
+        if !self.out.is_empty() && self.blank_pending {
+            // Only quotes enclosing both blocks continue across the gap.
+            let depth = self.quote_depth.min(self.last_quote_depth);
+            self.out.push_str("> ".repeat(depth).trim_end());
+            self.out.push('\n');
+        }
+        self.blank_pending = false;
+        self.last_quote_depth = self.quote_depth;
+
+        let outer: usize = self
+            .lists
+            .iter()
+            .rev()
+            .skip(1)
+            .map(|l| l.indent)
+            .sum::<usize>();
+        let indent = " ".repeat(outer + self.lists.last().map_or(0, |l| l.indent));
+        let bullet = self.bullet.take();
+        for (i, line) in lines.iter().enumerate() {
+            let prefix = match (&bullet, i) {
+                (Some(bullet), 0) => format!("{quote}{}{bullet}", " ".repeat(outer)),
+                _ => format!("{quote}{indent}"),
+            };
+            let full = format!("{prefix}{line}");
+            self.out.push_str(full.trim_end());
+            self.out.push('\n');
+        }
+    }
+
+    fn finish(mut self) -> String {
+        // An unclosed table still ends with the document.
+        while self.pending_table.is_some() {
+            self.lay_out_table();
+        }
+        self.flush(false);
+        if self.table.is_some() {
+            let table = self.table.take().unwrap_or_default();
+            self.emit(table.lines());
+        }
+        if !self.urls.is_empty() {
+            self.blank_pending = true;
+            let urls: Vec<String> = self
+                .urls
+                .iter()
+                .enumerate()
+                .map(|(i, url)| format!("[{}] {url}", i + 1))
+                .collect();
+            self.quote_depth = 0;
+            self.lists.clear();
+            self.emit(urls);
+        }
+        self.out.replace('\u{a0}', " ")
+    }
+}
+
+impl Table {
+    fn close_cell(&mut self) {
+        if let Some(cell) = self.cell.take() {
+            let cell = cell.trim().to_string();
+            match self.rows.last_mut() {
+                Some(row) => row.push(cell),
+                None => self.rows.push(vec![cell]),
+            }
+        }
+    }
+
+    /// Rows with columns padded to a common width; a header row is underlined.
+    fn lines(mut self) -> Vec<String> {
+        self.close_cell();
+        self.rows.retain(|row| row.iter().any(|c| !c.is_empty()));
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|c| c.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let format_row = |cells: Vec<String>| {
+            cells
+                .iter()
+                .enumerate()
+                .map(|(i, c)| format!("{c:<width$}", width = widths[i]))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        let mut lines: Vec<String> = self.caption.clone().into_iter().collect();
+        for (i, row) in self.rows.iter().enumerate() {
+            lines.push(format_row(row.clone()));
+            if i == 0 && self.header && self.rows.len() > 1 {
+                lines.push(format_row(widths.iter().map(|w| "-".repeat(*w)).collect()));
+            }
+        }
+        lines
+    }
+}
+
+/// Whether a read-ahead table holds data: some row with two or more cells,
+/// and cells with only inline content (no blocks, line breaks or tables).
+fn is_data_table(tokens: &[Pending]) -> bool {
+    let mut columns = 0;
+    let mut row = 0;
+    for token in tokens.iter().skip(1) {
+        let Pending::Tag(tag) = token else {
+            continue;
+        };
+        let start = tag.kind == TagKind::StartTag;
+        match &*tag.name {
+            "tr" if start => row = 0,
+            "td" | "th" if start => {
+                row += 1;
+                columns = usize::max(columns, row);
+            }
+            // The table's own end tag closes the token list.
+            "table" if start => return false,
+            "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "li"
+            | "blockquote" | "pre" | "hr" | "dl" | "br" | "figure" | "section" | "article"
+            | "header" | "footer" | "main" | "nav" | "aside" | "address" | "center" | "form"
+            | "fieldset" => return false,
+            _ => {}
+        }
+    }
+    columns >= 2
+}
+
+fn attr(tag: &Tag, name: &str) -> Option<String> {
+    tag.attrs
+        .iter()
+        .find(|a| &*a.name.local == name)
+        .map(|a| a.value.to_string())
+}
+
+/// Greedy word wrap at `width` characters; 0 leaves the line alone. Words
+/// longer than the width (such as URLs) get a line of their own.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    if width == 0 || line.chars().count() <= width {
+        return vec![line.to_string()];
+    }
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split(' ').filter(|w| !w.is_empty()) {
+        let len = current.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    lines.push(current);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(html: &str) -> String {
+        html_to_text(html, &PlainTextOptions::default())
+    }
+
+    #[test]
+    fn test_blocks_and_line_breaks() {
+        assert_eq!(
+            text("<h1>Welcome</h1><p>First\n  paragraph.</p><p>Line one<br>Line two</p>"),
+            "Welcome\n=======\n\nFirst paragraph.\n\nLine one\nLine two\n"
+        );
+        assert_eq!(text("<div>a</div><div>b</div>"), "a\nb\n");
+        assert_eq!(text(""), "");
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            text("<ul><li>one</li><li>two<ul><li>inner</li></ul></li></ul><ol start=\"9\"><li>nine</li><li>ten</li></ol>"),
+            "- one\n- two\n  - inner\n\n9. nine\n10. ten\n"
+        );
+    }
+
+    #[test]
+    fn test_links_inline_and_footnotes() {
+        let html = "<p>See <a href=\"https://example.com/a\">the docs</a>, \
+                    <a href=\"https://example.com/a\">again</a> or \
+                    <a href=\"mailto:help@example.com\">help@example.com</a>.</p>";
+        assert_eq!(
+            text(html),
+            "See the docs (https://example.com/a), again (https://example.com/a) or\nhelp@example.com.\n"
+        );
+        let footnotes = PlainTextOptions {
+            wrap: Some(0),
+            links: Some(LinkStyle::Footnotes),
+        };
+        assert_eq!(
+            html_to_text(html, &footnotes),
+            "See the docs[1], again[1] or help@example.com.\n\n[1] https://example.com/a\n"
+        );
+    }
+
+    #[test]
+    fn test_entities_style_and_head_dropped() {
+        let html = "<html><head><title>T</title><style>p { color: red; }</style></head>\
+                    <body><script>var x = '<p>';</script><p>5&#8364; &amp; &euro;&nbsp;10 &lt;ok&gt;</p></body></html>";
+        assert_eq!(text(html), "5€ & € 10 <ok>\n");
+    }
+
+    #[test]
+    fn test_table_columns() {
+        let html = "<table><tr><th>Item</th><th>Qty</th></tr>\
+                    <tr><td>Apples</td><td>3</td></tr><tr><td>Kiwi</td><td>12</td></tr></table>\
+                    <p>after</p>";
+        assert_eq!(
+            text(html),
+            "Item    Qty\n------  ---\nApples  3\nKiwi    12\n\nafter\n"
+        );
+    }
+
+    #[test]
+    fn test_layout_tables_transparent() {
+        let html = "<table width=\"600\"><tr><td><h1>Welcome</h1><p>First paragraph with \
+                    enough words in it to need wrapping at the default width of the text part.</p>\
+                    <ul><li>one</li><li>two</li></ul><p>Second paragraph.</p></td></tr></table>";
+        assert_eq!(
+            text(html),
+            "Welcome\n=======\n\nFirst paragraph with enough words in it to need wrapping at the default width\nof the text part.\n\n- one\n- two\n\nSecond paragraph.\n"
+        );
+
+        // A data table inside a layout cell keeps its columns.
+        let html = "<table><tr><td><p>Your order:</p>\
+                    <table><tr><td>Apples</td><td>3</td></tr><tr><td>Kiwi</td><td>12</td></tr></table>\
+                    </td></tr><tr><td>Line one<br>Line two</td></tr></table>";
+        assert_eq!(
+            text(html),
+            "Your order:\n\nApples  3\nKiwi    12\n\nLine one\nLine two\n"
+        );
+    }
+
+    #[test]
+    fn test_wrapping_and_pre() {
+        let options = PlainTextOptions {
+            wrap: Some(20),
+            links: None,
+        };
+        assert_eq!(
+            html_to_text(
+                "<p>the quick brown fox jumps over the lazy dog</p><blockquote><p>quoted text that wraps here</p></blockquote><pre>\n  keep   this\n</pre>",
+                &options
+            ),
+            "the quick brown fox\njumps over the lazy\ndog\n\n> quoted text that\n> wraps here\n\n  keep   this\n"
+        );
+    }
+}
@@ -10,7 +10,7 @@ use lettre::{
 use serde::{Deserialize, Serialize};
 use tokio::sync::Semaphore;
 
-use crate::{render::RenderedEmail, MailnirError, Result};
+use crate::{env::Environment, render::RenderedEmail, MailnirError, Result};
 
 fn default_parallelism() -> usize {
     1
@@ -26,6 +26,9 @@ pub enum Encryption {
 }
 
 /// Named SMTP send profile — connection settings and send behaviour.
+///
+/// `host` and `from` may contain `${NAME}` environment references, resolved
+/// with [`SmtpProfile::resolve_env`] before use.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SmtpProfile {
     pub name: String,
@@ -39,6 +42,36 @@ pub struct SmtpProfile {
     pub parallelism: usize,
 }
 
+impl SmtpProfile {
+    /// A copy with every `${NAME}` in `host` and `from` replaced from `env`.
+    pub fn resolve_env(&self, env: &Environment) -> Result<SmtpProfile> {
+        let mut missing: Vec<String> = Vec::new();
+        let mut expand = |text: &str| {
+            env.expand(text).unwrap_or_else(|names| {
+                for name in names {
+                    if !missing.contains(&name) {
+                        missing.push(name);
+                    }
+                }
+                String::new()
+            })
+        };
+        let resolved = SmtpProfile {
+            host: expand(&self.host),
+            from: expand(&self.from),
+            ..self.clone()
+        };
+        if missing.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(MailnirError::UndefinedEnvVar {
+                names: missing,
+                location: format!("SMTP profile '{}'", self.name),
+            })
+        }
+    }
+}
+
 /// SMTP account credentials retrieved from the OS keychain.
 #[derive(Debug, Clone)]
 pub struct SmtpCredentials {
@@ -371,8 +404,28 @@ fn build_transport(
 /// Build a lettre [`Message`] from a [`RenderedEmail`] and a from-address.
 ///
 /// Produces `multipart/alternative` when `html_body` is present, plain text otherwise.
-/// Attachments are wrapped in an outer `multipart/mixed`.
-fn build_message(email: &RenderedEmail, from: &str, entry_index: usize) -> Result<Message> {
+/// Inline images are wrapped with the HTML part in `multipart/related`, and
+/// attachments in an outer `multipart/mixed`.
+pub fn build_message(email: &RenderedEmail, from: &str, entry_index: usize) -> Result<Message> {
+    compose_message(email, from, entry_index, false)
+}
+
+/// Like [`build_message`], but keeps the `Bcc` header, which is otherwise
+/// dropped once the envelope is set, so a saved copy shows every recipient.
+pub fn build_message_keeping_bcc(
+    email: &RenderedEmail,
+    from: &str,
+    entry_index: usize,
+) -> Result<Message> {
+    compose_message(email, from, entry_index, true)
+}
+
+fn compose_message(
+    email: &RenderedEmail,
+    from: &str,
+    entry_index: usize,
+    keep_bcc: bool,
+) -> Result<Message> {
     let from_mbox = from
         .parse::<Mailbox>()
         .map_err(|e| MailnirError::SmtpSend {
@@ -406,11 +459,32 @@ fn build_message(email: &RenderedEmail, from: &str, entry_index: usize) -> Resul
         })?;
         builder = builder.bcc(mbox);
     }
+    if keep_bcc {
+        builder = builder.keep_bcc();
+    }
 
     let message = if let Some(html) = &email.html_body {
-        let alt = MultiPart::alternative()
-            .singlepart(SinglePart::plain(email.text_body.clone()))
-            .singlepart(SinglePart::html(html.clone()));
+        let alt = MultiPart::alternative().singlepart(SinglePart::plain(email.text_body.clone()));
+        let alt = if email.inline_images.is_empty() {
+            alt.singlepart(SinglePart::html(html.clone()))
+        } else {
+            let mut related = MultiPart::related().singlepart(SinglePart::html(html.clone()));
+            for image in &email.inline_images {
+                let bytes = std::fs::read(&image.path).map_err(|e| MailnirError::Io {
+                    path: image.path.clone(),
+                    source: e,
+                })?;
+                let content_type = guess_content_type(&image.path);
+                related = related.singlepart(
+                    Attachment::new_inline_with_name(
+                        image.cid.clone(),
+                        attachment_name(&image.path),
+                    )
+                    .body(bytes, content_type),
+                );
+            }
+            alt.multipart(related)
+        };
         if email.attachments.is_empty() {
             builder.multipart(alt)
         } else {
@@ -519,6 +593,7 @@ mod tests {
             html_body: Some("<p>Hello</p>".to_string()),
             text_body: "Hello".to_string(),
             attachments: vec![],
+            inline_images: vec![],
         }
     }
 
@@ -632,6 +707,32 @@ mod tests {
         assert!(raw.contains("Hello"), "missing text body");
     }
 
+    #[test]
+    fn test_build_message_inline_images_related() {
+        let mut tmp_png = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        std::io::Write::write_all(&mut tmp_png, b"\x89PNG-fake").unwrap();
+        let mut email = sample_email("r@example.com");
+        email.html_body = Some("<img src=\"cid:logo@mailnir\">".to_string());
+        email.inline_images = vec![crate::render::InlineImage {
+            cid: "logo@mailnir".to_string(),
+            path: tmp_png.path().to_path_buf(),
+        }];
+        let msg = build_message(&email, "s@example.com", 0).unwrap();
+        let raw = String::from_utf8(msg.formatted()).unwrap();
+        let alternative = raw.find("multipart/alternative").expect("alternative");
+        let related = raw.find("multipart/related").expect("related part");
+        assert!(alternative < related, "related must sit inside alternative");
+        assert!(raw.contains("Content-ID: <logo@mailnir>"), "{raw}");
+        assert!(raw.contains("Content-Disposition: inline"), "{raw}");
+        assert!(raw.contains("image/png"), "{raw}");
+
+        email.inline_images[0].path = tmp_png.path().with_extension("missing");
+        assert!(matches!(
+            build_message(&email, "s@example.com", 0),
+            Err(MailnirError::Io { .. })
+        ));
+    }
+
     #[test]
     fn test_build_message_plain_text_only() {
         let mut email = sample_email("r@example.com");
@@ -660,6 +761,7 @@ mod tests {
             html_body: Some("<p>Hi</p>".to_string()),
             text_body: "Hi".to_string(),
             attachments: vec![tmp_pdf.path().to_path_buf(), tmp_png.path().to_path_buf()],
+            inline_images: vec![],
         };
         let msg = build_message(&email, "s@example.com", 0).unwrap();
         let raw = String::from_utf8(msg.formatted()).unwrap();
@@ -689,6 +791,7 @@ mod tests {
             html_body: None,
             text_body: "Hi".to_string(),
             attachments: vec![tmp.path().to_path_buf()],
+            inline_images: vec![],
         };
         let msg = build_message(&email, "s@example.com", 0).unwrap();
         let raw = String::from_utf8(msg.formatted()).unwrap();
@@ -736,4 +839,26 @@ mod tests {
             "expected application/octet-stream, got: {unknown}"
         );
     }
+
+    #[test]
+    fn test_resolve_env_in_profile() {
+        let mut profile = sample_profile("staging");
+        profile.host = "${SMTP_HOST}".into();
+        profile.from = "Mailnir <${FROM_USER}@example.com>".into();
+        let env: Environment = [
+            ("SMTP_HOST", "smtp.staging.example.com"),
+            ("FROM_USER", "noreply"),
+        ]
+        .into_iter()
+        .collect();
+        let resolved = profile.resolve_env(&env).unwrap();
+        assert_eq!(resolved.host, "smtp.staging.example.com");
+        assert_eq!(resolved.from, "Mailnir <noreply@example.com>");
+
+        let err = profile.resolve_env(&Environment::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "undefined environment variables SMTP_HOST, FROM_USER in SMTP profile 'staging'"
+        );
+    }
 }
@@ -0,0 +1,63 @@
+/// A line diff of `expected` against `actual`: unchanged lines start with
+/// two spaces, removed ones with `- `, added ones with `+ `.
+///
+/// Uses the longest common subsequence of lines, which is plenty for email
+/// bodies.
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // lcs[i][j]: length of the LCS of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let mut push = |prefix: &str, line: &str| {
+        out.push_str(prefix);
+        out.push_str(line);
+        out.push('\n');
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            push("  ", old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            push("- ", old[i]);
+            i += 1;
+        } else {
+            push("+ ", new[j]);
+            j += 1;
+        }
+    }
+    // Differences only in trailing newlines leave no changed line.
+    if expected.ends_with('\n') != actual.ends_with('\n') {
+        out.push_str(if actual.ends_with('\n') {
+            "+ (trailing newline)\n"
+        } else {
+            "- (trailing newline)\n"
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff() {
+        assert_eq!(line_diff("a\nb\nc", "a\nc\nd"), "  a\n- b\n  c\n+ d\n");
+        assert_eq!(line_diff("same", "same"), "  same\n");
+        assert_eq!(line_diff("x\n", "x"), "  x\n- (trailing newline)\n");
+    }
+}
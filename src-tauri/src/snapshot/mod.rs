@@ -0,0 +1,445 @@
+//! Golden-output tests for templates.
+//!
+//! Tests live in the template's `tests:` key or in a sibling
+//! `<name>.mailnir.test.yml` holding the same `tests:` list. Each one renders
+//! its inline sources through `build_contexts` and `render_context` and
+//! compares the result with the stored snapshots.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::env::Environment;
+use crate::join::build_contexts_lenient;
+use crate::render::{CompiledTemplate, RenderedEmail};
+use crate::template::{edit_top_level_keys, parse_template, ExpectedEmail, Template, TemplateTest};
+
+mod diff;
+
+pub use diff::line_diff;
+
+/// Suffix of template files, replaced by [`TEST_FILE_SUFFIX`] for the sibling.
+pub const TEMPLATE_SUFFIX: &str = ".mailnir.yml";
+pub const TEST_FILE_SUFFIX: &str = ".mailnir.test.yml";
+
+/// Where a test is stored.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum TestOrigin {
+    Template,
+    TestFile(PathBuf),
+}
+
+/// Why a test failed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SnapshotFailure {
+    /// The inline sources could not be joined at all.
+    Contexts { reason: String },
+    /// A different number of emails was rendered than snapshots exist.
+    EntryCount { expected: usize, actual: usize },
+    /// One entry failed to join or render.
+    Render { entry: usize, reason: String },
+    /// A rendered field differs from its snapshot; `diff` is line-based.
+    Mismatch {
+        entry: usize,
+        field: String,
+        diff: String,
+    },
+}
+
+impl fmt::Display for SnapshotFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotFailure::Contexts { reason } => write!(f, "could not build contexts: {reason}"),
+            SnapshotFailure::EntryCount { expected, actual } => {
+                write!(f, "expected {expected} emails, rendered {actual}")
+            }
+            SnapshotFailure::Render { entry, reason } => {
+                write!(f, "email {}: {reason}", entry + 1)
+            }
+            SnapshotFailure::Mismatch { entry, field, diff } => {
+                write!(f, "email {} {field} differs:\n{diff}", entry + 1)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestOutcome {
+    pub name: String,
+    pub origin: TestOrigin,
+    pub failures: Vec<SnapshotFailure>,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotReport {
+    pub outcomes: Vec<TestOutcome>,
+}
+
+impl SnapshotReport {
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(TestOutcome::passed)
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.outcomes.iter().filter(|o| !o.passed()).count()
+    }
+}
+
+impl fmt::Display for SnapshotReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for outcome in &self.outcomes {
+            let status = if outcome.passed() { "ok" } else { "FAILED" };
+            writeln!(f, "test {} ... {status}", outcome.name)?;
+            for failure in &outcome.failures {
+                for line in failure.to_string().lines() {
+                    writeln!(f, "    {line}")?;
+                }
+            }
+        }
+        write!(
+            f,
+            "{} tests, {} passed, {} failed",
+            self.outcomes.len(),
+            self.outcomes.len() - self.failed_count(),
+            self.failed_count()
+        )
+    }
+}
+
+/// The sibling test file of a template: `welcome.mailnir.yml` →
+/// `welcome.mailnir.test.yml`.
+pub fn test_file_path(template_path: &Path) -> PathBuf {
+    let name = template_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = name.strip_suffix(TEMPLATE_SUFFIX).unwrap_or_else(|| {
+        name.rsplit_once('.')
+            .map_or(name.as_str(), |(stem, _)| stem)
+    });
+    template_path.with_file_name(format!("{stem}{TEST_FILE_SUFFIX}"))
+}
+
+/// Run the snapshot tests of the template at `path`: its `tests:` key, then
+/// those of its sibling test file, if present.
+pub fn run_template_test_file(path: &Path) -> crate::Result<SnapshotReport> {
+    let template = parse_template(path)?;
+    let template_dir = path.parent().unwrap_or(Path::new("."));
+    let outcomes = collect_tests(&template, path)?
+        .into_iter()
+        .map(|(origin, test)| {
+            let failures = check_test(&template, template_dir, &test);
+            TestOutcome {
+                name: test.name,
+                origin,
+                failures,
+            }
+        })
+        .collect();
+    Ok(SnapshotReport { outcomes })
+}
+
+/// Run `tests` against `template`; relative paths resolve from `template_dir`.
+pub fn run_template_tests(
+    template: &Template,
+    template_dir: &Path,
+    tests: &[TemplateTest],
+) -> Vec<TestOutcome> {
+    tests
+        .iter()
+        .map(|test| TestOutcome {
+            name: test.name.clone(),
+            origin: TestOrigin::Template,
+            failures: check_test(template, template_dir, test),
+        })
+        .collect()
+}
+
+/// Rewrite the snapshots of every test of the template at `path` with the
+/// current output. An existing snapshot keeps its set of fields; a missing
+/// one records all of them. Tests whose entries fail to render are left as
+/// they are and reported. Returns the outcomes from before the update.
+pub fn update_snapshots(path: &Path) -> crate::Result<SnapshotReport> {
+    let report = run_template_test_file(path)?;
+    let template = parse_template(path)?;
+    let template_dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut in_template = Vec::new();
+    let mut in_test_file = Vec::new();
+    for (origin, mut test) in collect_tests(&template, path)? {
+        if let Ok(rendered) = render_test(&template, template_dir, &test) {
+            test.expect = rendered
+                .iter()
+                .enumerate()
+                .map(|(i, email)| snapshot_of(email, test.expect.get(i)))
+                .collect();
+        }
+        match origin {
+            TestOrigin::Template => in_template.push(test),
+            TestOrigin::TestFile(_) => in_test_file.push(test),
+        }
+    }
+
+    if template.tests.is_some() {
+        write_tests(path, &in_template)?;
+    }
+    let test_file = test_file_path(path);
+    if test_file.exists() {
+        write_tests(&test_file, &in_test_file)?;
+    }
+    Ok(report)
+}
+
+/// The tests of a template, in template-then-test-file order.
+fn collect_tests(
+    template: &Template,
+    path: &Path,
+) -> crate::Result<Vec<(TestOrigin, TemplateTest)>> {
+    let mut tests: Vec<(TestOrigin, TemplateTest)> = template
+        .tests
+        .iter()
+        .flatten()
+        .map(|t| (TestOrigin::Template, t.clone()))
+        .collect();
+    let test_file = test_file_path(path);
+    if test_file.exists() {
+        let origin = TestOrigin::TestFile(test_file.clone());
+        tests.extend(
+            read_test_file(&test_file)?
+                .into_iter()
+                .map(|t| (origin.clone(), t)),
+        );
+    }
+    Ok(tests)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TestFile {
+    #[serde(default)]
+    tests: Vec<TemplateTest>,
+}
+
+fn read_test_file(path: &Path) -> crate::Result<Vec<TemplateTest>> {
+    let content = std::fs::read_to_string(path).map_err(|source| crate::MailnirError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let file: TestFile = serde_yaml::from_str(&content).map_err(|source| {
+        crate::MailnirError::TemplateParseYaml {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    Ok(file.tests)
+}
+
+/// Replace the `tests:` key of the YAML file at `path`, keeping the rest.
+fn write_tests(path: &Path, tests: &[TemplateTest]) -> crate::Result<()> {
+    let io_err = |source| crate::MailnirError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let content = std::fs::read_to_string(path).map_err(io_err)?;
+    let value =
+        serde_yaml::to_value(tests).map_err(|source| crate::MailnirError::TemplateParseYaml {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    let updated = edit_top_level_keys(&content, &[("tests", Some(value))])?;
+    std::fs::write(path, updated).map_err(io_err)
+}
+
+fn render_test(
+    template: &Template,
+    template_dir: &Path,
+    test: &TemplateTest,
+) -> Result<Vec<RenderedEmail>, SnapshotFailure> {
+    let sources: HashMap<String, Value> = test
+        .sources
+        .iter()
+        .map(|(ns, data)| (ns.clone(), data.clone()))
+        .collect();
+    let env: Environment = test
+        .env
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let contexts =
+        build_contexts_lenient(template, &sources).map_err(|e| SnapshotFailure::Contexts {
+            reason: e.to_string(),
+        })?;
+    let compiled = CompiledTemplate::new(template, template_dir, &env);
+    contexts
+        .into_iter()
+        .enumerate()
+        .map(|(entry, context)| {
+            context
+                .and_then(|ctx| compiled.render(&ctx))
+                .map_err(|e| SnapshotFailure::Render {
+                    entry,
+                    reason: e.to_string(),
+                })
+        })
+        .collect()
+}
+
+fn check_test(
+    template: &Template,
+    template_dir: &Path,
+    test: &TemplateTest,
+) -> Vec<SnapshotFailure> {
+    let rendered = match render_test(template, template_dir, test) {
+        Ok(rendered) => rendered,
+        Err(failure) => return vec![failure],
+    };
+    let mut failures = Vec::new();
+    if rendered.len() != test.expect.len() {
+        failures.push(SnapshotFailure::EntryCount {
+            expected: test.expect.len(),
+            actual: rendered.len(),
+        });
+    }
+    for (entry, (email, expected)) in rendered.iter().zip(&test.expect).enumerate() {
+        let actual = snapshot_of(email, None);
+        let fields = [
+            ("to", &expected.to, &actual.to),
+            ("subject", &expected.subject, &actual.subject),
+            ("text_body", &expected.text_body, &actual.text_body),
+            ("html_body", &expected.html_body, &actual.html_body),
+        ];
+        for (field, expected, actual) in fields {
+            let Some(expected) = expected else { continue };
+            let actual = actual.as_deref().unwrap_or("");
+            if expected != actual {
+                failures.push(SnapshotFailure::Mismatch {
+                    entry,
+                    field: field.to_string(),
+                    diff: line_diff(expected, actual),
+                });
+            }
+        }
+    }
+    failures
+}
+
+/// The snapshot of `email`, limited to the fields `previous` records (all
+/// fields without one).
+fn snapshot_of(email: &RenderedEmail, previous: Option<&ExpectedEmail>) -> ExpectedEmail {
+    let keep = |had: Option<&Option<String>>| had.is_none_or(Option::is_some);
+    ExpectedEmail {
+        to: keep(previous.map(|p| &p.to)).then(|| email.to.clone()),
+        subject: keep(previous.map(|p| &p.subject)).then(|| email.subject.clone()),
+        text_body: keep(previous.map(|p| &p.text_body)).then(|| email.text_body.clone()),
+        html_body: email
+            .html_body
+            .clone()
+            .filter(|_| keep(previous.map(|p| &p.html_body))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "sources:\n  p: {primary: true}\nto: '{{p.email}}'\nsubject: 'Hi {{p.name}}'\nbody: |\n  Hello {{p.name}},\n  see you.\nbody_format: text\n";
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_test_file_path() {
+        assert_eq!(
+            test_file_path(Path::new("/t/welcome.mailnir.yml")),
+            Path::new("/t/welcome.mailnir.test.yml")
+        );
+        assert_eq!(
+            test_file_path(Path::new("welcome.yml")),
+            Path::new("welcome.mailnir.test.yml")
+        );
+    }
+
+    #[test]
+    fn test_inline_tests_pass_and_fail_with_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "t.mailnir.yml",
+            &format!(
+                "{TEMPLATE}tests:\n  - name: ok\n    sources:\n      p: [{{name: Ada, email: a@x.org}}]\n    expect:\n      - subject: Hi Ada\n        text_body: \"Hello Ada,\\nsee you.\\n\"\n  - name: changed\n    sources:\n      p: [{{name: Bo, email: b@x.org}}]\n    expect:\n      - text_body: \"Hello Bo,\\nbye.\\n\"\n"
+            ),
+        );
+        let report = run_template_test_file(&path).unwrap();
+        assert!(report.outcomes[0].passed(), "{report}");
+        assert_eq!(
+            report.outcomes[1].failures,
+            vec![SnapshotFailure::Mismatch {
+                entry: 0,
+                field: "text_body".into(),
+                diff: "  Hello Bo,\n- bye.\n+ see you.\n".into(),
+            }]
+        );
+        assert_eq!(report.failed_count(), 1);
+    }
+
+    #[test]
+    fn test_entry_count_and_render_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "t.mailnir.yml", TEMPLATE);
+        write(
+            dir.path(),
+            "t.mailnir.test.yml",
+            "tests:\n  - name: two rows\n    sources:\n      p: [{name: A, email: a@x.org}, {name: B, email: b@x.org}]\n    expect:\n      - {to: a@x.org}\n  - name: missing field\n    sources:\n      p: [{name: A}]\n",
+        );
+        let report = run_template_test_file(&path).unwrap();
+        assert_eq!(
+            report.outcomes[0].failures,
+            vec![SnapshotFailure::EntryCount {
+                expected: 1,
+                actual: 2
+            }]
+        );
+        assert!(matches!(report.outcomes[1].origin, TestOrigin::TestFile(_)));
+        assert!(matches!(
+            report.outcomes[1].failures.as_slice(),
+            [SnapshotFailure::Render { entry: 0, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_update_snapshots_keeps_field_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "t.mailnir.yml",
+            &format!(
+                "# keep this comment\n{TEMPLATE}tests:\n  - name: one\n    sources:\n      p: [{{name: Ada, email: a@x.org}}, {{name: Bo, email: b@x.org}}]\n    expect:\n      - subject: stale\n"
+            ),
+        );
+        let before = update_snapshots(&path).unwrap();
+        assert!(!before.passed());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# keep this comment\n"));
+        let template = parse_template(&path).unwrap();
+        let expect = &template.tests.as_ref().unwrap()[0].expect;
+        assert_eq!(expect[0].subject.as_deref(), Some("Hi Ada"));
+        assert_eq!(expect[0].to, None);
+        assert_eq!(expect[1].to.as_deref(), Some("b@x.org"));
+        assert!(run_template_test_file(&path).unwrap().passed());
+    }
+}
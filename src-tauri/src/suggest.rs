@@ -0,0 +1,73 @@
+/// Levenshtein edit distance between two strings, counted in chars.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut curr = vec![0; b_chars.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, &cb) in b_chars.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b_chars.len()]
+}
+
+/// Pick the candidate closest to `needle`, if any is near enough to be a
+/// plausible typo (within a third of the needle's length, rounded up).
+pub(crate) fn closest<'a, I>(needle: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max_distance = needle.chars().count().div_ceil(3);
+    candidates
+        .into_iter()
+        .filter(|c| *c != needle)
+        .map(|c| (edit_distance(needle, c), c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance_identical() {
+        assert_eq!(edit_distance("class_id", "class_id"), 0);
+    }
+
+    #[test]
+    fn test_edit_distance_empty() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+    }
+
+    #[test]
+    fn test_edit_distance_typos() {
+        assert_eq!(edit_distance("clas_id", "class_id"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("M-101", "M-110"), 2);
+    }
+
+    #[test]
+    fn test_edit_distance_counts_chars_not_bytes() {
+        assert_eq!(edit_distance("Müller", "Muller"), 1);
+    }
+
+    #[test]
+    fn test_closest_picks_nearest() {
+        let fields = ["class_id", "name", "email"];
+        assert_eq!(closest("clas_id", fields), Some("class_id".to_string()));
+        assert_eq!(closest("emial", fields), Some("email".to_string()));
+    }
+
+    #[test]
+    fn test_closest_rejects_distant() {
+        assert_eq!(closest("zzz", ["class_id", "name"]), None);
+    }
+}
@@ -0,0 +1,209 @@
+use regex::Regex;
+use serde_json::Value;
+
+use super::types::{FormField, FormFieldType};
+
+impl FormField {
+    pub fn is_required(&self) -> bool {
+        self.required == Some(true)
+    }
+
+    /// Compile `pattern`, anchored so it must match the whole value.
+    pub fn compiled_pattern(&self) -> Result<Option<Regex>, String> {
+        self.pattern
+            .as_deref()
+            .map(|p| Regex::new(&format!("^(?:{p})$")).map_err(|e| format!("invalid pattern: {e}")))
+            .transpose()
+    }
+
+    /// Check a submitted value against the field's type, options, pattern and
+    /// required flag. `None` and `null` count as not filled in.
+    pub fn check_value(&self, value: Option<&Value>) -> Result<(), String> {
+        let value = value.unwrap_or(&Value::Null);
+        if is_empty(self.field_type, value) {
+            return if self.is_required() {
+                Err("is required".into())
+            } else {
+                Ok(())
+            };
+        }
+
+        let texts: Vec<String> = match (self.field_type, value) {
+            (FormFieldType::Text | FormFieldType::Multiline, Value::String(s)) => vec![s.clone()],
+            (FormFieldType::Email, Value::String(s)) => {
+                if s.trim().parse::<lettre::Address>().is_err() {
+                    return Err(format!("'{s}' is not a valid email address"));
+                }
+                vec![s.clone()]
+            }
+            (FormFieldType::Number, Value::Number(n)) => vec![n.to_string()],
+            (FormFieldType::Number, Value::String(s)) => {
+                if s.trim().parse::<f64>().is_err() {
+                    return Err(format!("'{s}' is not a number"));
+                }
+                vec![s.trim().to_string()]
+            }
+            (FormFieldType::Date, Value::String(s)) => {
+                if !is_iso_date(s) {
+                    return Err(format!("'{s}' is not a date (expected YYYY-MM-DD)"));
+                }
+                vec![s.clone()]
+            }
+            (FormFieldType::Select, Value::String(s)) => {
+                let options = self.options.as_deref().unwrap_or_default();
+                if !options.contains(s) {
+                    return Err(format!("'{s}' is not one of: {}", options.join(", ")));
+                }
+                vec![s.clone()]
+            }
+            (FormFieldType::Checkbox, Value::Bool(_)) => return Ok(()),
+            (FormFieldType::List, Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s.clone()),
+                    _ => Err("must be a list of text values".to_string()),
+                })
+                .collect::<Result<_, _>>()?,
+            (FormFieldType::Number, _) => return Err("must be a number".into()),
+            (FormFieldType::Checkbox, _) => return Err("must be true or false".into()),
+            (FormFieldType::List, _) => return Err("must be a list".into()),
+            _ => return Err("must be text".into()),
+        };
+
+        if let Ok(Some(re)) = self.compiled_pattern() {
+            let pattern = self.pattern.as_deref().unwrap_or_default();
+            if let Some(bad) = texts.iter().find(|t| !re.is_match(t)) {
+                return Err(format!("'{bad}' does not match pattern '{pattern}'"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Unfilled: null, blank text, an empty list, or an unticked checkbox.
+fn is_empty(field_type: FormFieldType, value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Bool(b) => field_type == FormFieldType::Checkbox && !b,
+        _ => false,
+    }
+}
+
+/// `YYYY-MM-DD` naming a real calendar day.
+fn is_iso_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    let [y, m, d] = parts.as_slice() else {
+        return false;
+    };
+    if y.len() != 4 || m.len() != 2 || d.len() != 2 {
+        return false;
+    }
+    let (Ok(y), Ok(m), Ok(d)) = (y.parse::<u32>(), m.parse::<u32>(), d.parse::<u32>()) else {
+        return false;
+    };
+    let leap = (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+    let days = match m {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&d)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(yaml: &str) -> FormField {
+        serde_yaml::from_str(yaml).expect("fixture must parse")
+    }
+
+    #[test]
+    fn test_type_defaults_to_text() {
+        let f = field("name: nickname");
+        assert_eq!(f.field_type, FormFieldType::Text);
+        assert!(f.check_value(Some(&json!("Bo"))).is_ok());
+        assert_eq!(f.check_value(Some(&json!(3))), Err("must be text".into()));
+    }
+
+    #[test]
+    fn test_required_and_empty() {
+        let f = field("{name: n, required: true}");
+        assert_eq!(f.check_value(None), Err("is required".into()));
+        assert_eq!(f.check_value(Some(&json!("  "))), Err("is required".into()));
+        assert!(field("name: n").check_value(None).is_ok());
+
+        let tick = field("{name: agree, type: checkbox, required: true}");
+        assert_eq!(
+            tick.check_value(Some(&json!(false))),
+            Err("is required".into())
+        );
+        assert!(tick.check_value(Some(&json!(true))).is_ok());
+    }
+
+    #[test]
+    fn test_email_number_date() {
+        let email = field("{name: e, type: email}");
+        assert!(email.check_value(Some(&json!("a@b.com"))).is_ok());
+        assert!(email.check_value(Some(&json!("nope"))).is_err());
+
+        let number = field("{name: n, type: number}");
+        assert!(number.check_value(Some(&json!(4.5))).is_ok());
+        assert!(number.check_value(Some(&json!("12"))).is_ok());
+        assert_eq!(
+            number.check_value(Some(&json!("twelve"))),
+            Err("'twelve' is not a number".into())
+        );
+
+        let date = field("{name: d, type: date}");
+        assert!(date.check_value(Some(&json!("2024-02-29"))).is_ok());
+        assert!(date.check_value(Some(&json!("2023-02-29"))).is_err());
+        assert!(date.check_value(Some(&json!("29.02.2024"))).is_err());
+    }
+
+    #[test]
+    fn test_select_options() {
+        let f = field("{name: size, type: select, options: [S, M, L]}");
+        assert!(f.check_value(Some(&json!("M"))).is_ok());
+        assert_eq!(
+            f.check_value(Some(&json!("XL"))),
+            Err("'XL' is not one of: S, M, L".into())
+        );
+    }
+
+    #[test]
+    fn test_list_items_and_pattern() {
+        let f = field("{name: tags, type: list, pattern: '[a-z]+'}");
+        assert!(f.check_value(Some(&json!(["a", "bc"]))).is_ok());
+        assert_eq!(
+            f.check_value(Some(&json!(["a", "B1"]))),
+            Err("'B1' does not match pattern '[a-z]+'".into())
+        );
+        assert_eq!(
+            f.check_value(Some(&json!("a"))),
+            Err("must be a list".into())
+        );
+    }
+
+    #[test]
+    fn test_pattern_must_match_whole_value() {
+        let f = field("{name: code, pattern: '[A-Z]-\\d{3}'}");
+        assert!(f.check_value(Some(&json!("M-101"))).is_ok());
+        assert!(f.check_value(Some(&json!("xM-101x"))).is_err());
+    }
+
+    #[test]
+    fn test_invalid_pattern_reported() {
+        let f = field("{name: code, pattern: '('}");
+        assert!(f
+            .compiled_pattern()
+            .unwrap_err()
+            .starts_with("invalid pattern"));
+    }
+}
@@ -1,55 +1,305 @@
 use std::collections::BTreeSet;
 
-use super::types::Template;
+use handlebars::template::{
+    BlockParam, HelperTemplate, Parameter, Template as HbsTemplate, TemplateElement,
+};
+use handlebars::{Path, PathSeg};
+
+use super::types::{FormField, Template};
+
+/// How a template uses a referenced path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableUsage {
+    /// Interpolated, passed to a helper, or used as a `with`/`if` argument.
+    Scalar,
+    /// Iterated with `{{#each}}`.
+    Iterated,
+}
+
+/// One data path referenced from a template field.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct VariableRef {
+    pub namespace: String,
+    /// Segments below the namespace, e.g. `["address", "city"]`. Empty when the
+    /// namespace itself is referenced.
+    pub path: Vec<String>,
+    /// Template field the reference appears in (`to`, `subject`, `body`, ...).
+    pub field: String,
+    /// Byte offset of the enclosing `{{` within the field's string.
+    pub offset: usize,
+    pub usage: VariableUsage,
+}
+
+/// Collect every data path referenced by the template's Handlebars fields.
+///
+/// Paths are resolved through the Handlebars AST, so references inside
+/// `{{#with}}`/`{{#each}}` blocks, block params, `../` and `@root` paths,
+/// helper arguments and subexpressions are attributed to their namespace.
+/// Text outside `{{ }}` is ignored. Fields that fail to compile are skipped
+/// (rendering reports the syntax error). Results are in field order, then
+/// document order.
+pub fn template_variables(template: &Template) -> Vec<VariableRef> {
+    let mut refs = Vec::new();
+    for (field, source) in template.handlebars_fields() {
+        let Ok(compiled) = HbsTemplate::compile(source) else {
+            continue;
+        };
+        let mut walker = Walker {
+            field,
+            source,
+            refs: &mut refs,
+        };
+        walker.walk_template(&compiled, &mut vec![Frame::new(Scope::Root)]);
+    }
+    refs
+}
 
 /// Extract field names referenced in template strings for a given namespace.
 ///
-/// Scans to, cc, bcc, subject, body, and attachments for patterns like
-/// `namespace.field` inside Handlebars expressions. Returns a sorted,
-/// deduplicated list of field names.
+/// Returns the sorted, deduplicated first path segments of every reference
+/// into `namespace` found by [`template_variables`].
 pub fn infer_form_fields(template: &Template, namespace: &str) -> Vec<String> {
-    let strings: Vec<&str> = [
-        Some(template.to.as_str()),
-        template.cc.as_deref(),
-        template.bcc.as_deref(),
-        Some(template.subject.as_str()),
-        Some(template.body.as_str()),
-        template.attachments.as_deref(),
-    ]
-    .into_iter()
-    .flatten()
-    .collect();
-
-    let needle = format!("{namespace}.");
-    let mut fields = BTreeSet::new();
-
-    for s in &strings {
-        let mut start = 0;
-        while let Some(pos) = s[start..].find(&needle) {
-            let abs = start + pos;
-            // Ensure the match is at a word boundary (not preceded by a word char).
-            if abs > 0 {
-                let prev = s.as_bytes()[abs - 1];
-                if prev.is_ascii_alphanumeric() || prev == b'_' {
-                    start = abs + needle.len();
-                    continue;
+    template_variables(template)
+        .into_iter()
+        .filter(|r| r.namespace == namespace)
+        .filter_map(|r| r.path.into_iter().next())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The inputs a form source should show: its declared `fields:` in order,
+/// followed by any other field the template references as plain text. Computed
+/// fields are derived, not entered, so they are left out.
+pub fn form_schema(template: &Template, namespace: &str) -> Vec<FormField> {
+    let cfg = template.sources.get(namespace);
+    let mut schema: Vec<FormField> = cfg.and_then(|cfg| cfg.fields.clone()).unwrap_or_default();
+    let computed = cfg.and_then(|cfg| cfg.computed.as_ref());
+    for name in infer_form_fields(template, namespace) {
+        let is_computed = computed.is_some_and(|c| c.contains_key(&name));
+        if !is_computed && !schema.iter().any(|f| f.name == name) {
+            schema.push(FormField::text(name));
+        }
+    }
+    schema
+}
+
+/// What unqualified paths resolve against inside a block.
+#[derive(Debug, Clone)]
+enum Scope {
+    /// The merged context: the first segment names a namespace.
+    Root,
+    /// A value inside a namespace.
+    Path {
+        namespace: String,
+        path: Vec<String>,
+    },
+    /// Something we can't attribute (e.g. `each` over a literal).
+    Unknown,
+}
+
+impl Scope {
+    fn join(&self, names: &[&str]) -> Option<(String, Vec<String>)> {
+        match self {
+            Scope::Root => {
+                let (namespace, rest) = names.split_first()?;
+                Some((
+                    namespace.to_string(),
+                    rest.iter().map(|s| s.to_string()).collect(),
+                ))
+            }
+            Scope::Path { namespace, path } => {
+                let mut full = path.clone();
+                full.extend(names.iter().map(|s| s.to_string()));
+                Some((namespace.clone(), full))
+            }
+            Scope::Unknown => None,
+        }
+    }
+}
+
+/// One context level: `with`/`each` push a frame, `../` pops one.
+struct Frame {
+    scope: Scope,
+    block_params: Vec<(String, Scope)>,
+}
+
+impl Frame {
+    fn new(scope: Scope) -> Self {
+        Frame {
+            scope,
+            block_params: Vec::new(),
+        }
+    }
+}
+
+struct Walker<'a> {
+    field: &'static str,
+    source: &'a str,
+    refs: &'a mut Vec<VariableRef>,
+}
+
+impl Walker<'_> {
+    fn walk_template(&mut self, template: &HbsTemplate, frames: &mut Vec<Frame>) {
+        for (element, mapping) in template.elements.iter().zip(&template.mapping) {
+            let offset = line_col_to_offset(self.source, mapping.0, mapping.1);
+            match element {
+                TemplateElement::Expression(helper)
+                | TemplateElement::HtmlExpression(helper)
+                | TemplateElement::HelperBlock(helper) => {
+                    self.walk_helper(helper, offset, frames);
                 }
+                _ => {}
+            }
+        }
+    }
+
+    fn walk_helper(&mut self, helper: &HelperTemplate, offset: usize, frames: &mut Vec<Frame>) {
+        let helper_name = match &helper.name {
+            Parameter::Name(name) => Some(name.as_str()),
+            other => {
+                self.walk_param(other, VariableUsage::Scalar, offset, frames);
+                None
             }
-            // Extract the field name: word characters after the dot.
-            let field_start = abs + needle.len();
-            let field_end = s[field_start..]
-                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
-                .map(|i| field_start + i)
-                .unwrap_or(s.len());
-            let field = &s[field_start..field_end];
-            if !field.is_empty() {
-                fields.insert(field.to_string());
+        };
+
+        for (i, param) in helper.params.iter().enumerate() {
+            let usage = if i == 0 && helper_name == Some("each") {
+                VariableUsage::Iterated
+            } else {
+                VariableUsage::Scalar
+            };
+            self.walk_param(param, usage, offset, frames);
+        }
+        for param in helper.hash.values() {
+            self.walk_param(param, VariableUsage::Scalar, offset, frames);
+        }
+
+        if let Some(inner) = &helper.template {
+            match helper_name {
+                Some("each") | Some("with") => {
+                    let scope = match helper.params.first() {
+                        Some(Parameter::Path(path)) => self
+                            .resolve(path, frames)
+                            .map(|(namespace, path)| Scope::Path { namespace, path })
+                            .unwrap_or(Scope::Unknown),
+                        _ => Scope::Unknown,
+                    };
+                    let mut frame = Frame::new(scope.clone());
+                    match &helper.block_param {
+                        Some(BlockParam::Single(Parameter::Name(name))) => {
+                            frame.block_params.push((name.clone(), scope));
+                        }
+                        Some(BlockParam::Pair((Parameter::Name(value), Parameter::Name(key)))) => {
+                            frame.block_params.push((value.clone(), scope));
+                            frame.block_params.push((key.clone(), Scope::Unknown));
+                        }
+                        _ => {}
+                    }
+                    frames.push(frame);
+                    self.walk_template(inner, frames);
+                    frames.pop();
+                }
+                // `if`, `unless` and custom helpers keep the current context.
+                _ => self.walk_template(inner, frames),
             }
-            start = field_end;
+        }
+        if let Some(inverse) = &helper.inverse {
+            self.walk_template(inverse, frames);
         }
     }
 
-    fields.into_iter().collect()
+    fn walk_param(
+        &mut self,
+        param: &Parameter,
+        usage: VariableUsage,
+        offset: usize,
+        frames: &mut Vec<Frame>,
+    ) {
+        match param {
+            Parameter::Path(path) => {
+                if let Some((namespace, path)) = self.resolve(path, frames) {
+                    self.refs.push(VariableRef {
+                        namespace,
+                        path,
+                        field: self.field.to_string(),
+                        offset,
+                        usage,
+                    });
+                }
+            }
+            Parameter::Subexpression(sub) => {
+                if let TemplateElement::Expression(helper) = sub.as_element() {
+                    self.walk_helper(helper, offset, frames);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Resolve a Handlebars path against the frame stack to `(namespace, path)`.
+    ///
+    /// Returns `None` for `@index`-style data variables and unattributable paths.
+    fn resolve(&self, path: &Path, frames: &[Frame]) -> Option<(String, Vec<String>)> {
+        let Path::Relative((segs, raw)) = path else {
+            return None;
+        };
+        let names: Vec<&str> = segs
+            .iter()
+            .filter_map(|seg| match seg {
+                PathSeg::Named(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        if raw.starts_with("@root") {
+            return Scope::Root.join(&names);
+        }
+        if raw.starts_with('@') {
+            return None;
+        }
+
+        let mut rest = raw.as_str();
+        let mut up = 0;
+        while let Some(stripped) = rest.strip_prefix("../") {
+            up += 1;
+            rest = stripped;
+        }
+        let visible = frames.len().checked_sub(up)?;
+        let frames = &frames[..visible];
+
+        // Block params are visible from nested blocks too; innermost wins.
+        if let Some((first, tail)) = names.split_first() {
+            let param_scope = frames.iter().rev().find_map(|frame| {
+                frame
+                    .block_params
+                    .iter()
+                    .find(|(name, _)| name == first)
+                    .map(|(_, scope)| scope)
+            });
+            if let Some(scope) = param_scope {
+                return scope.join(tail);
+            }
+        }
+
+        frames.last()?.scope.join(&names)
+    }
+}
+
+/// Convert a 1-based line/column (in chars) into a byte offset in `source`.
+fn line_col_to_offset(source: &str, line: usize, col: usize) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let col_bytes: usize = source[line_start..]
+        .chars()
+        .take(col.saturating_sub(1))
+        .map(char::len_utf8)
+        .sum();
+    line_start + col_bytes
 }
 
 #[cfg(test)]
@@ -129,12 +379,125 @@ mod tests {
         let t = parse_template_str(
             "sources:\n  rcpt: {primary: true, form: true}\n\
              to: '{{rcpt.email}}'\ncc: '{{rcpt.cc_email}}'\n\
-             subject: s\nbody: b\nattachments: '{{rcpt.name}}/doc.pdf'",
+             subject: s\nbody: b\ntext_body: '{{rcpt.phone}}'\n\
+             attachments: '{{rcpt.name}}/doc.pdf'",
         )
         .unwrap();
         assert_eq!(
             infer_form_fields(&t, "rcpt"),
-            vec!["cc_email", "email", "name"]
+            vec!["cc_email", "email", "name", "phone"]
+        );
+    }
+
+    #[test]
+    fn test_with_block_and_parent_paths() {
+        let t = parse_template_str(
+            "sources:\n  rcpt: {primary: true}\n  cfg: {}\n\
+             to: x\nsubject: s\n\
+             body: '{{#with rcpt.address}}{{city}} {{../cfg.sender}}{{/with}}'",
+        )
+        .unwrap();
+        let paths: Vec<(String, Vec<String>)> = template_variables(&t)
+            .into_iter()
+            .map(|r| (r.namespace, r.path))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("rcpt".to_string(), vec!["address".to_string()]),
+                (
+                    "rcpt".to_string(),
+                    vec!["address".to_string(), "city".to_string()]
+                ),
+                ("cfg".to_string(), vec!["sender".to_string()]),
+            ]
         );
     }
+
+    #[test]
+    fn test_each_element_paths_and_usage() {
+        let t = parse_template_str(
+            "sources:\n  rcpt: {primary: true}\n\
+             to: x\nsubject: s\n\
+             body: '{{#each rcpt.items as |item i|}}{{item.sku}}{{i}}{{@index}}{{qty}}{{@root.rcpt.name}}{{/each}}'",
+        )
+        .unwrap();
+        let refs = template_variables(&t);
+        let summary: Vec<(String, VariableUsage)> =
+            refs.iter().map(|r| (r.path.join("."), r.usage)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("items".to_string(), VariableUsage::Iterated),
+                ("items.sku".to_string(), VariableUsage::Scalar),
+                ("items.qty".to_string(), VariableUsage::Scalar),
+                ("name".to_string(), VariableUsage::Scalar),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_helper_arguments_and_subexpressions() {
+        let t = parse_template_str(
+            "sources:\n  rcpt: {primary: true}\n  cfg: {}\n\
+             to: x\nsubject: s\n\
+             body: '{{#if (lookup cfg.flags rcpt.tier)}}{{lookup rcpt.prices \"eur\"}}{{/if}}'",
+        )
+        .unwrap();
+        assert_eq!(infer_form_fields(&t, "rcpt"), vec!["prices", "tier"]);
+        assert_eq!(infer_form_fields(&t, "cfg"), vec!["flags"]);
+    }
+
+    #[test]
+    fn test_text_outside_expressions_ignored() {
+        let t = parse_template_str(
+            "sources:\n  rcpt: {primary: true}\n\
+             to: 'rcpt.email@example.com'\nsubject: s\n\
+             body: \"write rcpt.name here {{!-- rcpt.secret --}}\"",
+        )
+        .unwrap();
+        assert!(template_variables(&t).is_empty());
+    }
+
+    #[test]
+    fn test_location_is_field_and_byte_offset() {
+        let t = parse_template_str(
+            "sources:\n  rcpt: {primary: true}\n\
+             to: x\nsubject: 'Héllo {{rcpt.name}}'\nbody: \"line\\n  {{rcpt.id}}\"",
+        )
+        .unwrap();
+        let refs = template_variables(&t);
+        assert_eq!(refs[0].field, "subject");
+        let subject = t.subject.as_single().unwrap();
+        assert_eq!(&subject[refs[0].offset..refs[0].offset + 2], "{{");
+        assert_eq!(refs[0].offset, "Héllo ".len());
+        assert_eq!(refs[1].field, "body");
+        assert_eq!(refs[1].offset, 7);
+    }
+
+    #[test]
+    fn test_invalid_field_is_skipped() {
+        let t = parse_template_str(
+            "sources:\n  rcpt: {primary: true}\n\
+             to: '{{rcpt.email}}'\nsubject: '{{#if}}'\nbody: b",
+        )
+        .unwrap();
+        assert_eq!(infer_form_fields(&t, "rcpt"), vec!["email"]);
+    }
+
+    #[test]
+    fn test_form_schema_declared_then_inferred() {
+        let t = parse_template_str(
+            "sources:\n  rcpt:\n    primary: true\n    form: true\n    fields:\n\
+             \x20     - {name: name, label: Full name, required: true}\n\
+             \x20     - {name: email, type: email}\n\
+             to: '{{rcpt.email}}'\nsubject: '{{rcpt.name}} {{rcpt.city}}'\nbody: b",
+        )
+        .unwrap();
+        let schema = form_schema(&t, "rcpt");
+        let names: Vec<&str> = schema.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["name", "email", "city"]);
+        assert_eq!(schema[0].label.as_deref(), Some("Full name"));
+        assert_eq!(schema[2], FormField::text("city"));
+    }
 }
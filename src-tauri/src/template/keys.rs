@@ -0,0 +1,174 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde_yaml::Value;
+
+use super::span::key_position;
+use super::types::{SourceConfig, Template};
+use crate::suggest::closest;
+
+/// Keys with this prefix are free-form extensions and never rejected.
+const EXTENSION_PREFIX: &str = "x-";
+
+/// Reject keys that `Template` or `SourceConfig` would silently ignore.
+///
+/// `doc` is the raw YAML of `content`; the first unknown key in document
+/// order is reported, with its position and the closest valid key.
+pub(crate) fn check_unknown_keys(doc: &Value, content: &str) -> crate::Result<()> {
+    let Some(root) = doc.as_mapping() else {
+        return Ok(());
+    };
+    check_mapping(root, struct_fields::<Template>(), "template", None, content)?;
+
+    if let Some(sources) = root.get("sources").and_then(Value::as_mapping) {
+        for (namespace, cfg) in sources {
+            let (Some(namespace), Some(cfg)) = (namespace.as_str(), cfg.as_mapping()) else {
+                continue;
+            };
+            check_mapping(
+                cfg,
+                struct_fields::<SourceConfig>(),
+                &format!("sources.{namespace}"),
+                Some(namespace),
+                content,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn check_mapping(
+    map: &serde_yaml::Mapping,
+    known: &[&str],
+    location: &str,
+    namespace: Option<&str>,
+    content: &str,
+) -> crate::Result<()> {
+    for key in map.keys() {
+        let Some(key) = key.as_str() else { continue };
+        if known.contains(&key) || key.starts_with(EXTENSION_PREFIX) {
+            continue;
+        }
+        return Err(crate::MailnirError::UnknownKey {
+            key: key.to_string(),
+            location: location.to_string(),
+            position: key_position(content, namespace, key),
+            suggestion: closest(key, known.iter().copied()),
+        });
+    }
+    Ok(())
+}
+
+/// The field names serde expects for `T`, read from its derived `Deserialize`.
+pub(super) fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut probe = FieldProbe(&[]);
+    let _ = T::deserialize(&mut probe);
+    probe.0
+}
+
+/// A deserializer that records the field list of the struct asked for, then bails.
+struct FieldProbe(&'static [&'static str]);
+
+impl<'de> Deserializer<'de> for &mut FieldProbe {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("field probe"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0 = fields;
+        Err(de::Error::custom("field probe"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::{parse_template_str, TextPosition};
+    use crate::MailnirError;
+
+    #[test]
+    fn test_struct_fields_follow_serde() {
+        let fields = super::struct_fields::<super::Template>();
+        assert!(fields.contains(&"body_format"));
+        assert!(!fields.contains(&"spans"));
+    }
+
+    #[test]
+    fn test_unknown_top_level_key_rejected_with_suggestion() {
+        let err = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a\nsubjct: b\nsubject: b\nbody: c",
+        )
+        .unwrap_err();
+        match err {
+            MailnirError::UnknownKey {
+                key,
+                location,
+                position,
+                suggestion,
+            } => {
+                assert_eq!(key, "subjct");
+                assert_eq!(location, "template");
+                assert_eq!(position, Some(TextPosition { line: 4, column: 1 }));
+                assert_eq!(suggestion.as_deref(), Some("subject"));
+            }
+            other => panic!("expected UnknownKey, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_source_key_rejected() {
+        let err = parse_template_str(
+            "sources:\n  p:\n    primary: true\n    jion: {a: q.b}\n  q: {}\nto: a\nsubject: b\nbody: c",
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        match err {
+            MailnirError::UnknownKey {
+                key,
+                location,
+                position,
+                suggestion,
+            } => {
+                assert_eq!(key, "jion");
+                assert_eq!(location, "sources.p");
+                assert_eq!(position, Some(TextPosition { line: 4, column: 5 }));
+                assert_eq!(suggestion.as_deref(), Some("join"));
+            }
+            other => panic!("expected UnknownKey, got {other:?}"),
+        }
+        assert!(message.contains("did you mean 'join'"), "{message}");
+    }
+
+    #[test]
+    fn test_flow_source_key_position() {
+        let err = parse_template_str(
+            "sources:\n  p: {primary: true, formm: true}\nto: a\nsubject: b\nbody: c",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.file_position(),
+            Some(TextPosition {
+                line: 2,
+                column: 22
+            })
+        );
+    }
+
+    #[test]
+    fn test_extension_keys_allowed() {
+        let t = parse_template_str(
+            "x-notes: anything\nsources:\n  p: {primary: true, x-owner: ops}\nto: a\nsubject: b\nbody: c",
+        );
+        assert!(t.is_ok(), "{t:?}");
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+use super::yaml_edit::{edit_top_level_keys, find_entries, top_level_key};
+
+/// The template format version this build reads and writes.
+///
+/// Documents without a `version:` key are version 1, the format that
+/// predates versioning.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// One upgrade step; `MIGRATIONS[n]` upgrades version `n + 1` to `n + 2`.
+type Migration = fn(&mut Mapping, &mut Vec<MigrationChange>);
+
+const MIGRATIONS: &[Migration] = &[v1_extension_keys];
+
+/// A single change made while upgrading a document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MigrationChange {
+    /// A key was renamed; `namespace` is set for keys inside `sources.<ns>`.
+    RenameKey {
+        namespace: Option<String>,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for MigrationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationChange::RenameKey {
+                namespace: Some(ns),
+                from,
+                to,
+            } => write!(f, "renamed 'sources.{ns}.{from}' to 'sources.{ns}.{to}'"),
+            MigrationChange::RenameKey {
+                namespace: None,
+                from,
+                to,
+            } => write!(f, "renamed '{from}' to '{to}'"),
+        }
+    }
+}
+
+/// What an in-memory upgrade did to an older document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Empty when the document only lacked the `version:` key.
+    pub changes: Vec<MigrationChange>,
+}
+
+/// Upgrade a raw template document to `CURRENT_VERSION` in place.
+///
+/// Returns `None` if the document is already current. A `version:` that is
+/// not a positive integer is left for typed deserialization to reject.
+pub(crate) fn migrate(doc: &mut Value) -> crate::Result<Option<MigrationReport>> {
+    let Some(root) = doc.as_mapping_mut() else {
+        return Ok(None);
+    };
+    let from_version = match root.get("version") {
+        None => 1,
+        Some(v) => match v.as_u64() {
+            Some(n) if n >= 1 && n <= u64::from(CURRENT_VERSION) => n as u32,
+            Some(n) => {
+                return Err(crate::MailnirError::UnsupportedTemplateVersion {
+                    version: n,
+                    supported: CURRENT_VERSION,
+                })
+            }
+            None => return Ok(None),
+        },
+    };
+    if from_version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    let mut changes = Vec::new();
+    for step in &MIGRATIONS[from_version as usize - 1..] {
+        step(root, &mut changes);
+    }
+    root.insert(
+        Value::String("version".into()),
+        Value::Number(CURRENT_VERSION.into()),
+    );
+    Ok(Some(MigrationReport {
+        from_version,
+        to_version: CURRENT_VERSION,
+        changes,
+    }))
+}
+
+/// Version 1 ignored unknown keys, and `_`-prefixed keys were the usual home
+/// for YAML anchor fragments. Version 2 rejects unknown keys, so those move
+/// to the `x-` extension prefix.
+fn v1_extension_keys(root: &mut Mapping, changes: &mut Vec<MigrationChange>) {
+    rename_underscore_keys(root, None, changes);
+    if let Some(sources) = root.get_mut("sources").and_then(Value::as_mapping_mut) {
+        for (namespace, cfg) in sources.iter_mut() {
+            let (Some(namespace), Some(cfg)) = (namespace.as_str(), cfg.as_mapping_mut()) else {
+                continue;
+            };
+            rename_underscore_keys(cfg, Some(namespace), changes);
+        }
+    }
+}
+
+fn rename_underscore_keys(
+    map: &mut Mapping,
+    namespace: Option<&str>,
+    changes: &mut Vec<MigrationChange>,
+) {
+    let renames: Vec<(String, String)> = map
+        .keys()
+        .filter_map(Value::as_str)
+        .filter_map(|k| {
+            let rest = k.strip_prefix('_')?.trim_start_matches('_');
+            let to = format!("x-{}", rest.replace('_', "-"));
+            (!rest.is_empty() && !map.contains_key(to.as_str())).then(|| (k.to_string(), to))
+        })
+        .collect();
+
+    // Rebuild to keep the renamed key where it was.
+    if renames.is_empty() {
+        return;
+    }
+    let old = std::mem::take(map);
+    for (key, value) in old {
+        let renamed = key
+            .as_str()
+            .and_then(|k| renames.iter().find(|(from, _)| from == k));
+        match renamed {
+            Some((_, to)) => {
+                map.insert(Value::String(to.clone()), value);
+            }
+            None => {
+                map.insert(key, value);
+            }
+        }
+    }
+    changes.extend(
+        renames
+            .into_iter()
+            .map(|(from, to)| MigrationChange::RenameKey {
+                namespace: namespace.map(str::to_string),
+                from,
+                to,
+            }),
+    );
+}
+
+/// Upgrade the template file at `path` on disk.
+///
+/// Top-level renames and the new `version:` key are applied to the text so
+/// comments, anchors and layout survive; the `sources` entry is rewritten
+/// only if one of its keys was renamed. Returns `None` (and leaves the file
+/// untouched) if it is already current.
+pub fn migrate_template_file(path: &Path) -> crate::Result<Option<MigrationReport>> {
+    let io_err = |source| crate::MailnirError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let content = std::fs::read_to_string(path).map_err(io_err)?;
+    let Some((migrated, report)) = migrate_content(&content, path)? else {
+        return Ok(None);
+    };
+    std::fs::write(path, migrated).map_err(io_err)?;
+    Ok(Some(report))
+}
+
+fn migrate_content(content: &str, path: &Path) -> crate::Result<Option<(String, MigrationReport)>> {
+    let yaml_err = |source| crate::MailnirError::TemplateParseYaml {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut expected: Value = serde_yaml::from_str(content).map_err(yaml_err)?;
+    let Some(report) = migrate(&mut expected)? else {
+        return Ok(None);
+    };
+
+    let preserved = preserving_migrate(content, &expected, &report)
+        .filter(|text| serde_yaml::from_str::<Value>(text).ok().as_ref() == Some(&expected));
+    let text = match preserved {
+        Some(text) => text,
+        None => serde_yaml::to_string(&expected).map_err(yaml_err)?,
+    };
+    Ok(Some((text, report)))
+}
+
+fn preserving_migrate(content: &str, expected: &Value, report: &MigrationReport) -> Option<String> {
+    let crlf = content.contains("\r\n");
+    let mut lines: Vec<String> = content
+        .replace("\r\n", "\n")
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect();
+    let entries = find_entries(&lines)?;
+
+    let mut sources_changed = false;
+    for change in &report.changes {
+        let MigrationChange::RenameKey {
+            namespace,
+            from,
+            to,
+        } = change;
+        if namespace.is_some() {
+            sources_changed = true;
+            continue;
+        }
+        let entry = entries.iter().find(|e| &e.key == from)?;
+        let line = &lines[entry.start];
+        let (_, colon) = top_level_key(line)?;
+        lines[entry.start] = format!("{to}:{}", &line[colon..]);
+    }
+
+    // `version:` goes above the first key, below any leading comments.
+    let version_line = format!("version: {CURRENT_VERSION}\n");
+    if let Some(entry) = entries.iter().find(|e| e.key == "version") {
+        lines.splice(entry.start..entry.end, [version_line]);
+    } else {
+        let first = entries.first().map_or(lines.len(), |e| e.start);
+        lines.insert(first, version_line);
+    }
+
+    let mut text = lines.concat();
+    if sources_changed {
+        let sources = expected.get("sources")?.clone();
+        text = edit_top_level_keys(&text, &[("sources", Some(sources))]).ok()?;
+    }
+    Some(if crlf {
+        text.replace('\n', "\r\n")
+    } else {
+        text
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated(content: &str) -> (String, MigrationReport) {
+        migrate_content(content, Path::new("<string>"))
+            .unwrap()
+            .expect("document should need migration")
+    }
+
+    #[test]
+    fn test_current_document_untouched() {
+        let mut doc: Value = serde_yaml::from_str("version: 2\nto: a\n").unwrap();
+        assert!(migrate(&mut doc).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unversioned_document_is_v1() {
+        let (text, report) = migrated("# header\nsources:\n  p: {primary: true}\nto: a\n");
+        assert_eq!(report.from_version, 1);
+        assert_eq!(report.to_version, CURRENT_VERSION);
+        assert!(report.changes.is_empty());
+        assert_eq!(
+            text,
+            "# header\nversion: 2\nsources:\n  p: {primary: true}\nto: a\n"
+        );
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let mut doc: Value = serde_yaml::from_str("version: 9\n").unwrap();
+        assert!(matches!(
+            migrate(&mut doc),
+            Err(crate::MailnirError::UnsupportedTemplateVersion {
+                version: 9,
+                supported: CURRENT_VERSION
+            })
+        ));
+    }
+
+    #[test]
+    fn test_underscore_keys_renamed_keeping_anchor() {
+        let content = "_base_body: &b |\n  Hi\n# keep me\nto: a\nbody: *b\n";
+        let (text, report) = migrated(content);
+        assert_eq!(
+            report.changes,
+            vec![MigrationChange::RenameKey {
+                namespace: None,
+                from: "_base_body".into(),
+                to: "x-base-body".into(),
+            }]
+        );
+        assert_eq!(
+            text,
+            "version: 2\nx-base-body: &b |\n  Hi\n# keep me\nto: a\nbody: *b\n"
+        );
+    }
+
+    #[test]
+    fn test_source_keys_renamed() {
+        let content = "sources:\n  p:\n    primary: true\n    _note: hi\nto: a\n";
+        let (text, report) = migrated(content);
+        assert_eq!(
+            report.changes[0].to_string(),
+            "renamed 'sources.p._note' to 'sources.p.x-note'"
+        );
+        let doc: Value = serde_yaml::from_str(&text).unwrap();
+        assert_eq!(doc["sources"]["p"]["x-note"], Value::from("hi"));
+        assert_eq!(doc["version"], Value::from(2));
+    }
+
+    #[test]
+    fn test_existing_extension_key_not_clobbered() {
+        let mut doc: Value = serde_yaml::from_str("_a: 1\nx-a: 2\n").unwrap();
+        let report = migrate(&mut doc).unwrap().unwrap();
+        assert!(report.changes.is_empty());
+        assert_eq!(doc["_a"], Value::from(1));
+    }
+}
@@ -1,9 +1,24 @@
+mod form;
 mod infer;
+mod keys;
+mod migrate;
 mod parse;
+mod schema;
+mod span;
 mod types;
 mod validate;
+mod yaml_edit;
 
-pub use infer::infer_form_fields;
-pub use parse::{parse_template, parse_template_str};
-pub use types::{BodyFormat, SourceConfig, Template};
+pub use infer::{form_schema, infer_form_fields, template_variables, VariableRef, VariableUsage};
+pub use migrate::{migrate_template_file, MigrationChange, MigrationReport, CURRENT_VERSION};
+pub use parse::{parse_template, parse_template_str, parse_template_with_report};
+pub use schema::json_schema;
+pub use span::{FieldSpan, FieldSpans, TextPosition};
+pub(crate) use types::{select_locale, ENV_NAMESPACE, VARS_NAMESPACE};
+pub use types::{
+    BodyFormat, ContainerOptions, CssMode, DocumentOptions, ExpectedEmail, FormField,
+    FormFieldType, JoinClause, JoinCondition, JoinOp, JoinOperand, LinkStyle, Localized,
+    MarkdownOptions, PlainTextOptions, SourceConfig, Template, TemplateTest,
+};
 pub use validate::validate_sources;
+pub use yaml_edit::{edit_nested_keys, edit_top_level_keys, rewrite_top_level_keys};
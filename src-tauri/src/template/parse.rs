@@ -1,23 +1,49 @@
 use std::path::Path;
 
+use crate::template::keys::check_unknown_keys;
+use crate::template::migrate::{migrate, MigrationReport, CURRENT_VERSION};
+use crate::template::span::field_spans;
 use crate::template::types::Template;
 
 pub fn parse_template(path: &Path) -> crate::Result<Template> {
+    parse_template_with_report(path).map(|(template, _)| template)
+}
+
+pub fn parse_template_str(content: &str) -> crate::Result<Template> {
+    parse_content(content, Path::new("<string>")).map(|(template, _)| template)
+}
+
+/// Like [`parse_template`], also reporting how an older document was upgraded.
+pub fn parse_template_with_report(
+    path: &Path,
+) -> crate::Result<(Template, Option<MigrationReport>)> {
     let content = std::fs::read_to_string(path).map_err(|source| crate::MailnirError::Io {
         path: path.to_path_buf(),
         source,
     })?;
-    serde_yaml::from_str(&content).map_err(|source| crate::MailnirError::TemplateParseYaml {
-        path: path.to_path_buf(),
-        source,
-    })
+    parse_content(&content, path)
 }
 
-pub fn parse_template_str(content: &str) -> crate::Result<Template> {
-    serde_yaml::from_str(content).map_err(|source| crate::MailnirError::TemplateParseYaml {
-        path: std::path::PathBuf::from("<string>"),
+fn parse_content(content: &str, path: &Path) -> crate::Result<(Template, Option<MigrationReport>)> {
+    let yaml_err = |source| crate::MailnirError::TemplateParseYaml {
+        path: path.to_path_buf(),
         source,
-    })
+    };
+    // Check keys on the raw document first: a misspelled required key is
+    // better reported as unknown than as missing.
+    let mut doc: serde_yaml::Value = serde_yaml::from_str(content).map_err(yaml_err)?;
+    let report = migrate(&mut doc)?;
+    check_unknown_keys(&doc, content)?;
+
+    // Deserialize from the text when it is unchanged apart from `version`, so
+    // errors keep their line and column.
+    let mut template: Template = match &report {
+        Some(r) if !r.changes.is_empty() => serde_yaml::from_value(doc).map_err(yaml_err)?,
+        _ => serde_yaml::from_str(content).map_err(yaml_err)?,
+    };
+    template.version = Some(CURRENT_VERSION);
+    template.spans = field_spans(content);
+    Ok((template, report))
 }
 
 #[cfg(test)]
@@ -35,7 +61,7 @@ mod tests {
     fn test_parse_minimal() {
         let t = parse_template(&fixtures_dir().join("minimal.mailnir.yml")).unwrap();
         assert_eq!(t.to, "{{primary.email}}");
-        assert_eq!(t.subject, "Hello {{primary.name}}");
+        assert_eq!(t.subject, "Hello {{primary.name}}".into());
         assert!(t.cc.is_none());
         assert!(t.bcc.is_none());
         assert!(t.attachments.is_none());
@@ -109,4 +135,40 @@ mod tests {
         let result = parse_template_str("sources:\n  p: {primary: true}\nsubject: b\nbody: c");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_upgrades_v1_document() {
+        let t = parse_template_str(
+            "_greeting: &g Hello\nsources:\n  p: {primary: true}\nto: a\nsubject: *g\nbody: c",
+        )
+        .unwrap();
+        assert_eq!(t.version, Some(crate::template::CURRENT_VERSION));
+        assert_eq!(t.subject, "Hello".into());
+    }
+
+    #[test]
+    fn test_parse_rejects_newer_version() {
+        let result = parse_template_str(
+            "version: 99\nsources:\n  p: {primary: true}\nto: a\nsubject: b\nbody: c",
+        );
+        assert!(matches!(
+            result,
+            Err(crate::MailnirError::UnsupportedTemplateVersion { version: 99, .. })
+        ));
+    }
+
+    #[test]
+    fn test_equality_ignores_layout() {
+        let a = parse_template_str("sources:\n  p: {primary: true}\nto: a\nsubject: b\nbody: c")
+            .unwrap();
+        let b = parse_template_str(
+            "sources:\n  p:\n    primary: true\nto: a\nsubject: 'b'\nbody: |-\n  c\n",
+        )
+        .unwrap();
+        assert_ne!(a.spans, b.spans);
+        assert_eq!(a, b);
+        let c = parse_template_str("sources:\n  p: {primary: true}\nto: a\nsubject: b\nbody: d")
+            .unwrap();
+        assert_ne!(a, c);
+    }
 }
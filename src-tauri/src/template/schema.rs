@@ -0,0 +1,299 @@
+use serde_json::{json, Value};
+
+use super::migrate::CURRENT_VERSION;
+use super::types::{FormFieldType, JoinOp};
+
+/// JSON Schema (draft 2020-12) for `.mailnir.yml` documents of
+/// `CURRENT_VERSION`, for editors that validate YAML against a schema.
+pub fn json_schema() -> Value {
+    let ops: Vec<&str> = JoinOp::ALL.iter().map(|op| op.name()).collect();
+    let field_types: Vec<String> = [
+        FormFieldType::Text,
+        FormFieldType::Multiline,
+        FormFieldType::Email,
+        FormFieldType::Number,
+        FormFieldType::Date,
+        FormFieldType::Select,
+        FormFieldType::Checkbox,
+        FormFieldType::List,
+    ]
+    .iter()
+    .filter_map(|t| serde_json::to_value(t).ok()?.as_str().map(str::to_string))
+    .collect();
+    let text = |description: &str| json!({ "type": "string", "description": description });
+    let flag = |description: &str| json!({ "type": "boolean", "description": description });
+    let extensions = json!({ "^x-": {} });
+    let localized = |description: &str| {
+        json!({
+            "description": description,
+            "oneOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "description": "Variant per locale tag.",
+                    "minProperties": 1,
+                    "additionalProperties": { "type": "string" }
+                }
+            ]
+        })
+    };
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("https://mailnir.app/schema/template-v{CURRENT_VERSION}.json"),
+        "title": "Mailnir template",
+        "type": "object",
+        "required": ["sources", "to", "subject", "body"],
+        "properties": {
+            "version": {
+                "type": "integer",
+                "minimum": 1,
+                "maximum": CURRENT_VERSION,
+                "description": "Template format version; omitted means 1."
+            },
+            "sources": {
+                "type": "object",
+                "description": "Data sources by namespace.",
+                "additionalProperties": { "$ref": "#/$defs/source" }
+            },
+            "vars": {
+                "type": "object",
+                "description": "Static values available as {{vars.name}}."
+            },
+            "to": text("Recipient address (Handlebars)."),
+            "cc": text("Cc addresses (Handlebars)."),
+            "bcc": text("Bcc addresses (Handlebars)."),
+            "subject": localized("Subject line (Handlebars)."),
+            "body": localized("Message body (Handlebars)."),
+            "text_body": localized("Plain-text part (Handlebars); replaces the text derived from the HTML body."),
+            "preheader": localized("Preview text shown after the subject (Handlebars); hidden in the HTML body."),
+            "attachments": localized("Attachment paths, one per line (Handlebars)."),
+            "locale_field": {
+                "type": "string",
+                "pattern": "^[^.]+\\..+$",
+                "description": "namespace.field holding each entry's locale."
+            },
+            "fallback_locale": text("Variant used when an entry's locale has none."),
+            "translations": {
+                "type": "object",
+                "description": "Catalog for {{t \"id\"}} per locale: a .ftl or .po path, relative to the template.",
+                "additionalProperties": { "type": "string", "pattern": "\\.(ftl|po)$" }
+            },
+            "tests": {
+                "type": "array",
+                "description": "Snapshot tests: inline sources and expected output.",
+                "items": { "$ref": "#/$defs/test" }
+            },
+            "body_format": { "enum": ["markdown", "html", "text"] },
+            "escape_html": flag("HTML-escape {{value}} in HTML and Markdown bodies ({{{value}}} never is). Default true."),
+            "markdown": {
+                "type": "object",
+                "description": "Markdown extensions. strikethrough, tables and autolink default to true, the rest to false.",
+                "properties": {
+                    "strikethrough": flag("~~text~~."),
+                    "tables": flag("Pipe tables."),
+                    "autolink": flag("Bare URLs and addresses become links."),
+                    "footnotes": flag("[^1] references with notes at the end."),
+                    "tasklists": flag("- [ ] and - [x] items as checkboxes."),
+                    "header_ids": flag("id attributes on headings."),
+                    "smart_punctuation": flag("Curly quotes, dashes and ellipses."),
+                    "description_lists": flag("Term / : details as <dl>."),
+                    "superscript": flag("^text^."),
+                    "hard_breaks": flag("Every line break becomes <br>."),
+                    "safe": flag("Omit raw HTML and unsafe links.")
+                },
+                "additionalProperties": false
+            },
+            "plain_text": {
+                "type": "object",
+                "description": "How HTML bodies become the plain-text alternative.",
+                "properties": {
+                    "wrap": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "description": "Line width; 0 turns wrapping off. Default 78."
+                    },
+                    "links": {
+                        "enum": ["inline", "footnotes"],
+                        "description": "Links as \"text (url)\" or as numbered footnotes."
+                    }
+                },
+                "additionalProperties": false
+            },
+            "document": {
+                "type": "object",
+                "description": "The HTML document wrapped around HTML bodies.",
+                "properties": {
+                    "enabled": {
+                        "type": "boolean",
+                        "description": "false sends the body as a bare fragment. Default true."
+                    },
+                    "lang": text("<html lang>; defaults to the entry's locale."),
+                    "container": {
+                        "type": "object",
+                        "description": "Centered table the body is placed in.",
+                        "properties": {
+                            "width": {
+                                "type": "integer",
+                                "minimum": 1,
+                                "description": "Width in pixels. Default 600."
+                            },
+                            "class": text("Class of the container table."),
+                            "style": text("Inline style of the container cell.")
+                        },
+                        "additionalProperties": false
+                    }
+                },
+                "additionalProperties": false
+            },
+            "stylesheet": text("Path of a CSS file, relative to the template."),
+            "style": text("Inline CSS, applied after the stylesheet."),
+            "css_mode": {
+                "enum": ["inline", "keep_leftovers", "none"],
+                "description": "inline: inline and drop the rest (default); keep_leftovers: keep @media, :hover etc. in a <style> element; none: no inlining."
+            }
+        },
+        "patternProperties": extensions,
+        "additionalProperties": false,
+        "$defs": {
+            "source": {
+                "type": "object",
+                "properties": {
+                    "primary": { "type": "boolean" },
+                    "join": {
+                        "type": "object",
+                        "additionalProperties": { "$ref": "#/$defs/join_condition" }
+                    },
+                    "many": { "type": "boolean" },
+                    "form": { "type": "boolean" },
+                    "fields": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/form_field" }
+                    },
+                    "computed": {
+                        "type": "object",
+                        "description": "Fields added to every row (Handlebars over the row and vars).",
+                        "additionalProperties": { "type": "string" }
+                    }
+                },
+                "patternProperties": extensions,
+                "additionalProperties": false
+            },
+            "join_condition": {
+                "oneOf": [
+                    { "$ref": "#/$defs/field_ref" },
+                    {
+                        "type": "object",
+                        "minProperties": 1,
+                        "propertyNames": { "enum": ops },
+                        "additionalProperties": {
+                            "oneOf": [
+                                { "$ref": "#/$defs/field_ref" },
+                                {
+                                    "type": "array",
+                                    "items": { "$ref": "#/$defs/field_ref" },
+                                    "minItems": 2,
+                                    "maxItems": 2
+                                }
+                            ]
+                        }
+                    }
+                ]
+            },
+            "test": {
+                "type": "object",
+                "required": ["name", "sources"],
+                "properties": {
+                    "name": { "type": "string" },
+                    "sources": {
+                        "type": "object",
+                        "description": "Data per namespace: a list of rows or a single object."
+                    },
+                    "env": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" }
+                    },
+                    "expect": {
+                        "type": "array",
+                        "description": "One entry per rendered email, in primary row order.",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "to": { "type": "string" },
+                                "subject": { "type": "string" },
+                                "text_body": { "type": "string" },
+                                "html_body": { "type": "string" }
+                            },
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "additionalProperties": false
+            },
+            "field_ref": {
+                "type": "string",
+                "pattern": "^[^.]+\\..+$",
+                "description": "namespace.field"
+            },
+            "form_field": {
+                "type": "object",
+                "required": ["name"],
+                "properties": {
+                    "name": { "type": "string" },
+                    "type": { "enum": field_types },
+                    "label": { "type": "string" },
+                    "default": {},
+                    "required": { "type": "boolean" },
+                    "pattern": { "type": "string" },
+                    "options": { "type": "array", "items": { "type": "string" } }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::keys::struct_fields;
+    use crate::template::types::{SourceConfig, Template};
+
+    fn property_names(schema: &Value) -> Vec<String> {
+        let mut names: Vec<String> = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn sorted(fields: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_schema_covers_template_keys() {
+        let schema = json_schema();
+        assert_eq!(property_names(&schema), sorted(struct_fields::<Template>()));
+    }
+
+    #[test]
+    fn test_schema_covers_source_keys() {
+        let schema = json_schema();
+        assert_eq!(
+            property_names(&schema["$defs"]["source"]),
+            sorted(struct_fields::<SourceConfig>())
+        );
+    }
+
+    #[test]
+    fn test_schema_lists_every_join_operator() {
+        let schema = json_schema();
+        let ops = &schema["$defs"]["join_condition"]["oneOf"][1]["propertyNames"]["enum"];
+        assert_eq!(ops.as_array().unwrap().len(), JoinOp::ALL.len());
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+
+use super::yaml_edit::{find_entries, top_level_key};
+
+/// A 1-based line and column; columns count chars, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where a top-level field's value starts in the template file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSpan {
+    /// Position of the value's first character (inside any opening quote).
+    pub start: TextPosition,
+    /// `|`/`>` block scalar: every line of the value is its own file line,
+    /// indented to `start.column`.
+    pub block: bool,
+}
+
+impl FieldSpan {
+    /// Map a position within the field's string to a position in the file.
+    ///
+    /// Exact for block scalars and single-line values. For flow scalars that
+    /// span several lines, lines are assumed to correspond one to one.
+    pub fn locate(&self, pos: TextPosition) -> TextPosition {
+        let line = self.start.line + pos.line.saturating_sub(1);
+        if self.block || pos.line <= 1 {
+            TextPosition {
+                line,
+                column: self.start.column + pos.column.saturating_sub(1),
+            }
+        } else {
+            TextPosition {
+                line,
+                column: pos.column,
+            }
+        }
+    }
+}
+
+/// Value spans of a template's top-level keys, by key, and of the values of
+/// mapping fields such as localized variants, by `key.variant`.
+///
+/// Layout metadata only: `Template`'s equality ignores it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldSpans(HashMap<String, FieldSpan>);
+
+impl FieldSpans {
+    pub fn get(&self, field: &str) -> Option<&FieldSpan> {
+        self.0.get(field)
+    }
+
+    /// Forget a field's spans, e.g. after its text was replaced in memory.
+    pub fn remove(&mut self, field: &str) {
+        let prefix = format!("{field}.");
+        self.0
+            .retain(|key, _| key != field && !key.starts_with(&prefix));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Locate the value of every top-level key of a block-mapping document.
+///
+/// Returns an empty map for layouts the line scanner does not understand
+/// (flow mappings, multiple documents); positions are then simply unknown.
+pub(crate) fn field_spans(content: &str) -> FieldSpans {
+    let lines: Vec<String> = content
+        .replace("\r\n", "\n")
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect();
+    let Some(entries) = find_entries(&lines) else {
+        return FieldSpans::default();
+    };
+
+    let mut spans = HashMap::new();
+    for entry in entries {
+        let first = lines[entry.start].trim_end_matches('\n');
+        let Some((_, after_colon)) = top_level_key(first) else {
+            continue;
+        };
+        let Some(span) = value_span(&lines, entry.start, entry.end, after_colon) else {
+            continue;
+        };
+        // Values of a mapping (e.g. localized variants) as `key.variant`.
+        for (variant, span) in mapping_spans(&lines, entry.start, entry.end, span) {
+            spans.insert(format!("{}.{variant}", entry.key), span);
+        }
+        spans.insert(entry.key, span);
+    }
+    FieldSpans(spans)
+}
+
+/// Skip whitespace and any anchor or tag before a value; returns its offset.
+fn skip_to_value(line: &str, mut offset: usize) -> usize {
+    loop {
+        let rest = &line[offset..];
+        let trimmed = rest.trim_start();
+        offset += rest.len() - trimmed.len();
+        match trimmed.chars().next() {
+            Some('&') | Some('!') => {
+                offset += trimmed.find([' ', '\t']).unwrap_or(trimmed.len());
+            }
+            _ => return offset,
+        }
+    }
+}
+
+fn column_of(line: &str, byte: usize) -> usize {
+    line[..byte].chars().count() + 1
+}
+
+/// Span of the value whose key is on line `start`, `after_colon` bytes in;
+/// the value's lines end before `end`.
+fn value_span(lines: &[String], start: usize, end: usize, after_colon: usize) -> Option<FieldSpan> {
+    let first = lines[start].trim_end_matches('\n');
+    let offset = skip_to_value(first, after_colon);
+    let rest = &first[offset..];
+    let span = match rest.chars().next() {
+        Some('|') | Some('>') => {
+            let header_line = start + 1;
+            let explicit = rest
+                .split([' ', '\t'])
+                .next()
+                .and_then(|h| h.chars().find_map(|c| c.to_digit(10)))
+                .map(|d| d as usize);
+            let detected = lines[start + 1..end]
+                .iter()
+                .find(|l| !l.trim().is_empty())
+                .map(|l| l.len() - l.trim_start_matches(' ').len());
+            let indent = explicit.or(detected).unwrap_or(0);
+            FieldSpan {
+                start: TextPosition {
+                    line: header_line + 1,
+                    column: indent + 1,
+                },
+                block: true,
+            }
+        }
+        Some('\'') | Some('"') => FieldSpan {
+            start: TextPosition {
+                line: start + 1,
+                column: column_of(first, offset) + 1,
+            },
+            block: false,
+        },
+        Some('#') | None => {
+            // Value starts on a following line.
+            let (index, line) = lines[start + 1..end]
+                .iter()
+                .enumerate()
+                .find(|(_, l)| !l.trim().is_empty())?;
+            let indent = line.len() - line.trim_start().len();
+            FieldSpan {
+                start: TextPosition {
+                    line: start + index + 2,
+                    column: column_of(line, indent),
+                },
+                block: false,
+            }
+        }
+        Some(_) => FieldSpan {
+            start: TextPosition {
+                line: start + 1,
+                column: column_of(first, offset),
+            },
+            block: false,
+        },
+    };
+    Some(span)
+}
+
+/// Spans of the values of a mapping whose key is on line `start`, by key:
+/// a block mapping on the following lines or a one-line flow mapping.
+fn mapping_spans(
+    lines: &[String],
+    start: usize,
+    end: usize,
+    span: FieldSpan,
+) -> Vec<(String, FieldSpan)> {
+    if span.block {
+        return Vec::new();
+    }
+    let line = lines[span.start.line - 1].trim_end_matches('\n');
+    let value_at = line
+        .char_indices()
+        .nth(span.start.column - 1)
+        .map_or(line.len(), |(i, _)| i);
+    if span.start.line - 1 == start {
+        if !line[value_at..].starts_with('{') {
+            return Vec::new();
+        }
+        return flow_values(line, value_at)
+            .into_iter()
+            .map(|(key, at)| {
+                let quoted = line[at..].starts_with(['\'', '"']);
+                let span = FieldSpan {
+                    start: TextPosition {
+                        line: start + 1,
+                        column: column_of(line, at) + usize::from(quoted),
+                    },
+                    block: false,
+                };
+                (key, span)
+            })
+            .collect();
+    }
+
+    // Block mapping: keys at the indent of the first value line.
+    let indent_of = |l: &str| l.len() - l.trim_start().len();
+    let indent = value_at;
+    let mut spans = Vec::new();
+    for i in span.start.line - 1..end {
+        let line = lines[i].trim_end_matches('\n');
+        if line.trim().is_empty() || indent_of(line) != indent || line[indent..].starts_with('#') {
+            continue;
+        }
+        let Some((key, after_colon)) = top_level_key(&line[indent..]) else {
+            continue;
+        };
+        let child_end = (i + 1..end)
+            .find(|&j| !lines[j].trim().is_empty() && indent_of(&lines[j]) <= indent)
+            .unwrap_or(end);
+        if let Some(span) = value_span(lines, i, child_end, indent + after_colon) {
+            spans.push((key, span));
+        }
+    }
+    spans
+}
+
+/// Keys of a one-line flow mapping opening at `open`, with the byte offset
+/// of each value.
+fn flow_values(line: &str, open: usize) -> Vec<(String, usize)> {
+    let mut values = Vec::new();
+    let mut at = open + 1;
+    loop {
+        let rest = &line[at..];
+        let key_at = at + rest.len() - rest.trim_start().len();
+        let Some((key, after_colon)) = top_level_key(&line[key_at..]) else {
+            break;
+        };
+        let value_at = skip_to_value(line, key_at + after_colon);
+        values.push((key, value_at));
+        let Some(comma) = flow_value_end(&line[value_at..]) else {
+            break;
+        };
+        at = value_at + comma + 1;
+    }
+    values
+}
+
+/// Offset of the `,` after a flow value, or `None` if the mapping ends.
+fn flow_value_end(value: &str) -> Option<usize> {
+    let mut chars = value.char_indices().peekable();
+    // Skip a quoted scalar first; quotes inside plain scalars are literal.
+    match chars.peek().map(|(_, c)| *c) {
+        Some('\'') => {
+            chars.next();
+            while let Some((_, c)) = chars.next() {
+                if c == '\'' && chars.next_if(|(_, n)| *n == '\'').is_none() {
+                    break;
+                }
+            }
+        }
+        Some('"') => {
+            chars.next();
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => break,
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+    let mut depth = 0;
+    for (i, c) in chars {
+        match c {
+            '{' | '[' => depth += 1,
+            '}' | ']' if depth == 0 => return None,
+            '}' | ']' => depth -= 1,
+            ',' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Position of `key` in the file: a top-level key when `namespace` is `None`,
+/// otherwise a key of `sources.<namespace>` (block or flow style).
+pub(crate) fn key_position(
+    content: &str,
+    namespace: Option<&str>,
+    key: &str,
+) -> Option<TextPosition> {
+    let lines: Vec<String> = content
+        .replace("\r\n", "\n")
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect();
+    let entries = find_entries(&lines)?;
+    let wanted = namespace.unwrap_or(key);
+    let parent = if namespace.is_some() { "sources" } else { key };
+    let entry = entries.iter().find(|e| e.key == parent)?;
+    if namespace.is_none() {
+        return Some(TextPosition {
+            line: entry.start + 1,
+            column: 1,
+        });
+    }
+
+    let indent_of = |l: &str| l.len() - l.trim_start().len();
+    let is_key_line = |l: &str, k: &str| {
+        let t = l.trim_start();
+        t.strip_prefix(k)
+            .is_some_and(|r| r.trim_start().starts_with(':'))
+    };
+    let block = &lines[entry.start + 1..entry.end];
+    let (ns_index, ns_line) = block
+        .iter()
+        .enumerate()
+        .find(|(_, l)| is_key_line(l, wanted))?;
+    let ns_indent = indent_of(ns_line);
+    let file_line = |index: usize| entry.start + 2 + index;
+
+    // Flow style: `ns: {primary: true, key: ...}` on one line.
+    if let Some(brace) = ns_line.find('{') {
+        let needle = format!("{key}:");
+        let mut from = brace;
+        while let Some(found) = ns_line[from..].find(&needle) {
+            let at = from + found;
+            if ns_line[..at].trim_end().ends_with(['{', ',']) {
+                return Some(TextPosition {
+                    line: file_line(ns_index),
+                    column: ns_line[..at].chars().count() + 1,
+                });
+            }
+            from = at + needle.len();
+        }
+        return None;
+    }
+
+    block[ns_index + 1..]
+        .iter()
+        .enumerate()
+        .take_while(|(_, l)| l.trim().is_empty() || indent_of(l) > ns_indent)
+        .find(|(_, l)| is_key_line(l, key))
+        .map(|(i, l)| TextPosition {
+            line: file_line(ns_index + 1 + i),
+            column: indent_of(l) + 1,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: usize, column: usize) -> TextPosition {
+        TextPosition { line, column }
+    }
+
+    const DOC: &str = "\
+sources:
+  p: {primary: true}
+to: '{{p.email}}'
+subject: &s Hëllo {{p.name}}
+body: |
+  line one
+  {{p.missing}}
+";
+
+    #[test]
+    fn test_spans_for_inline_and_block_values() {
+        let spans = field_spans(DOC);
+        assert_eq!(spans.get("to").unwrap().start, pos(3, 6));
+        assert_eq!(spans.get("subject").unwrap().start, pos(4, 13));
+        assert_eq!(
+            *spans.get("body").unwrap(),
+            FieldSpan {
+                start: pos(6, 3),
+                block: true
+            }
+        );
+        assert_eq!(spans.get("sources").unwrap().start, pos(2, 3));
+    }
+
+    #[test]
+    fn test_locate_maps_field_positions_to_file() {
+        let spans = field_spans(DOC);
+        // `{{p.missing}}` is on line 2, column 1 of the body.
+        assert_eq!(spans.get("body").unwrap().locate(pos(2, 1)), pos(7, 3));
+        // `{{p.name}}` follows "Hëllo " in the subject.
+        assert_eq!(spans.get("subject").unwrap().locate(pos(1, 7)), pos(4, 19));
+    }
+
+    #[test]
+    fn test_spans_for_mapping_values() {
+        let doc = "\
+subject: {de: 'Hallo {{p.nmae}}', en: \"Hi, {{p.name}}\", fr: Salut}
+body:
+  de: |
+    Hallo
+  en: Hi
+";
+        let spans = field_spans(doc);
+        assert_eq!(spans.get("subject.de").unwrap().start, pos(1, 16));
+        assert_eq!(spans.get("subject.en").unwrap().start, pos(1, 40));
+        assert_eq!(spans.get("subject.fr").unwrap().start, pos(1, 61));
+        assert_eq!(
+            *spans.get("body.de").unwrap(),
+            FieldSpan {
+                start: pos(4, 5),
+                block: true
+            }
+        );
+        assert_eq!(spans.get("body.en").unwrap().start, pos(5, 7));
+        assert!(spans.get("to.de").is_none());
+    }
+
+    #[test]
+    fn test_flow_document_has_no_spans() {
+        assert!(field_spans("{to: a, subject: b, body: c}").is_empty());
+    }
+}
@@ -1,4 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use super::span::FieldSpans;
+
+/// Context key of the template's `vars:` map; no source may use this name.
+pub(crate) const VARS_NAMESPACE: &str = "vars";
+
+/// Context key of the environment variables a template references.
+pub(crate) const ENV_NAMESPACE: &str = "env";
 
 #[derive(Debug, Clone, PartialEq, Default, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "lowercase")]
@@ -9,24 +18,575 @@ pub enum BodyFormat {
     Text,
 }
 
+/// Markdown extensions for `body_format: markdown`. Unset flags keep their
+/// defaults: strikethrough, tables and autolinks on, everything else off.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarkdownOptions {
+    /// `~~text~~`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    /// GitHub-style pipe tables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tables: Option<bool>,
+    /// Bare URLs and addresses become links.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autolink: Option<bool>,
+    /// `[^1]` references with the notes collected at the end.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footnotes: Option<bool>,
+    /// `- [ ]` and `- [x]` list items as checkboxes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tasklists: Option<bool>,
+    /// `id` attributes on headings, derived from their text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_ids: Option<bool>,
+    /// Curly quotes, en/em dashes and ellipses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smart_punctuation: Option<bool>,
+    /// `Term` followed by `: details` lines as `<dl>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description_lists: Option<bool>,
+    /// `^text^`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub superscript: Option<bool>,
+    /// Every line break in a paragraph becomes `<br>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hard_breaks: Option<bool>,
+    /// Omit raw HTML and drop `javascript:` style links.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe: Option<bool>,
+}
+
+/// What happens to the template's CSS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CssMode {
+    /// Inline what can be inlined and drop the rest.
+    #[default]
+    Inline,
+    /// Inline what can be inlined; keep at-rules such as `@media` and rules
+    /// for `:hover` or pseudo-elements in a `<style>` element in the head.
+    KeepLeftovers,
+    /// Put the whole stylesheet in a `<style>` element without inlining.
+    None,
+}
+
+/// How links appear in the plain-text alternative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// `text (url)`.
+    #[default]
+    Inline,
+    /// `text[1]`, with the numbered URLs listed at the end.
+    Footnotes,
+}
+
+/// Settings for deriving the plain-text alternative from an HTML body.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlainTextOptions {
+    /// Line width to wrap at; `0` turns wrapping off. Defaults to 78.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrap: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<LinkStyle>,
+}
+
+/// Settings for the HTML document wrapped around rendered HTML bodies.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DocumentOptions {
+    /// `false` sends the body as a bare fragment. Defaults to `true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// `<html lang>`; defaults to the entry's locale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Centered table the body is placed in; none when absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<ContainerOptions>,
+}
+
+/// The outer container of a wrapped document.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContainerOptions {
+    /// Width in pixels. Defaults to 600.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Inline style of the container cell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+}
+
+/// Comparison applied between a secondary row's join key and referenced values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinOp {
+    /// Row value equals the referenced value.
+    Eq,
+    /// Row value is an element of the referenced array.
+    In,
+    /// Row value is an array containing the referenced value.
+    Contains,
+    /// Row value starts with the referenced value.
+    StartsWith,
+    /// Row value is greater than or equal to the referenced value.
+    Gte,
+    /// Row value is less than or equal to the referenced value.
+    Lte,
+    /// Row value lies within two referenced values, inclusive.
+    Between,
+}
+
+impl JoinOp {
+    pub const ALL: [JoinOp; 7] = [
+        JoinOp::Eq,
+        JoinOp::In,
+        JoinOp::Contains,
+        JoinOp::StartsWith,
+        JoinOp::Gte,
+        JoinOp::Lte,
+        JoinOp::Between,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            JoinOp::Eq => "eq",
+            JoinOp::In => "in",
+            JoinOp::Contains => "contains",
+            JoinOp::StartsWith => "starts_with",
+            JoinOp::Gte => "gte",
+            JoinOp::Lte => "lte",
+            JoinOp::Between => "between",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+}
+
+/// Operand of a join operator: one `namespace.field` ref, or a list for `between`.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum JoinOperand {
+    Ref(String),
+    Refs(Vec<String>),
+}
+
+/// Right-hand side of one `join` entry.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum JoinCondition {
+    /// `key: namespace.field` — shorthand for equality.
+    Equals(String),
+    /// `key: {op: operand, ...}` — every listed operator must hold.
+    Operators(BTreeMap<String, JoinOperand>),
+}
+
+/// One parsed predicate of a join condition: `row[key] <op> refs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinClause<'a> {
+    pub op: JoinOp,
+    /// `namespace.field` refs, unvalidated. Two for `between`, one otherwise.
+    pub refs: Vec<&'a str>,
+}
+
+impl JoinCondition {
+    /// Parse into clauses, or explain why the operators are malformed.
+    pub fn clauses(&self) -> Result<Vec<JoinClause<'_>>, String> {
+        let ops = match self {
+            JoinCondition::Equals(r) => {
+                return Ok(vec![JoinClause {
+                    op: JoinOp::Eq,
+                    refs: vec![r.as_str()],
+                }])
+            }
+            JoinCondition::Operators(ops) => ops,
+        };
+        if ops.is_empty() {
+            return Err("no operator given".into());
+        }
+        ops.iter()
+            .map(|(name, operand)| {
+                let op = JoinOp::from_name(name).ok_or_else(|| {
+                    let known: Vec<&str> = JoinOp::ALL.iter().map(|op| op.name()).collect();
+                    format!(
+                        "unknown operator '{name}' (expected one of: {})",
+                        known.join(", ")
+                    )
+                })?;
+                let refs = match (op, operand) {
+                    (JoinOp::Between, JoinOperand::Refs(refs)) if refs.len() == 2 => {
+                        refs.iter().map(String::as_str).collect()
+                    }
+                    (JoinOp::Between, _) => {
+                        return Err("'between' takes a list of two namespace.field refs".into())
+                    }
+                    (_, JoinOperand::Ref(r)) => vec![r.as_str()],
+                    (_, JoinOperand::Refs(_)) => {
+                        return Err(format!("'{name}' takes a single namespace.field ref"))
+                    }
+                };
+                Ok(JoinClause { op, refs })
+            })
+            .collect()
+    }
+
+    /// Every ref string in the condition, whether or not the operators are valid.
+    pub fn refs(&self) -> Vec<&str> {
+        match self {
+            JoinCondition::Equals(r) => vec![r.as_str()],
+            JoinCondition::Operators(ops) => ops
+                .values()
+                .flat_map(|operand| match operand {
+                    JoinOperand::Ref(r) => vec![r.as_str()],
+                    JoinOperand::Refs(refs) => refs.iter().map(String::as_str).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for JoinCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinCondition::Equals(r) => f.write_str(r),
+            JoinCondition::Operators(ops) => {
+                let parts: Vec<String> = ops
+                    .iter()
+                    .map(|(name, operand)| match operand {
+                        JoinOperand::Ref(r) => format!("{name}: {r}"),
+                        JoinOperand::Refs(refs) => format!("{name}: [{}]", refs.join(", ")),
+                    })
+                    .collect();
+                write!(f, "{{{}}}", parts.join(", "))
+            }
+        }
+    }
+}
+
+/// A template field given once, or once per locale (`body: {en: ..., de: ...}`).
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Localized {
+    Single(String),
+    /// Locale tag → variant, e.g. `en`, `de`, `pt-BR`.
+    ByLocale(BTreeMap<String, String>),
+}
+
+impl Localized {
+    /// The text of a field that is not localized.
+    pub fn as_single(&self) -> Option<&str> {
+        match self {
+            Localized::Single(text) => Some(text),
+            Localized::ByLocale(_) => None,
+        }
+    }
+
+    /// Every variant as `(locale, text)`; the locale is `None` for a single text.
+    pub fn variants(&self) -> Vec<(Option<&str>, &str)> {
+        match self {
+            Localized::Single(text) => vec![(None, text.as_str())],
+            Localized::ByLocale(map) => map
+                .iter()
+                .map(|(locale, text)| (Some(locale.as_str()), text.as_str()))
+                .collect(),
+        }
+    }
+
+    /// Whether every variant is empty.
+    pub fn is_empty(&self) -> bool {
+        self.variants().iter().all(|(_, text)| text.is_empty())
+    }
+
+    /// Pick the variant for `locale` (see [`select_locale`]). A single text
+    /// always matches.
+    pub fn select(
+        &self,
+        locale: Option<&str>,
+        fallback: Option<&str>,
+    ) -> Option<(Option<&str>, &str)> {
+        match self {
+            Localized::Single(text) => Some((None, text)),
+            Localized::ByLocale(map) => {
+                select_locale(map, locale, fallback).map(|(key, text)| (Some(key), text.as_str()))
+            }
+        }
+    }
+}
+
+/// The entry of `map` for `locale`: the exact tag, then its language alone
+/// (`de-AT` → `de`), then `fallback`. Tags compare case-insensitively with
+/// `_` and `-` treated alike.
+pub(crate) fn select_locale<'a, V>(
+    map: &'a BTreeMap<String, V>,
+    locale: Option<&str>,
+    fallback: Option<&str>,
+) -> Option<(&'a str, &'a V)> {
+    let normalize = |tag: &str| tag.trim().to_ascii_lowercase().replace('_', "-");
+    let find = |wanted: &str| {
+        let wanted = normalize(wanted);
+        map.iter()
+            .find(|(key, _)| normalize(key) == wanted)
+            .map(|(key, value)| (key.as_str(), value))
+    };
+    let language = |tag: &str| normalize(tag).split('-').next().map(str::to_string);
+    locale
+        .and_then(find)
+        .or_else(|| locale.and_then(language).and_then(|l| find(&l)))
+        .or_else(|| fallback.and_then(find))
+}
+
+impl From<&str> for Localized {
+    fn from(text: &str) -> Self {
+        Localized::Single(text.to_string())
+    }
+}
+
+impl From<String> for Localized {
+    fn from(text: String) -> Self {
+        Localized::Single(text)
+    }
+}
+
+/// Input kind of a declared form field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormFieldType {
+    #[default]
+    Text,
+    Multiline,
+    Email,
+    Number,
+    /// `YYYY-MM-DD`.
+    Date,
+    /// One of `options`.
+    Select,
+    Checkbox,
+    /// A list of strings.
+    List,
+}
+
+/// One declared input of a `form: true` source.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FormField {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub field_type: FormFieldType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+    /// Regex the whole value (each item, for lists) must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Allowed values for `select`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<String>>,
+}
+
+impl FormField {
+    /// An undeclared field inferred from the template: optional plain text.
+    pub fn text(name: impl Into<String>) -> Self {
+        FormField {
+            name: name.into(),
+            field_type: FormFieldType::Text,
+            label: None,
+            default: None,
+            required: None,
+            pattern: None,
+            options: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct SourceConfig {
     pub primary: Option<bool>,
-    pub join: Option<HashMap<String, String>>,
+    pub join: Option<HashMap<String, JoinCondition>>,
     pub many: Option<bool>,
     pub form: Option<bool>,
+    /// Declared inputs of a form source, in display order.
+    pub fields: Option<Vec<FormField>>,
+    /// Extra fields added to every row after loading: name → Handlebars
+    /// expression over the row's own fields and `vars`.
+    pub computed: Option<BTreeMap<String, String>>,
 }
 
+/// A snapshot test: inline source data and the emails it should render.
 #[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateTest {
+    pub name: String,
+    /// Data per namespace, used instead of loading the template's sources.
+    pub sources: BTreeMap<String, serde_json::Value>,
+    /// Values for `{{env.NAME}}`; the real environment is not consulted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// One snapshot per rendered email, in primary row order.
+    #[serde(default)]
+    pub expect: Vec<ExpectedEmail>,
+}
+
+/// Expected output of one email; fields left out are not compared.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedEmail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html_body: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Template {
+    /// Format version; parsed templates are always upgraded to
+    /// `CURRENT_VERSION` before deserialization.
+    pub version: Option<u32>,
     pub sources: HashMap<String, SourceConfig>,
+    /// Static values shared by every entry, available as `{{vars.name}}`.
+    pub vars: Option<BTreeMap<String, serde_json::Value>>,
     pub to: String,
     pub cc: Option<String>,
     pub bcc: Option<String>,
-    pub subject: String,
-    pub body: String,
-    pub attachments: Option<String>,
+    pub subject: Localized,
+    pub body: Localized,
+    /// Hand-written plain-text part; replaces the text derived from the HTML body.
+    pub text_body: Option<Localized>,
+    /// Preview text shown by mail clients after the subject; hidden in the body.
+    pub preheader: Option<Localized>,
+    pub attachments: Option<Localized>,
+    /// `namespace.field` holding each entry's locale, for localized fields.
+    pub locale_field: Option<String>,
+    /// Variant used when an entry's locale has none (or the entry has no locale).
+    pub fallback_locale: Option<String>,
+    /// Translation catalogs for `{{t "id"}}` by locale: `.ftl` (Fluent) or
+    /// `.po` (gettext) paths, relative to the template.
+    pub translations: Option<BTreeMap<String, String>>,
+    /// Snapshot tests run by `snapshot::run_template_tests`.
+    pub tests: Option<Vec<TemplateTest>>,
     pub body_format: Option<BodyFormat>,
+    /// Markdown extensions, for `body_format: markdown`.
+    pub markdown: Option<MarkdownOptions>,
+    /// HTML-escape `{{value}}` in HTML and Markdown bodies and the preheader;
+    /// `{{{value}}}` is never escaped. Defaults to `true`.
+    pub escape_html: Option<bool>,
+    /// How HTML bodies are converted to the plain-text alternative.
+    pub plain_text: Option<PlainTextOptions>,
+    /// Doctype, head and container wrapped around HTML bodies.
+    pub document: Option<DocumentOptions>,
     pub stylesheet: Option<String>,
     pub style: Option<String>,
+    /// How `stylesheet`/`style` are applied; defaults to inlining.
+    pub css_mode: Option<CssMode>,
+    /// Where each top-level value sits in the file; empty for templates not
+    /// parsed from YAML text.
+    #[serde(skip)]
+    pub spans: FieldSpans,
+}
+
+/// Equality of content: `spans` is layout metadata, so the same template
+/// written two ways still compares equal.
+impl PartialEq for Template {
+    fn eq(&self, other: &Self) -> bool {
+        // Destructured so that a new field cannot be left out by accident.
+        let Template {
+            version,
+            sources,
+            vars,
+            to,
+            cc,
+            bcc,
+            subject,
+            body,
+            text_body,
+            preheader,
+            attachments,
+            locale_field,
+            fallback_locale,
+            translations,
+            tests,
+            body_format,
+            markdown,
+            escape_html,
+            plain_text,
+            document,
+            stylesheet,
+            style,
+            css_mode,
+            spans: _,
+        } = self;
+        *version == other.version
+            && *sources == other.sources
+            && *vars == other.vars
+            && *to == other.to
+            && *cc == other.cc
+            && *bcc == other.bcc
+            && *subject == other.subject
+            && *body == other.body
+            && *text_body == other.text_body
+            && *preheader == other.preheader
+            && *attachments == other.attachments
+            && *locale_field == other.locale_field
+            && *fallback_locale == other.fallback_locale
+            && *translations == other.translations
+            && *tests == other.tests
+            && *body_format == other.body_format
+            && *markdown == other.markdown
+            && *escape_html == other.escape_html
+            && *plain_text == other.plain_text
+            && *document == other.document
+            && *stylesheet == other.stylesheet
+            && *style == other.style
+            && *css_mode == other.css_mode
+    }
+}
+
+impl Template {
+    /// Every Handlebars-bearing field as `(name, source)`, in document order.
+    /// Localized fields contribute one pair per variant.
+    pub(crate) fn handlebars_fields(&self) -> Vec<(&'static str, &str)> {
+        let mut fields: Vec<(&'static str, &str)> = [
+            ("to", Some(self.to.as_str())),
+            ("cc", self.cc.as_deref()),
+            ("bcc", self.bcc.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, source)| Some((name, source?)))
+        .collect();
+        for (name, field) in self.localized_fields() {
+            fields.extend(
+                field
+                    .variants()
+                    .into_iter()
+                    .map(|(_, source)| (name, source)),
+            );
+        }
+        fields
+    }
+
+    /// The localized fields as `(name, value)`, skipping absent ones.
+    pub(crate) fn localized_fields(&self) -> Vec<(&'static str, &Localized)> {
+        [
+            ("subject", Some(&self.subject)),
+            ("body", Some(&self.body)),
+            ("text_body", self.text_body.as_ref()),
+            ("preheader", self.preheader.as_ref()),
+            ("attachments", self.attachments.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, field)| Some((name, field?)))
+        .collect()
+    }
 }
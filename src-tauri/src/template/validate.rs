@@ -1,4 +1,10 @@
-use crate::template::types::Template;
+use std::path::Path;
+
+use crate::i18n::CatalogFormat;
+use crate::template::types::{
+    select_locale, BodyFormat, FormField, FormFieldType, Localized, Template, ENV_NAMESPACE,
+    VARS_NAMESPACE,
+};
 
 pub fn validate_sources(template: &Template) -> crate::Result<()> {
     let primaries: Vec<String> = template
@@ -18,39 +24,216 @@ pub fn validate_sources(template: &Template) -> crate::Result<()> {
         }
     }
 
+    for reserved in [VARS_NAMESPACE, ENV_NAMESPACE] {
+        if template.sources.contains_key(reserved) {
+            return Err(crate::MailnirError::ReservedNamespace {
+                namespace: reserved.into(),
+            });
+        }
+    }
+
+    for (namespace, cfg) in &template.sources {
+        if let Some(fields) = &cfg.fields {
+            validate_form_fields(namespace, cfg.form == Some(true), fields)?;
+        }
+        for (field, expression) in cfg.computed.iter().flatten() {
+            handlebars::Template::compile(expression).map_err(|e| {
+                crate::MailnirError::ComputedField {
+                    namespace: namespace.clone(),
+                    field: field.clone(),
+                    row: None,
+                    reason: e.to_string(),
+                }
+            })?;
+        }
+    }
+
+    if template.body_format == Some(BodyFormat::Text) {
+        if template.text_body.is_some() {
+            return Err(crate::MailnirError::InvalidBodyConfig {
+                reason: "text_body has no effect with body_format: text; the body is the text part"
+                    .into(),
+            });
+        }
+        if template.preheader.is_some() {
+            return Err(crate::MailnirError::InvalidBodyConfig {
+                reason: "preheader needs an HTML body; body_format: text has none".into(),
+            });
+        }
+    }
+
+    validate_locales(template)?;
+
     for (namespace, cfg) in &template.sources {
         let Some(join_map) = &cfg.join else {
             continue;
         };
-        for (join_key, ref_value) in join_map {
-            let parts: Vec<&str> = ref_value.splitn(2, '.').collect();
-            let valid = parts.len() == 2 && !parts[0].is_empty() && !parts[1].is_empty();
-            if !valid {
-                return Err(crate::MailnirError::InvalidJoinRef {
-                    namespace: namespace.clone(),
-                    join_key: join_key.clone(),
-                    ref_value: ref_value.clone(),
-                });
+        for (join_key, condition) in join_map {
+            let clauses =
+                condition
+                    .clauses()
+                    .map_err(|reason| crate::MailnirError::InvalidJoinRef {
+                        namespace: namespace.clone(),
+                        join_key: join_key.clone(),
+                        ref_value: condition.to_string(),
+                        reason,
+                    })?;
+
+            for ref_value in clauses.iter().flat_map(|c| c.refs.iter()) {
+                let parts: Vec<&str> = ref_value.splitn(2, '.').collect();
+                let valid = parts.len() == 2 && !parts[0].is_empty() && !parts[1].is_empty();
+                if !valid {
+                    return Err(crate::MailnirError::InvalidJoinRef {
+                        namespace: namespace.clone(),
+                        join_key: join_key.clone(),
+                        ref_value: ref_value.to_string(),
+                        reason: "must be namespace.field".into(),
+                    });
+                }
+
+                let ref_namespace = parts[0];
+
+                if ref_namespace == VARS_NAMESPACE {
+                    let declared = template
+                        .vars
+                        .as_ref()
+                        .is_some_and(|v| v.contains_key(parts[1]));
+                    if !declared {
+                        return Err(crate::MailnirError::InvalidJoinRef {
+                            namespace: namespace.clone(),
+                            join_key: join_key.clone(),
+                            ref_value: ref_value.to_string(),
+                            reason: format!("'{}' is not declared in vars", parts[1]),
+                        });
+                    }
+                    continue;
+                }
+
+                if ref_namespace == namespace {
+                    return Err(crate::MailnirError::SelfJoin {
+                        namespace: namespace.clone(),
+                    });
+                }
+
+                if !template.sources.contains_key(ref_namespace) {
+                    return Err(crate::MailnirError::UnknownJoinNamespace {
+                        namespace: namespace.clone(),
+                        join_key: join_key.clone(),
+                        ref_namespace: ref_namespace.to_string(),
+                    });
+                }
             }
+        }
+    }
 
-            let ref_namespace = parts[0];
+    Ok(())
+}
 
-            if ref_namespace == namespace {
-                return Err(crate::MailnirError::SelfJoin {
-                    namespace: namespace.clone(),
-                });
+fn validate_locales(template: &Template) -> crate::Result<()> {
+    let invalid = |reason: String| crate::MailnirError::InvalidLocaleConfig { reason };
+
+    if let Some(path) = &template.locale_field {
+        let namespace = path
+            .split_once('.')
+            .map(|(ns, _)| ns)
+            .filter(|ns| !ns.is_empty())
+            .ok_or_else(|| invalid(format!("locale_field '{path}' must be namespace.field")))?;
+        if !template.sources.contains_key(namespace) {
+            return Err(invalid(format!(
+                "locale_field '{path}' refers to unknown namespace '{namespace}'"
+            )));
+        }
+    }
+
+    let fallback = template.fallback_locale.as_deref();
+    for (name, field) in template.localized_fields() {
+        let Localized::ByLocale(variants) = field else {
+            continue;
+        };
+        if variants.is_empty() {
+            return Err(invalid(format!("'{name}' has no locale variants")));
+        }
+        if template.locale_field.is_none() && fallback.is_none() {
+            return Err(invalid(format!(
+                "'{name}' is localized but neither locale_field nor fallback_locale is set"
+            )));
+        }
+        if let Some(fallback) = fallback {
+            if field.select(Some(fallback), None).is_none() {
+                return Err(invalid(format!(
+                    "'{name}' has no variant for fallback_locale '{fallback}'"
+                )));
             }
+        }
+    }
 
-            if !template.sources.contains_key(ref_namespace) {
-                return Err(crate::MailnirError::UnknownJoinNamespace {
-                    namespace: namespace.clone(),
-                    join_key: join_key.clone(),
-                    ref_namespace: ref_namespace.to_string(),
-                });
+    if let Some(translations) = &template.translations {
+        if translations.is_empty() {
+            return Err(invalid("translations lists no catalogs".into()));
+        }
+        if template.locale_field.is_none() && fallback.is_none() {
+            return Err(invalid(
+                "translations need locale_field or fallback_locale".into(),
+            ));
+        }
+        if let Some(fallback) = fallback {
+            if select_locale(translations, Some(fallback), None).is_none() {
+                return Err(invalid(format!(
+                    "translations have no catalog for fallback_locale '{fallback}'"
+                )));
+            }
+        }
+        for (locale, path) in translations {
+            if CatalogFormat::from_path(Path::new(path)).is_none() {
+                return Err(invalid(format!(
+                    "translation catalog '{path}' for '{locale}' must be a .ftl or .po file"
+                )));
             }
         }
     }
+    Ok(())
+}
+
+fn validate_form_fields(namespace: &str, is_form: bool, fields: &[FormField]) -> crate::Result<()> {
+    let invalid = |field: &FormField, reason: String| crate::MailnirError::InvalidFormField {
+        namespace: namespace.to_string(),
+        field: field.name.clone(),
+        reason,
+    };
 
+    for (i, field) in fields.iter().enumerate() {
+        if !is_form {
+            return Err(invalid(
+                field,
+                "fields are only allowed on form: true sources".into(),
+            ));
+        }
+        if fields[..i].iter().any(|f| f.name == field.name) {
+            return Err(invalid(field, "declared more than once".into()));
+        }
+        let is_select = field.field_type == FormFieldType::Select;
+        match &field.options {
+            Some(options) if !options.is_empty() && is_select => {}
+            Some(_) if !is_select => {
+                return Err(invalid(field, "options only apply to select fields".into()))
+            }
+            _ if is_select => {
+                return Err(invalid(
+                    field,
+                    "select needs a non-empty options list".into(),
+                ))
+            }
+            _ => {}
+        }
+        field
+            .compiled_pattern()
+            .map_err(|reason| invalid(field, reason))?;
+        if let Some(default) = &field.default {
+            field
+                .check_value(Some(default))
+                .map_err(|reason| invalid(field, format!("default {reason}")))?;
+        }
+    }
     Ok(())
 }
 
@@ -146,6 +329,56 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_validate_valid_operator_joins() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  s:\n    join:\n      courses: {contains: p.course}\n      code: {starts_with: p.prefix}\n      date: {between: [p.from, p.until]}\n      score: {gte: p.min, lte: p.max}\nto: a\nsubject: b\nbody: c",
+        );
+        assert!(validate_sources(&t).is_ok());
+    }
+
+    #[test]
+    fn test_validate_unknown_join_operator() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  s:\n    join:\n      key: {like: p.id}\nto: a\nsubject: b\nbody: c",
+        );
+        let err = validate_sources(&t).unwrap_err();
+        assert!(
+            matches!(&err, crate::MailnirError::InvalidJoinRef { reason, .. } if reason.contains("unknown operator 'like'")),
+            "got: {err}"
+        );
+    }
+
+    #[test]
+    fn test_validate_join_operator_arity() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  s:\n    join:\n      key: {between: p.id}\nto: a\nsubject: b\nbody: c",
+        );
+        assert!(matches!(
+            validate_sources(&t),
+            Err(crate::MailnirError::InvalidJoinRef { .. })
+        ));
+
+        let t2 = make_template(
+            "sources:\n  p: {primary: true}\n  s:\n    join:\n      key: {in: [p.a, p.b]}\nto: a\nsubject: b\nbody: c",
+        );
+        assert!(matches!(
+            validate_sources(&t2),
+            Err(crate::MailnirError::InvalidJoinRef { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_operator_ref_without_dot() {
+        let t = make_template(
+            "sources:\n  p: {primary: true}\n  s:\n    join:\n      key: {gte: nodot}\nto: a\nsubject: b\nbody: c",
+        );
+        assert!(matches!(
+            validate_sources(&t),
+            Err(crate::MailnirError::InvalidJoinRef { ref_value, .. }) if ref_value == "nodot"
+        ));
+    }
+
     #[test]
     fn test_validate_unknown_join_namespace() {
         let t = make_template(
@@ -167,4 +400,173 @@ mod tests {
             Err(crate::MailnirError::SelfJoin { .. })
         ));
     }
+
+    #[test]
+    fn test_validate_form_fields_ok() {
+        let t = make_template(
+            "sources:\n  r:\n    primary: true\n    form: true\n    fields:\n\
+             \x20     - {name: size, type: select, options: [S, M], default: M}\n\
+             \x20     - {name: code, pattern: '[0-9]+', default: '42'}\n\
+             to: a\nsubject: b\nbody: c",
+        );
+        assert!(validate_sources(&t).is_ok());
+    }
+
+    #[test]
+    fn test_validate_form_fields_errors() {
+        let cases = [
+            (
+                "form: false\n    fields: [{name: a}]",
+                "only allowed on form",
+            ),
+            (
+                "form: true\n    fields: [{name: a}, {name: a}]",
+                "more than once",
+            ),
+            (
+                "form: true\n    fields: [{name: a, type: select}]",
+                "non-empty options",
+            ),
+            (
+                "form: true\n    fields: [{name: a, options: [x]}]",
+                "only apply to select",
+            ),
+            (
+                "form: true\n    fields: [{name: a, pattern: '('}]",
+                "invalid pattern",
+            ),
+            (
+                "form: true\n    fields: [{name: a, type: number, default: x}]",
+                "default 'x' is not a number",
+            ),
+        ];
+        for (cfg, expected) in cases {
+            let t = make_template(&format!(
+                "sources:\n  r:\n    primary: true\n    {cfg}\nto: a\nsubject: b\nbody: c"
+            ));
+            match validate_sources(&t) {
+                Err(crate::MailnirError::InvalidFormField {
+                    namespace,
+                    field,
+                    reason,
+                }) => {
+                    assert_eq!(namespace, "r");
+                    assert_eq!(field, "a");
+                    assert!(reason.contains(expected), "{cfg}: {reason}");
+                }
+                other => panic!("{cfg}: expected InvalidFormField, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_reserved_source_names() {
+        for name in ["vars", "env"] {
+            let t = make_template(&format!(
+                "sources:\n  p: {{primary: true}}\n  {name}: {{}}\nto: x\nsubject: y\nbody: z"
+            ));
+            assert!(matches!(
+                validate_sources(&t),
+                Err(crate::MailnirError::ReservedNamespace { namespace }) if namespace == name
+            ));
+        }
+    }
+
+    #[test]
+    fn test_validate_join_on_vars() {
+        let yaml = |var: &str| {
+            format!(
+                "vars:\n  term: Fall\nsources:\n  p: {{primary: true}}\n  r:\n    join:\n      term: vars.{var}\nto: x\nsubject: y\nbody: z"
+            )
+        };
+        assert!(validate_sources(&make_template(&yaml("term"))).is_ok());
+        assert!(matches!(
+            validate_sources(&make_template(&yaml("year"))),
+            Err(crate::MailnirError::InvalidJoinRef { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_computed_syntax() {
+        let t = make_template(
+            "sources:\n  p:\n    primary: true\n    computed:\n      bad: \"{{#if x}}\"\nto: x\nsubject: y\nbody: z",
+        );
+        assert!(matches!(
+            validate_sources(&t),
+            Err(crate::MailnirError::ComputedField { row: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_locale_settings() {
+        let yaml = |extra: &str| {
+            format!(
+                "sources:\n  s: {{primary: true}}\n{extra}to: x\nsubject: {{en: Hi, de: Hallo}}\nbody: z"
+            )
+        };
+        let ok = |extra: &str| validate_sources(&make_template(&yaml(extra)));
+        assert!(ok("locale_field: s.lang\n").is_ok());
+        assert!(ok("locale_field: s.lang\nfallback_locale: EN\n").is_ok());
+        for bad in [
+            "",
+            "locale_field: lang\n",
+            "locale_field: t.lang\n",
+            "fallback_locale: fr\n",
+        ] {
+            assert!(
+                matches!(
+                    ok(bad),
+                    Err(crate::MailnirError::InvalidLocaleConfig { .. })
+                ),
+                "{bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_translations() {
+        let yaml = |extra: &str| {
+            format!("sources:\n  s: {{primary: true}}\n{extra}to: x\nsubject: y\nbody: z")
+        };
+        let ok = |extra: &str| validate_sources(&make_template(&yaml(extra)));
+        assert!(ok("locale_field: s.lang\ntranslations: {en: i18n/en.ftl, de: de.po}\n").is_ok());
+        for bad in [
+            "translations: {en: en.ftl}\n",
+            "locale_field: s.lang\ntranslations: {}\n",
+            "fallback_locale: fr\ntranslations: {en: en.ftl}\n",
+            "locale_field: s.lang\ntranslations: {en: en.json}\n",
+        ] {
+            assert!(
+                matches!(
+                    ok(bad),
+                    Err(crate::MailnirError::InvalidLocaleConfig { .. })
+                ),
+                "{bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_text_body_requires_html_part() {
+        let yaml = |format: &str| {
+            format!("sources:\n  s: {{primary: true}}\nto: x\nsubject: y\nbody: z\ntext_body: t\nbody_format: {format}")
+        };
+        assert!(validate_sources(&make_template(&yaml("html"))).is_ok());
+        assert!(matches!(
+            validate_sources(&make_template(&yaml("text"))),
+            Err(crate::MailnirError::InvalidBodyConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_preheader_requires_html_part() {
+        let yaml = |format: &str| {
+            format!("sources:\n  s: {{primary: true}}\nto: x\nsubject: y\nbody: z\npreheader: p\nbody_format: {format}")
+        };
+        assert!(validate_sources(&make_template(&yaml("markdown"))).is_ok());
+        assert!(matches!(
+            validate_sources(&make_template(&yaml("text"))),
+            Err(crate::MailnirError::InvalidBodyConfig { .. })
+        ));
+    }
 }
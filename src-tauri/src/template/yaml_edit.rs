@@ -0,0 +1,737 @@
+use serde_yaml::{Mapping, Value};
+
+/// Set (`Some`) or remove (`None`) top-level keys of a template document,
+/// touching only the entries whose value actually changes.
+///
+/// Comments, key order, anchors and the quoting or block-scalar style of each
+/// rewritten scalar are kept. The edited text is re-parsed and compared with
+/// the intended document; if the two disagree (exotic layouts such as flow
+/// mappings or multiple documents), `TemplateEdit` is returned and the caller
+/// decides whether [`rewrite_top_level_keys`] is acceptable.
+pub fn edit_top_level_keys(
+    content: &str,
+    edits: &[(&str, Option<Value>)],
+) -> crate::Result<String> {
+    let (original, expected) = apply_edits(content, edits)?;
+    let normalized = content.replace("\r\n", "\n");
+    let edited = preserving_edit(&normalized, &original, edits).ok_or_else(|| {
+        crate::MailnirError::TemplateEdit {
+            reason: "the document is not a plain block mapping".into(),
+        }
+    })?;
+    verified(content, edited, &expected)
+}
+
+/// Set or remove keys of the mapping under top-level key `parent` (such as
+/// one source under `sources:`), touching only the entries that change.
+///
+/// Fails with `TemplateEdit` when `parent` is not a block mapping of block
+/// entries the line scanner understands.
+pub fn edit_nested_keys(
+    content: &str,
+    parent: &str,
+    edits: &[(&str, Option<Value>)],
+) -> crate::Result<String> {
+    let cannot = |reason: &str| crate::MailnirError::TemplateEdit {
+        reason: format!("'{parent}': {reason}"),
+    };
+    let (_, current) = apply_edits(content, &[])?;
+    let mut parent_map = match current.get(parent) {
+        Some(Value::Mapping(map)) => map.clone(),
+        _ => return Err(cannot("not a mapping")),
+    };
+    for (key, value) in edits {
+        let key = Value::String(key.to_string());
+        match value {
+            Some(v) => parent_map.insert(key, v.clone()),
+            None => parent_map.remove(&key),
+        };
+    }
+    let mut expected = current;
+    expected.insert(Value::String(parent.into()), Value::Mapping(parent_map));
+
+    let mut lines: Vec<String> = content
+        .replace("\r\n", "\n")
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect();
+    if lines.last().is_some_and(|l| !l.ends_with('\n')) {
+        if let Some(last) = lines.last_mut() {
+            last.push('\n');
+        }
+    }
+    let entries = find_entries(&lines).ok_or_else(|| cannot("unsupported layout"))?;
+    let entry = entries
+        .iter()
+        .find(|e| e.key == parent)
+        .ok_or_else(|| cannot("missing"))?;
+    let indent_of = |l: &str| l.len() - l.trim_start().len();
+    let is_content = |l: &str| !l.trim().is_empty() && !l.trim_start().starts_with('#');
+    let first = lines[entry.start].trim_end_matches('\n');
+    let (_, after_colon) = top_level_key(first).ok_or_else(|| cannot("unsupported layout"))?;
+    let rest = first[after_colon..].trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(cannot("not a block mapping"));
+    }
+    let indent = lines[entry.start + 1..entry.end]
+        .iter()
+        .find(|l| is_content(l))
+        .map(|l| indent_of(l))
+        .unwrap_or(2);
+
+    // Children of `parent`: (key, first line, end line).
+    let mut children: Vec<(String, usize, usize)> = Vec::new();
+    for i in entry.start + 1..entry.end {
+        let line = lines[i].trim_end_matches('\n');
+        if !is_content(line) || indent_of(line) != indent {
+            continue;
+        }
+        let (key, _) =
+            top_level_key(&line[indent..]).ok_or_else(|| cannot("unsupported layout"))?;
+        let next = (i + 1..entry.end)
+            .find(|&j| is_content(&lines[j]) && indent_of(&lines[j]) <= indent)
+            .unwrap_or(entry.end);
+        // Blank lines and comments before the next child stay with it.
+        let end = (i + 1..next)
+            .rev()
+            .find(|&j| is_content(&lines[j]))
+            .map_or(i + 1, |j| j + 1);
+        children.push((key, i, end));
+    }
+
+    let pad = " ".repeat(indent);
+    let render = |key: &str, value: &Value| -> Option<Vec<String>> {
+        Some(
+            render_new_entry(key, value)?
+                .into_iter()
+                .map(|l| if l == "\n" { l } else { format!("{pad}{l}") })
+                .collect(),
+        )
+    };
+    let mut replacements: Vec<(usize, usize, Vec<String>)> = Vec::new();
+    let mut appended: Vec<String> = Vec::new();
+    for (key, value) in edits {
+        let child = children.iter().find(|(k, _, _)| k == key);
+        match (child, value) {
+            (Some(&(_, start, end)), Some(value)) => {
+                let rendered = render(key, value).ok_or_else(|| cannot("cannot render value"))?;
+                replacements.push((start, end, rendered));
+            }
+            (Some(&(_, start, end)), None) => replacements.push((start, end, Vec::new())),
+            (None, Some(value)) => {
+                appended.extend(render(key, value).ok_or_else(|| cannot("cannot render value"))?)
+            }
+            (None, None) => {}
+        }
+    }
+    if !appended.is_empty() {
+        replacements.push((entry.end, entry.end, appended));
+    }
+    replacements.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    for (start, end, rendered) in replacements {
+        lines.splice(start..end, rendered);
+    }
+    verified(content, lines.concat(), &expected)
+}
+
+/// Re-serialize the whole document with `edits` applied. Comments, anchors
+/// and quoting are lost; for callers that accept that when
+/// [`edit_top_level_keys`] cannot edit in place.
+pub fn rewrite_top_level_keys(
+    content: &str,
+    edits: &[(&str, Option<Value>)],
+) -> crate::Result<String> {
+    let (_, expected) = apply_edits(content, edits)?;
+    serde_yaml::to_string(&expected).map_err(|source| crate::MailnirError::TemplateParseYaml {
+        path: std::path::PathBuf::from("<string>"),
+        source,
+    })
+}
+
+/// The parsed document before and after `edits`.
+fn apply_edits(
+    content: &str,
+    edits: &[(&str, Option<Value>)],
+) -> crate::Result<(Mapping, Mapping)> {
+    let original: Mapping =
+        serde_yaml::from_str(content).map_err(|source| crate::MailnirError::TemplateParseYaml {
+            path: std::path::PathBuf::from("<string>"),
+            source,
+        })?;
+    let mut expected = original.clone();
+    for (key, value) in edits {
+        let key_value = Value::String(key.to_string());
+        match value {
+            Some(v) => {
+                expected.insert(key_value, v.clone());
+            }
+            None => {
+                expected.remove(&key_value);
+            }
+        }
+    }
+    Ok((original, expected))
+}
+
+/// `edited` (LF endings) in `content`'s line endings, if it parses to `expected`.
+fn verified(content: &str, edited: String, expected: &Mapping) -> crate::Result<String> {
+    if serde_yaml::from_str::<Mapping>(&edited).ok().as_ref() != Some(expected) {
+        return Err(crate::MailnirError::TemplateEdit {
+            reason: "the edited text would not read back as intended".into(),
+        });
+    }
+    Ok(if content.contains("\r\n") {
+        edited.replace('\n', "\r\n")
+    } else {
+        edited
+    })
+}
+
+/// One `key: value` block at column 0, as a range of lines.
+pub(super) struct Entry {
+    pub(super) key: String,
+    pub(super) start: usize,
+    /// Exclusive; trailing blank lines are not part of the entry.
+    pub(super) end: usize,
+}
+
+fn preserving_edit(
+    content: &str,
+    original: &Mapping,
+    edits: &[(&str, Option<Value>)],
+) -> Option<String> {
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(str::to_string).collect();
+    if lines.last().is_some_and(|l| !l.ends_with('\n')) {
+        lines.last_mut()?.push('\n');
+    }
+    let entries = find_entries(&lines)?;
+
+    // Work on a per-entry replacement table, then splice once.
+    let mut replacements: Vec<(usize, usize, Vec<String>)> = Vec::new();
+    let mut appended: Vec<String> = Vec::new();
+
+    for (key, value) in edits {
+        let current = original.get(Value::String(key.to_string()));
+        if current == value.as_ref() {
+            continue;
+        }
+        let entry = entries.iter().find(|e| e.key == *key);
+        match (entry, value) {
+            (Some(entry), Some(value)) => {
+                let rendered = rewrite_entry(&lines[entry.start..entry.end], key, value)?;
+                replacements.push((entry.start, entry.end, rendered));
+            }
+            (Some(entry), None) => replacements.push((entry.start, entry.end, Vec::new())),
+            (None, Some(value)) => appended.extend(render_new_entry(key, value)?),
+            (None, None) => {}
+        }
+    }
+
+    replacements.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    for (start, end, rendered) in replacements {
+        lines.splice(start..end, rendered);
+    }
+    lines.extend(appended);
+    Some(lines.concat())
+}
+
+/// Locate every top-level entry, or `None` if the layout is not a plain block
+/// mapping (flow mapping, multiple documents, duplicate keys, ...).
+pub(super) fn find_entries(lines: &[String]) -> Option<Vec<Entry>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut seen_content = false;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_end_matches('\n');
+        if trimmed.trim().is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.starts_with([' ', '\t']) {
+            if entries.is_empty() {
+                return None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("---") && !seen_content && entries.is_empty() {
+            seen_content = true;
+            continue;
+        }
+        seen_content = true;
+        let key = top_level_key(trimmed)?.0;
+        if entries.iter().any(|e| e.key == key) {
+            return None;
+        }
+        entries.push(Entry {
+            key,
+            start: i,
+            end: i + 1,
+        });
+    }
+
+    // An entry runs until the next column-0 line; drop trailing blanks and
+    // column-0 comments (those introduce the following key).
+    let starts: Vec<usize> = entries.iter().map(|e| e.start).collect();
+    for (n, entry) in entries.iter_mut().enumerate() {
+        let limit = starts.get(n + 1).copied().unwrap_or(lines.len());
+        let mut end = entry.start + 1;
+        for (i, line) in lines.iter().enumerate().take(limit).skip(entry.start + 1) {
+            let trimmed = line.trim_end_matches('\n');
+            if trimmed.starts_with([' ', '\t']) && !trimmed.trim().is_empty() {
+                end = i + 1;
+            } else if !trimmed.trim().is_empty() {
+                break;
+            }
+        }
+        entry.end = end;
+    }
+    Some(entries)
+}
+
+/// Split a column-0 line into its key and the byte offset just past the colon.
+pub(super) fn top_level_key(line: &str) -> Option<(String, usize)> {
+    let first = line.chars().next()?;
+    if matches!(
+        first,
+        '{' | '[' | '?' | '-' | '&' | '*' | '!' | '|' | '>' | '%' | '@' | '`'
+    ) {
+        return None;
+    }
+    if first == '"' || first == '\'' {
+        let (key, len) = quoted_scalar(line)?;
+        let rest = &line[len..];
+        let colon = rest.find(':')?;
+        if !rest[..colon].trim().is_empty() {
+            return None;
+        }
+        return Some((key, len + colon + 1));
+    }
+    let bytes = line.as_bytes();
+    let colon = (0..bytes.len()).find(|&i| {
+        bytes[i] == b':' && bytes.get(i + 1).is_none_or(|b| *b == b' ' || *b == b'\t')
+    })?;
+    Some((line[..colon].trim_end().to_string(), colon + 1))
+}
+
+/// Parse a single-line quoted scalar at the start of `s`: `(value, byte length)`.
+fn quoted_scalar(s: &str) -> Option<(String, usize)> {
+    let quote = s.chars().next()?;
+    let mut value = String::new();
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            ('\'', '\'') => {
+                if chars.peek().map(|(_, n)| *n) == Some('\'') {
+                    chars.next();
+                    value.push('\'');
+                } else {
+                    return Some((value, i + 1));
+                }
+            }
+            ('"', '\\') => {
+                chars.next()?;
+            }
+            ('"', '"') => {
+                // Let the YAML parser resolve escapes in the quoted text.
+                let unescaped = serde_yaml::from_str::<String>(&s[..=i]).ok()?;
+                return Some((unescaped, i + 1));
+            }
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+/// How an existing scalar was written, so a new value can be written the same way.
+enum Style {
+    Plain,
+    SingleQuoted,
+    DoubleQuoted,
+    /// `|` or `>` with the content indentation in spaces.
+    Block {
+        folded: bool,
+        indent: usize,
+    },
+    /// Value on the following lines (nested mapping/sequence) or unknown.
+    Other,
+}
+
+fn rewrite_entry(entry_lines: &[String], key: &str, value: &Value) -> Option<Vec<String>> {
+    let Value::String(text) = value else {
+        return render_new_entry(key, value);
+    };
+    let first = entry_lines[0].trim_end_matches('\n');
+    let (_, after_colon) = top_level_key(first)?;
+    let prefix = &first[..after_colon];
+    let rest = first[after_colon..].trim_start();
+
+    // Keep an anchor so aliases elsewhere still resolve.
+    let (anchor, rest) = match rest.strip_prefix('&') {
+        Some(a) => {
+            let end = a.find([' ', '\t']).unwrap_or(a.len());
+            (format!("&{} ", &a[..end]), a[end..].trim_start())
+        }
+        None => (String::new(), rest),
+    };
+
+    let (style, comment) = match rest.chars().next() {
+        Some('|') | Some('>') => {
+            let header_end = rest.find([' ', '\t']).unwrap_or(rest.len());
+            let header = &rest[..header_end];
+            let explicit = header
+                .chars()
+                .find_map(|c| c.to_digit(10))
+                .map(|d| d as usize);
+            let detected = entry_lines[1..]
+                .iter()
+                .find(|l| !l.trim().is_empty())
+                .map(|l| l.len() - l.trim_start_matches(' ').len());
+            let indent = explicit.or(detected).unwrap_or(2).max(1);
+            (
+                Style::Block {
+                    folded: rest.starts_with('>'),
+                    indent,
+                },
+                trailing_comment(&rest[header_end..]),
+            )
+        }
+        Some(q @ ('\'' | '"')) => {
+            let style = if q == '\'' {
+                Style::SingleQuoted
+            } else {
+                Style::DoubleQuoted
+            };
+            let comment = quoted_scalar(rest)
+                .filter(|_| entry_lines.len() == 1)
+                .map(|(_, len)| trailing_comment(&rest[len..]))
+                .unwrap_or_default();
+            (style, comment)
+        }
+        Some('#') | None => (Style::Other, trailing_comment(rest)),
+        Some(_) if entry_lines.len() == 1 => {
+            let comment_at = rest.find(" #").or_else(|| rest.find("\t#"));
+            (
+                Style::Plain,
+                comment_at
+                    .map(|i| trailing_comment(&rest[i..]))
+                    .unwrap_or_default(),
+            )
+        }
+        Some(_) => (Style::Plain, String::new()),
+    };
+
+    let mut out = Vec::new();
+    match style {
+        Style::Block { folded, indent } => {
+            let (header, body) = block_scalar(text, folded, indent)?;
+            out.push(format!("{prefix} {anchor}{header}{comment}\n"));
+            out.extend(body);
+        }
+        _ if text.contains('\n') => {
+            let (header, body) = block_scalar(text, false, 2)?;
+            out.push(format!("{prefix} {anchor}{header}{comment}\n"));
+            out.extend(body);
+        }
+        Style::SingleQuoted => {
+            out.push(format!(
+                "{prefix} {anchor}'{}'{comment}\n",
+                text.replace('\'', "''")
+            ));
+        }
+        Style::DoubleQuoted => {
+            let quoted = serde_json::to_string(text).ok()?;
+            out.push(format!("{prefix} {anchor}{quoted}{comment}\n"));
+        }
+        Style::Plain | Style::Other => {
+            out.push(format!(
+                "{prefix} {anchor}{}{comment}\n",
+                emit_scalar(text)?
+            ));
+        }
+    }
+    Some(out)
+}
+
+/// Render a whole `key: value` entry for a key that does not exist yet (or
+/// whose value is not a string).
+fn render_new_entry(key: &str, value: &Value) -> Option<Vec<String>> {
+    if let Value::String(text) = value {
+        if text.contains('\n') {
+            let (header, body) = block_scalar(text, false, 2)?;
+            let mut out = vec![format!("{key}: {header}\n")];
+            out.extend(body);
+            return Some(out);
+        }
+    }
+    let mut single = Mapping::new();
+    single.insert(Value::String(key.to_string()), value.clone());
+    let emitted = serde_yaml::to_string(&single).ok()?;
+    Some(emitted.split_inclusive('\n').map(str::to_string).collect())
+}
+
+/// The emitter's rendering of a single-line string (plain when safe, quoted otherwise).
+fn emit_scalar(text: &str) -> Option<String> {
+    let emitted = serde_yaml::to_string(text).ok()?;
+    Some(emitted.trim_end_matches('\n').to_string())
+}
+
+/// `"  # comment"` from the remainder of a line, or empty when there is none.
+fn trailing_comment(rest: &str) -> String {
+    match rest.find('#') {
+        Some(i) if rest[..i].trim().is_empty() => {
+            let lead = if i == 0 { " " } else { &rest[..i] };
+            format!("{lead}{}", rest[i..].trim_end())
+        }
+        _ => String::new(),
+    }
+}
+
+/// Header (`|`, `>-`, `|2+`, ...) and content lines of a block scalar.
+fn block_scalar(text: &str, folded: bool, indent: usize) -> Option<(String, Vec<String>)> {
+    let trailing = text.len() - text.trim_end_matches('\n').len();
+    let content = text.trim_end_matches('\n');
+    if content.is_empty() {
+        return None;
+    }
+    let chomp = match trailing {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    let leading_space = content
+        .split('\n')
+        .find(|l| !l.is_empty())
+        .is_some_and(|l| l.starts_with([' ', '\t']));
+    let indicator = if leading_space {
+        indent.to_string()
+    } else {
+        String::new()
+    };
+    let style = if folded { '>' } else { '|' };
+    let header = format!("{style}{indicator}{chomp}");
+
+    let src: Vec<&str> = content.split('\n').collect();
+    let mut rendered: Vec<&str> = Vec::new();
+    let is_normal = |l: &str| !l.is_empty() && !l.starts_with([' ', '\t']);
+    let mut i = 0;
+    while i < src.len() {
+        rendered.push(src[i]);
+        if folded && is_normal(src[i]) {
+            // A break between two normal lines folds to a space, so each
+            // run of breaks needs one extra blank line.
+            let mut j = i + 1;
+            while j < src.len() && src[j].is_empty() {
+                j += 1;
+            }
+            if j < src.len() && is_normal(src[j]) {
+                rendered.push("");
+            }
+        }
+        i += 1;
+    }
+
+    let pad = " ".repeat(indent);
+    let mut body: Vec<String> = rendered
+        .iter()
+        .map(|l| {
+            if l.is_empty() {
+                "\n".to_string()
+            } else {
+                format!("{pad}{l}\n")
+            }
+        })
+        .collect();
+    body.extend((1..trailing).map(|_| "\n".to_string()));
+    Some((header, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(v: &str) -> Option<Value> {
+        Some(Value::String(v.to_string()))
+    }
+
+    const DOC: &str = "\
+# Welcome mail
+sources:
+  p: {primary: true} # the list
+to: '{{p.email}}'  # recipient
+subject: &subj Hello {{p.name}}
+cc: \"boss@example.com\"
+
+# Body follows
+body: |
+  Hi {{p.name}},
+
+  bye
+style: >-
+  p { color: red }
+";
+
+    #[test]
+    fn test_unchanged_values_leave_text_identical() {
+        let out = edit_top_level_keys(
+            DOC,
+            &[
+                ("to", s("{{p.email}}")),
+                ("body", s("Hi {{p.name}},\n\nbye\n")),
+                ("bcc", None),
+            ],
+        )
+        .unwrap();
+        assert_eq!(out, DOC);
+    }
+
+    #[test]
+    fn test_changed_scalars_keep_style_and_comments() {
+        let out = edit_top_level_keys(
+            DOC,
+            &[
+                ("to", s("{{p.mail}}")),
+                ("subject", s("Hi {{p.name}}")),
+                ("cc", s("team@example.com")),
+            ],
+        )
+        .unwrap();
+        assert!(out.contains("to: '{{p.mail}}'  # recipient\n"), "{out}");
+        assert!(out.contains("subject: &subj Hi {{p.name}}\n"), "{out}");
+        assert!(out.contains("cc: \"team@example.com\"\n"), "{out}");
+        assert!(out.starts_with("# Welcome mail\nsources:\n  p: {primary: true} # the list\n"));
+        assert!(out.contains("\n# Body follows\nbody: |\n"));
+    }
+
+    #[test]
+    fn test_block_scalar_rewritten_in_place() {
+        let out = edit_top_level_keys(DOC, &[("body", s("Dear {{p.name}},\n\n  indented\nend"))])
+            .unwrap();
+        assert!(
+            out.contains("body: |-\n  Dear {{p.name}},\n\n    indented\n  end\nstyle: >-\n"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_folded_scalar_stays_folded() {
+        let out = edit_top_level_keys(DOC, &[("style", s("p { color: blue }\nh1 { margin: 0 }"))])
+            .unwrap();
+        assert!(
+            out.ends_with("style: >-\n  p { color: blue }\n\n  h1 { margin: 0 }\n"),
+            "{out}"
+        );
+        let parsed: Mapping = serde_yaml::from_str(&out).unwrap();
+        assert_eq!(parsed["style"], "p { color: blue }\nh1 { margin: 0 }");
+    }
+
+    #[test]
+    fn test_remove_and_append_keys() {
+        let out = edit_top_level_keys(
+            DOC,
+            &[("cc", None), ("attachments", s("files/{{p.id}}.pdf"))],
+        )
+        .unwrap();
+        assert!(!out.contains("cc:"));
+        assert!(
+            out.ends_with("style: >-\n  p { color: red }\nattachments: files/{{p.id}}.pdf\n"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_plain_value_needing_quotes_is_quoted() {
+        let out = edit_top_level_keys("to: a\nsubject: b\nbody: c\n", &[("to", s("{{p.email}}"))])
+            .unwrap();
+        assert_eq!(out, "to: '{{p.email}}'\nsubject: b\nbody: c\n");
+    }
+
+    #[test]
+    fn test_changed_mapping_rewrites_only_that_entry() {
+        let mut sources = Mapping::new();
+        let mut cfg = Mapping::new();
+        cfg.insert("primary".into(), Value::Bool(true));
+        sources.insert("q".into(), Value::Mapping(cfg));
+        let out = edit_top_level_keys(DOC, &[("sources", Some(Value::Mapping(sources)))]).unwrap();
+        assert!(out.starts_with("# Welcome mail\nsources:\n  q:\n    primary: true\nto: '{{p.email}}'  # recipient\n"), "{out}");
+    }
+
+    #[test]
+    fn test_crlf_line_endings_preserved() {
+        let out = edit_top_level_keys("to: a\r\nbody: b\r\n", &[("to", s("c"))]).unwrap();
+        assert_eq!(out, "to: c\r\nbody: b\r\n");
+    }
+
+    #[test]
+    fn test_flow_mapping_is_not_rewritten_silently() {
+        let err = edit_top_level_keys("{to: a, body: b}\n", &[("to", s("c"))]).unwrap_err();
+        assert!(matches!(err, crate::MailnirError::TemplateEdit { .. }));
+
+        let out = rewrite_top_level_keys("{to: a, body: b}\n", &[("to", s("c"))]).unwrap();
+        let parsed: Mapping = serde_yaml::from_str(&out).unwrap();
+        assert_eq!(parsed["to"], "c");
+        assert_eq!(parsed["body"], "b");
+    }
+
+    #[test]
+    fn test_nested_edit_touches_only_changed_children() {
+        let doc = "\
+sources:
+  # people
+  p: &p {primary: true}  # list
+  q:
+    join: {id: p.id}
+    x-note: keep
+
+  # extra
+  r: {many: true}
+to: a
+";
+        let mut q = Mapping::new();
+        q.insert("join".into(), serde_yaml::from_str("{id: p.key}").unwrap());
+        q.insert("x-note".into(), "keep".into());
+        let out = edit_nested_keys(
+            doc,
+            "sources",
+            &[
+                ("q", Some(Value::Mapping(q))),
+                ("s", serde_yaml::from_str("{form: true}").ok()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "sources:\n  # people\n  p: &p {primary: true}  # list\n  q:\n    join:\n      id: p.key\n    x-note: keep\n\n  # extra\n  r: {many: true}\n  s:\n    form: true\nto: a\n"
+        );
+
+        let out = edit_nested_keys(doc, "sources", &[("r", None)]).unwrap();
+        assert!(
+            out.ends_with("    x-note: keep\n\n  # extra\nto: a\n"),
+            "{out}"
+        );
+
+        let err =
+            edit_nested_keys("sources: {p: {}}\nto: a\n", "sources", &[("p", None)]).unwrap_err();
+        assert!(matches!(err, crate::MailnirError::TemplateEdit { .. }));
+    }
+
+    #[test]
+    fn test_fixture_templates_round_trip_unchanged() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/templates");
+        for file in std::fs::read_dir(dir).unwrap() {
+            let path = file.unwrap().path();
+            if !path.to_string_lossy().ends_with(".mailnir.yml") {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path).unwrap();
+            let t = crate::template::parse_template_str(&content).unwrap();
+            let out = edit_top_level_keys(
+                &content,
+                &[
+                    ("to", s(&t.to)),
+                    ("subject", serde_yaml::to_value(&t.subject).ok()),
+                    ("body", serde_yaml::to_value(&t.body).ok()),
+                ],
+            )
+            .unwrap();
+            assert_eq!(out, content, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value;
+
+use crate::join::{build_contexts_traced, JoinTrace};
+use crate::suggest::edit_distance;
+use crate::template::{JoinCondition, Template};
+
+/// Maximum number of candidate keys suggested per missing match.
+const MAX_CANDIDATES: usize = 3;
+
+/// Join usage report across all primary entries, one section per secondary namespace.
+#[derive(Debug, Clone)]
+pub struct JoinDiagnostics {
+    /// Sorted by namespace name.
+    pub namespaces: Vec<NamespaceJoinDiagnostics>,
+}
+
+impl JoinDiagnostics {
+    /// Returns `true` when every secondary row was used and every lookup matched.
+    pub fn is_clean(&self) -> bool {
+        self.namespaces
+            .iter()
+            .all(|ns| ns.unmatched_rows.is_empty() && ns.missing_matches.is_empty())
+    }
+
+    pub fn namespace(&self, name: &str) -> Option<&NamespaceJoinDiagnostics> {
+        self.namespaces.iter().find(|ns| ns.namespace == name)
+    }
+}
+
+/// Join usage for one secondary namespace.
+#[derive(Debug, Clone)]
+pub struct NamespaceJoinDiagnostics {
+    pub namespace: String,
+    /// Rows joined to at least one primary entry.
+    pub matched_count: usize,
+    /// Rows never joined to any primary entry.
+    pub unmatched_count: usize,
+    /// The never-joined rows, in source order.
+    pub unmatched_rows: Vec<UnmatchedRow>,
+    /// One item per primary entry whose 1:1 lookup found no row.
+    pub missing_matches: Vec<MissingMatch>,
+}
+
+/// A secondary row that no primary entry joined against.
+#[derive(Debug, Clone)]
+pub struct UnmatchedRow {
+    /// Zero-based index into the secondary source array.
+    pub row_index: usize,
+    /// Join key → the row's value (`null` when the row lacks the key).
+    pub keys: BTreeMap<String, Value>,
+}
+
+/// A primary entry whose 1:1 join found no row, with the nearest existing keys.
+#[derive(Debug, Clone)]
+pub struct MissingMatch {
+    pub entry_index: usize,
+    /// Join key → the value the entry looked for.
+    pub expected: BTreeMap<String, Value>,
+    /// Closest distinct key combinations present in the secondary, nearest first.
+    pub candidates: Vec<KeyCandidate>,
+}
+
+/// An existing key combination and its edit distance from the looked-up one.
+#[derive(Debug, Clone)]
+pub struct KeyCandidate {
+    pub keys: BTreeMap<String, Value>,
+    /// Sum of per-key edit distances over the string forms of the values.
+    pub distance: usize,
+}
+
+/// Report which secondary rows were never joined and suggest near-miss keys
+/// for failed lookups.
+///
+/// Returns `Err` only on the same structural failures as [`crate::join::build_contexts`].
+/// Entries stop joining at their first failure, so rows of namespaces processed
+/// after a failed join are only counted for entries that got that far.
+pub fn join_diagnostics(
+    template: &Template,
+    sources: &HashMap<String, Value>,
+) -> crate::Result<JoinDiagnostics> {
+    let mut trace = JoinTrace::default();
+    build_contexts_traced(template, sources, Some(&mut trace))?;
+
+    let mut namespaces: Vec<NamespaceJoinDiagnostics> = trace
+        .used_rows
+        .iter()
+        .map(|(ns_name, used)| {
+            let join_map = template.sources[ns_name]
+                .join
+                .as_ref()
+                .expect("traced namespaces are secondaries");
+            // Shape was validated by the traced build.
+            let rows = sources[ns_name].as_array().expect("secondary is an array");
+            let row_keys: Vec<BTreeMap<String, Value>> = rows
+                .iter()
+                .map(|row| row_join_keys(row, join_map))
+                .collect();
+
+            let unmatched_rows: Vec<UnmatchedRow> = used
+                .iter()
+                .enumerate()
+                .filter(|(_, &was_used)| !was_used)
+                .map(|(row_index, _)| UnmatchedRow {
+                    row_index,
+                    keys: row_keys[row_index].clone(),
+                })
+                .collect();
+
+            let missing_matches = trace
+                .missing
+                .iter()
+                .filter(|m| &m.namespace == ns_name)
+                .map(|m| MissingMatch {
+                    entry_index: m.entry_index,
+                    expected: m.expected.clone(),
+                    candidates: closest_candidates(&m.expected, &row_keys),
+                })
+                .collect();
+
+            NamespaceJoinDiagnostics {
+                namespace: ns_name.clone(),
+                matched_count: used.len() - unmatched_rows.len(),
+                unmatched_count: unmatched_rows.len(),
+                unmatched_rows,
+                missing_matches,
+            }
+        })
+        .collect();
+
+    namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+    Ok(JoinDiagnostics { namespaces })
+}
+
+fn row_join_keys(
+    row: &Value,
+    join_map: &HashMap<String, JoinCondition>,
+) -> BTreeMap<String, Value> {
+    join_map
+        .keys()
+        .map(|key| (key.clone(), row.get(key).cloned().unwrap_or(Value::Null)))
+        .collect()
+}
+
+/// Rank the distinct key combinations in `row_keys` by distance to `expected`.
+fn closest_candidates(
+    expected: &BTreeMap<String, Value>,
+    row_keys: &[BTreeMap<String, Value>],
+) -> Vec<KeyCandidate> {
+    let mut candidates: Vec<KeyCandidate> = Vec::new();
+    for keys in row_keys {
+        if candidates.iter().any(|c| &c.keys == keys) {
+            continue;
+        }
+        let distance = expected
+            .iter()
+            .map(|(key, want)| {
+                let have = keys.get(key).unwrap_or(&Value::Null);
+                edit_distance(&key_text(want), &key_text(have))
+            })
+            .sum();
+        candidates.push(KeyCandidate {
+            keys: keys.clone(),
+            distance,
+        });
+    }
+    // Stable sort keeps source order among equal distances.
+    candidates.sort_by_key(|c| c.distance);
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+/// String form of a key value for distance comparison (strings unquoted).
+fn key_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::template::parse_template_str;
+
+    fn make_sources(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn class_inst_template() -> Template {
+        parse_template_str(
+            "sources:\n  classes: {primary: true}\n  inst:\n    join:\n      class_id: classes.id\nto: a\nsubject: b\nbody: c",
+        )
+        .expect("fixture must parse")
+    }
+
+    #[test]
+    fn test_counts_and_unmatched_rows() {
+        let sources = make_sources(&[
+            ("classes", json!([{"id": "M-101"}, {"id": "S-200"}])),
+            (
+                "inst",
+                json!([
+                    {"class_id": "M-101", "name": "A"},
+                    {"class_id": "S-200", "name": "B"},
+                    {"class_id": "H-300", "name": "C"},
+                ]),
+            ),
+        ]);
+
+        let diag = join_diagnostics(&class_inst_template(), &sources).unwrap();
+        let inst = diag.namespace("inst").expect("inst section");
+        assert_eq!(inst.matched_count, 2);
+        assert_eq!(inst.unmatched_count, 1);
+        assert_eq!(inst.unmatched_rows[0].row_index, 2);
+        assert_eq!(inst.unmatched_rows[0].keys["class_id"], json!("H-300"));
+        assert!(inst.missing_matches.is_empty());
+        assert!(!diag.is_clean());
+    }
+
+    #[test]
+    fn test_missing_match_suggests_closest_keys() {
+        let sources = make_sources(&[
+            ("classes", json!([{"id": "M-101"}, {"id": "S-20O"}])),
+            (
+                "inst",
+                json!([
+                    {"class_id": "M-101"},
+                    {"class_id": "S-200"},
+                    {"class_id": "H-300"},
+                ]),
+            ),
+        ]);
+
+        let diag = join_diagnostics(&class_inst_template(), &sources).unwrap();
+        let inst = diag.namespace("inst").unwrap();
+        assert_eq!(inst.missing_matches.len(), 1);
+        let missing = &inst.missing_matches[0];
+        assert_eq!(missing.entry_index, 1);
+        assert_eq!(missing.expected["class_id"], json!("S-20O"));
+        assert_eq!(missing.candidates[0].keys["class_id"], json!("S-200"));
+        assert_eq!(missing.candidates[0].distance, 1);
+        assert!(missing.candidates.len() <= MAX_CANDIDATES);
+    }
+
+    #[test]
+    fn test_many_join_marks_all_matched_rows() {
+        let t = parse_template_str(
+            "sources:\n  classes: {primary: true}\n  students:\n    join:\n      class_id: classes.id\n    many: true\nto: a\nsubject: b\nbody: c",
+        )
+        .unwrap();
+        let sources = make_sources(&[
+            ("classes", json!([{"id": 1}, {"id": 2}])),
+            (
+                "students",
+                json!([{"class_id": 1}, {"class_id": 1}, {"class_id": 3}]),
+            ),
+        ]);
+
+        let diag = join_diagnostics(&t, &sources).unwrap();
+        let students = diag.namespace("students").unwrap();
+        assert_eq!(students.matched_count, 2);
+        assert_eq!(students.unmatched_count, 1);
+        // An empty 1:N join is not a failure, so no suggestions.
+        assert!(students.missing_matches.is_empty());
+    }
+
+    #[test]
+    fn test_clean_when_all_rows_used() {
+        let sources = make_sources(&[
+            ("classes", json!([{"id": 1}, {"id": 2}])),
+            ("inst", json!([{"class_id": 2}, {"class_id": 1}])),
+        ]);
+
+        let diag = join_diagnostics(&class_inst_template(), &sources).unwrap();
+        assert!(diag.is_clean());
+    }
+}
@@ -1,18 +1,38 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
+use serde::Serialize;
 use serde_json::Value;
 
+use crate::env::Environment;
 use crate::join::build_contexts_lenient;
-use crate::render::{render_context, RenderedEmail};
-use crate::template::Template;
+use crate::render::{map_parallel, CompiledTemplate, RenderedEmail};
+use crate::template::{Template, TextPosition};
 use crate::MailnirError;
 
+mod diagnostics;
+mod references;
+
+pub use diagnostics::{
+    join_diagnostics, JoinDiagnostics, KeyCandidate, MissingMatch, NamespaceJoinDiagnostics,
+    UnmatchedRow,
+};
+pub use references::{check_references, ReferenceIssue};
+
 /// One problem found for a specific primary source entry.
 #[derive(Debug, Clone)]
 pub enum ValidationIssue {
     /// A Handlebars template variable could not be resolved (strict mode).
-    UnresolvedVariable { field: String, reason: String },
+    UnresolvedVariable {
+        field: String,
+        reason: String,
+        /// Line/column within the field's string.
+        field_position: Option<TextPosition>,
+        /// Line/column in the template file.
+        file_position: Option<TextPosition>,
+    },
     /// A secondary join found no match or an ambiguous match for this entry.
     JoinFailure {
         namespace: String,
@@ -22,12 +42,34 @@ pub enum ValidationIssue {
     InvalidEmail { field: String, value: String },
     /// An attachment path does not exist on the filesystem.
     AttachmentNotFound { path: PathBuf },
+    /// A local image referenced by the HTML body does not exist.
+    InlineImageNotFound { path: PathBuf },
     /// The `to`, `subject`, or `body` field is empty after rendering.
     RequiredFieldEmpty { field: String },
     /// The stylesheet file referenced in the template does not exist.
     StylesheetNotFound { path: PathBuf },
     /// CSS inlining failed (malformed stylesheet or HTML).
     CssInlineError { reason: String },
+    /// A submitted form value does not satisfy its declared field.
+    InvalidFormValue {
+        namespace: String,
+        field: String,
+        reason: String,
+    },
+    /// `{{env.NAME}}` is used but `NAME` is neither in the process
+    /// environment nor in the template's `.env` file.
+    UndefinedEnvVar { name: String },
+    /// A localized field has no variant for the entry's locale and no
+    /// fallback variant. `locale` is `None` when the entry has no locale.
+    MissingLocaleVariant {
+        field: String,
+        locale: Option<String>,
+    },
+    /// `{{t "id"}}` names a message the entry's catalog lacks. `locale` is the
+    /// catalog's locale, or the entry's own if no catalog matched it.
+    MissingTranslation { locale: Option<String>, id: String },
+    /// A translation catalog could not be parsed.
+    TranslationCatalogError { path: PathBuf, reason: String },
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +111,15 @@ impl ValidationReport {
     }
 }
 
+/// Emitted as each entry finishes validating.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationProgress {
+    pub completed: usize,
+    pub total: usize,
+    pub entry_index: usize,
+    pub is_valid: bool,
+}
+
 /// Run the full validation pipeline over all primary source entries.
 ///
 /// Returns `Err` only on structural failures (e.g. no primary source declared,
@@ -78,40 +129,120 @@ pub fn validate_all(
     sources: &HashMap<String, Value>,
     template_dir: &Path,
 ) -> crate::Result<ValidationReport> {
-    let per_entry_contexts = build_contexts_lenient(template, sources)?;
+    validate_all_with_progress(template, sources, template_dir, None, None)
+}
 
-    let mut entries = Vec::with_capacity(per_entry_contexts.len());
+/// Like [`validate_all`], reporting each entry as it completes and stopping
+/// with `Cancelled` once `cancel` is set.
+///
+/// Entries are rendered in parallel; the report keeps source order.
+pub fn validate_all_with_progress(
+    template: &Template,
+    sources: &HashMap<String, Value>,
+    template_dir: &Path,
+    cancel: Option<Arc<AtomicBool>>,
+    on_progress: Option<Arc<dyn Fn(ValidationProgress) + Send + Sync>>,
+) -> crate::Result<ValidationReport> {
+    let per_entry_contexts = build_contexts_lenient(template, sources)?;
+    let env = Environment::load(template_dir)?;
+    let compiled = CompiledTemplate::new(template, template_dir, &env);
+    // Form values feed every entry, so their problems block every entry.
+    let form_issues = check_form_values(template, sources);
+
+    let total = per_entry_contexts.len();
+    let entries = map_parallel(
+        &per_entry_contexts,
+        cancel.as_deref(),
+        |entry_index, ctx_result| {
+            let mut issues: Vec<ValidationIssue> = form_issues.clone();
+            match ctx_result {
+                Err(join_err) => {
+                    issues.push(issue_from_join_error(join_err));
+                }
+                Ok(context) => match compiled.render(context) {
+                    Err(MailnirError::UndefinedEnvVar { names, .. }) => {
+                        issues.extend(
+                            names
+                                .into_iter()
+                                .map(|name| ValidationIssue::UndefinedEnvVar { name }),
+                        );
+                    }
+                    Err(MailnirError::MissingTranslation { ids, locale }) => {
+                        issues.extend(ids.into_iter().map(|id| {
+                            ValidationIssue::MissingTranslation {
+                                locale: locale.clone(),
+                                id,
+                            }
+                        }));
+                    }
+                    Err(render_err) => {
+                        issues.push(issue_from_render_error(render_err));
+                    }
+                    Ok(rendered) => {
+                        post_render_checks(&rendered, &mut issues);
+                    }
+                },
+            }
+            EntryResult {
+                entry_index,
+                issues,
+            }
+        },
+        |completed, entry_index, entry| {
+            if let Some(progress_fn) = &on_progress {
+                progress_fn(ValidationProgress {
+                    completed,
+                    total,
+                    entry_index,
+                    is_valid: entry.is_valid(),
+                });
+            }
+        },
+    )
+    .ok_or(MailnirError::Cancelled)?;
 
-    for (entry_index, ctx_result) in per_entry_contexts.into_iter().enumerate() {
-        let mut issues: Vec<ValidationIssue> = Vec::new();
+    Ok(ValidationReport { entries })
+}
 
-        match ctx_result {
-            Err(join_err) => {
-                issues.push(issue_from_join_error(join_err));
-            }
-            Ok(context) => match render_context(template, &context, template_dir) {
-                Err(render_err) => {
-                    issues.push(issue_from_render_error(render_err));
-                }
-                Ok(rendered) => {
-                    post_render_checks(&rendered, &mut issues);
+/// Check loaded form source values against their declared `fields:`.
+fn check_form_values(
+    template: &Template,
+    sources: &HashMap<String, Value>,
+) -> Vec<ValidationIssue> {
+    let mut namespaces: Vec<&String> = template.sources.keys().collect();
+    namespaces.sort();
+
+    let mut issues = Vec::new();
+    for namespace in namespaces {
+        let cfg = &template.sources[namespace];
+        let (Some(true), Some(fields), Some(data)) =
+            (cfg.form, &cfg.fields, sources.get(namespace))
+        else {
+            continue;
+        };
+        let rows: &[Value] = match data {
+            Value::Array(rows) => rows,
+            single => std::slice::from_ref(single),
+        };
+        for row in rows {
+            for field in fields {
+                if let Err(reason) = field.check_value(row.get(&field.name)) {
+                    issues.push(ValidationIssue::InvalidFormValue {
+                        namespace: namespace.clone(),
+                        field: field.name.clone(),
+                        reason,
+                    });
                 }
-            },
+            }
         }
-
-        entries.push(EntryResult {
-            entry_index,
-            issues,
-        });
     }
-
-    Ok(ValidationReport { entries })
+    issues
 }
 
-fn issue_from_join_error(err: MailnirError) -> ValidationIssue {
+fn issue_from_join_error(err: &MailnirError) -> ValidationIssue {
     match err {
         MailnirError::JoinMissingMatch { namespace, .. } => ValidationIssue::JoinFailure {
-            namespace,
+            namespace: namespace.clone(),
             detail: JoinFailureDetail::MissingMatch,
         },
         MailnirError::JoinAmbiguousMatch {
@@ -119,26 +250,46 @@ fn issue_from_join_error(err: MailnirError) -> ValidationIssue {
             match_count,
             ..
         } => ValidationIssue::JoinFailure {
-            namespace,
-            detail: JoinFailureDetail::AmbiguousMatch { match_count },
+            namespace: namespace.clone(),
+            detail: JoinFailureDetail::AmbiguousMatch {
+                match_count: *match_count,
+            },
         },
         other => ValidationIssue::UnresolvedVariable {
             field: "<internal>".into(),
             reason: other.to_string(),
+            field_position: None,
+            file_position: None,
         },
     }
 }
 
 fn issue_from_render_error(err: MailnirError) -> ValidationIssue {
     match err {
-        MailnirError::HandlebarsRender { field, reason } => {
-            ValidationIssue::UnresolvedVariable { field, reason }
-        }
+        MailnirError::HandlebarsRender {
+            field,
+            reason,
+            field_position,
+            file_position,
+        } => ValidationIssue::UnresolvedVariable {
+            field,
+            reason,
+            field_position,
+            file_position,
+        },
         MailnirError::StylesheetNotFound { path } => ValidationIssue::StylesheetNotFound { path },
         MailnirError::CssInline { reason } => ValidationIssue::CssInlineError { reason },
+        MailnirError::MissingLocaleVariant { field, locale } => {
+            ValidationIssue::MissingLocaleVariant { field, locale }
+        }
+        MailnirError::TranslationCatalog { path, reason } => {
+            ValidationIssue::TranslationCatalogError { path, reason }
+        }
         other => ValidationIssue::UnresolvedVariable {
             field: "<internal>".into(),
             reason: other.to_string(),
+            field_position: None,
+            file_position: None,
         },
     }
 }
@@ -170,6 +321,13 @@ fn post_render_checks(rendered: &RenderedEmail, issues: &mut Vec<ValidationIssue
             issues.push(ValidationIssue::AttachmentNotFound { path: path.clone() });
         }
     }
+    for image in &rendered.inline_images {
+        if !image.path.exists() {
+            issues.push(ValidationIssue::InlineImageNotFound {
+                path: image.path.clone(),
+            });
+        }
+    }
 }
 
 fn check_required(field: &str, value: &str, issues: &mut Vec<ValidationIssue>) {
@@ -239,6 +397,29 @@ mod tests {
             "expected UnresolvedVariable on subject, got: {:?}",
             e0.issues
         );
+        assert!(
+            e0.issues.iter().any(|i| matches!(
+                i,
+                ValidationIssue::UnresolvedVariable { file_position: Some(p), .. }
+                    if p.line == 4 && p.column == 11
+            )),
+            "expected the subject's file position, got: {:?}",
+            e0.issues
+        );
+    }
+
+    #[test]
+    fn test_unresolved_variable_in_text_body() {
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\nbody: hi\ntext_body: '{{p.nmae}}'",
+        )
+        .unwrap();
+        let sources = make_sources(&[("p", json!([{"name": "Alice"}]))]);
+        let report = validate_all(&t, &sources, Path::new(".")).unwrap();
+        assert!(report.entries[0].issues.iter().any(|i| matches!(
+            i,
+            ValidationIssue::UnresolvedVariable { field, .. } if field == "text_body"
+        )));
     }
 
     // --- Exit criterion: valid email passes, invalid email fails ---
@@ -306,6 +487,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inline_image_not_found() {
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: 'a@b.com'\nsubject: s\nbody: '![Logo](missing_logo_xyz.png)'",
+        )
+        .unwrap();
+        let sources = make_sources(&[("p", json!([{"dummy": 1}]))]);
+
+        let report = validate_all(&t, &sources, Path::new(".")).unwrap();
+        assert!(
+            report.entries[0].issues.iter().any(|i| matches!(
+                i,
+                ValidationIssue::InlineImageNotFound { path }
+                if path.ends_with("missing_logo_xyz.png")
+            )),
+            "expected InlineImageNotFound, got: {:?}",
+            report.entries[0].issues
+        );
+    }
+
     // --- Exit criterion: empty `to` → required field error ---
 
     #[test]
@@ -455,4 +656,151 @@ mod tests {
             report.entries[0].issues
         );
     }
+
+    // --- Environment variables: .env values resolve, undefined ones are distinct ---
+
+    #[test]
+    fn test_env_vars_from_dotenv_and_undefined() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(crate::env::DOTENV_FILE),
+            "MAILNIR_TEST_PORTAL=https://staging.example.com\n",
+        )
+        .unwrap();
+        let defined = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nsubject: s\nbody: '{{env.MAILNIR_TEST_PORTAL}}'\nbody_format: text",
+        )
+        .unwrap();
+        let sources = make_sources(&[("p", json!([{"dummy": 1}]))]);
+        let report = validate_all(&defined, &sources, dir.path()).unwrap();
+        assert!(report.is_valid(), "got: {:?}", report.entries[0].issues);
+
+        let undefined = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nsubject: '{{env.MAILNIR_TEST_UNDEFINED}}'\nbody: b\nbody_format: text",
+        )
+        .unwrap();
+        let report = validate_all(&undefined, &sources, dir.path()).unwrap();
+        let issues = &report.entries[0].issues;
+        assert!(
+            matches!(
+                issues.as_slice(),
+                [ValidationIssue::UndefinedEnvVar { name }] if name == "MAILNIR_TEST_UNDEFINED"
+            ),
+            "got: {issues:?}"
+        );
+    }
+
+    // --- Localization: entries whose locale has no variant are flagged ---
+
+    #[test]
+    fn test_missing_locale_variant_flagged_per_entry() {
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nsubject:\n  en: Hi\n  de: Hallo\nbody: b\nbody_format: text",
+        )
+        .unwrap();
+        let sources = make_sources(&[("p", json!([{"lang": "de"}, {"lang": "fr"}]))]);
+        let report = validate_all(&t, &sources, Path::new(".")).unwrap();
+        assert!(report.entries[0].is_valid());
+        assert!(
+            matches!(
+                report.entries[1].issues.as_slice(),
+                [ValidationIssue::MissingLocaleVariant { field, locale: Some(l) }]
+                    if field == "subject" && l == "fr"
+            ),
+            "got: {:?}",
+            report.entries[1].issues
+        );
+    }
+
+    // --- Translations: missing message ids are listed per locale ---
+
+    #[test]
+    fn test_missing_translations_list_locale_and_id() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("en.ftl"),
+            "greeting = Hello { $name }\nfarewell = Bye\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("de.po"),
+            "msgid \"greeting\"\nmsgstr \"Hallo {{name}}\"\n",
+        )
+        .unwrap();
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: a@b.com\nlocale_field: p.lang\nfallback_locale: en\ntranslations: {en: en.ftl, de: de.po}\nsubject: '{{t \"greeting\" name=p.name}}'\nbody: '{{t \"farewell\"}} {{t \"signature\"}}'\nbody_format: text",
+        )
+        .unwrap();
+        let sources = make_sources(&[(
+            "p",
+            json!([{"lang": "en", "name": "Ada"}, {"lang": "de", "name": "Bo"}]),
+        )]);
+        let report = validate_all(&t, &sources, dir.path()).unwrap();
+        let ids = |entry: usize| -> Vec<(Option<String>, String)> {
+            report.entries[entry]
+                .issues
+                .iter()
+                .filter_map(|i| match i {
+                    ValidationIssue::MissingTranslation { locale, id } => {
+                        Some((locale.clone(), id.clone()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(ids(0), [(Some("en".to_string()), "signature".to_string())]);
+        assert_eq!(
+            ids(1),
+            [
+                (Some("de".to_string()), "farewell".to_string()),
+                (Some("de".to_string()), "signature".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_progress_reported_and_order_kept() {
+        let t = simple_template("{{p.email}}", "s", "b");
+        let rows: Vec<Value> = (0..50)
+            .map(|i| json!({"email": if i % 7 == 0 { "bad".to_string() } else { format!("u{i}@x.com") }}))
+            .collect();
+        let sources = make_sources(&[("p", Value::Array(rows))]);
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let report = validate_all_with_progress(
+            &t,
+            &sources,
+            Path::new("."),
+            None,
+            Some(Arc::new(move |p: ValidationProgress| {
+                sink.lock().unwrap().push(p)
+            })),
+        )
+        .unwrap();
+
+        let indices: Vec<usize> = report.entries.iter().map(|e| e.entry_index).collect();
+        assert_eq!(indices, (0..50).collect::<Vec<_>>());
+        let invalid: Vec<usize> = report.invalid_entries().map(|e| e.entry_index).collect();
+        assert_eq!(invalid, vec![0, 7, 14, 21, 28, 35, 42, 49]);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 50);
+        assert!(seen
+            .iter()
+            .enumerate()
+            .all(|(i, p)| p.completed == i + 1 && p.total == 50));
+        assert!(seen
+            .iter()
+            .all(|p| p.is_valid == report.entries[p.entry_index].is_valid()));
+    }
+
+    #[test]
+    fn test_cancelled_validation_stops() {
+        let t = simple_template("a@b.com", "s", "b");
+        let sources = make_sources(&[("p", json!([{}, {}]))]);
+        let cancel = Arc::new(AtomicBool::new(true));
+        let err = validate_all_with_progress(&t, &sources, Path::new("."), Some(cancel), None)
+            .unwrap_err();
+        assert!(matches!(err, MailnirError::Cancelled));
+    }
 }
@@ -0,0 +1,276 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::Value;
+
+use crate::suggest::closest;
+use crate::template::{infer_form_fields, Template, VARS_NAMESPACE};
+
+/// A template reference that does not line up with the loaded data.
+///
+/// Unlike [`super::ValidationIssue`], these concern the template as a whole
+/// rather than one primary entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReferenceIssue {
+    /// A join key column is absent from every row of the joined source.
+    UnknownJoinKey {
+        namespace: String,
+        join_key: String,
+        suggestion: Option<String>,
+    },
+    /// The field a join refers to is absent from every row of the referenced namespace.
+    UnknownJoinRefField {
+        namespace: String,
+        join_key: String,
+        ref_namespace: String,
+        ref_field: String,
+        suggestion: Option<String>,
+    },
+    /// A `namespace.field` used in the template is absent from every row.
+    UnknownField {
+        namespace: String,
+        field: String,
+        suggestion: Option<String>,
+    },
+    /// A `vars.name` used in the template is not declared under `vars:`.
+    UnknownVar {
+        name: String,
+        suggestion: Option<String>,
+    },
+}
+
+/// Check join keys and `namespace.field` references against the loaded data.
+///
+/// A field counts as existing when at least one row has it or the source
+/// declares it under `computed:`. Namespaces with no
+/// data in `sources` are skipped, as are field references into global and
+/// `many: true` namespaces (those resolve to arrays, not rows).
+pub fn check_references(
+    template: &Template,
+    sources: &HashMap<String, Value>,
+) -> Vec<ReferenceIssue> {
+    let fields: HashMap<&str, BTreeSet<&str>> = sources
+        .iter()
+        .map(|(ns, data)| {
+            let mut names = field_names(data);
+            if let Some(computed) = template.sources.get(ns).and_then(|c| c.computed.as_ref()) {
+                names.extend(computed.keys().map(String::as_str));
+            }
+            (ns.as_str(), names)
+        })
+        .collect();
+
+    let mut namespaces: Vec<&String> = template.sources.keys().collect();
+    namespaces.sort();
+
+    let mut issues = Vec::new();
+
+    for &namespace in &namespaces {
+        let Some(join_map) = &template.sources[namespace].join else {
+            continue;
+        };
+        let mut join_keys: Vec<&String> = join_map.keys().collect();
+        join_keys.sort();
+
+        for join_key in join_keys {
+            if let Some(own_fields) = fields.get(namespace.as_str()) {
+                if !own_fields.contains(join_key.as_str()) {
+                    issues.push(ReferenceIssue::UnknownJoinKey {
+                        namespace: namespace.clone(),
+                        join_key: join_key.clone(),
+                        suggestion: closest(join_key, own_fields.iter().copied()),
+                    });
+                }
+            }
+
+            for ref_value in join_map[join_key].refs() {
+                let Some((ref_namespace, ref_field)) = ref_value.split_once('.') else {
+                    continue;
+                };
+                if let Some(ref_fields) = fields.get(ref_namespace) {
+                    if !ref_fields.contains(ref_field) {
+                        issues.push(ReferenceIssue::UnknownJoinRefField {
+                            namespace: namespace.clone(),
+                            join_key: join_key.clone(),
+                            ref_namespace: ref_namespace.to_string(),
+                            ref_field: ref_field.to_string(),
+                            suggestion: closest(ref_field, ref_fields.iter().copied()),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    for &namespace in &namespaces {
+        let cfg = &template.sources[namespace];
+        let resolves_to_row =
+            cfg.primary == Some(true) || (cfg.join.is_some() && cfg.many != Some(true));
+        if !resolves_to_row {
+            continue;
+        }
+        let Some(ns_fields) = fields.get(namespace.as_str()) else {
+            continue;
+        };
+        for field in infer_form_fields(template, namespace) {
+            if !ns_fields.contains(field.as_str()) {
+                issues.push(ReferenceIssue::UnknownField {
+                    namespace: namespace.clone(),
+                    suggestion: closest(&field, ns_fields.iter().copied()),
+                    field,
+                });
+            }
+        }
+    }
+
+    let declared: Vec<&str> = template
+        .vars
+        .iter()
+        .flat_map(|vars| vars.keys().map(String::as_str))
+        .collect();
+    for name in infer_form_fields(template, VARS_NAMESPACE) {
+        if !declared.contains(&name.as_str()) {
+            issues.push(ReferenceIssue::UnknownVar {
+                suggestion: closest(&name, declared.iter().copied()),
+                name,
+            });
+        }
+    }
+
+    issues
+}
+
+/// Union of the keys of every object row in a source.
+fn field_names(data: &Value) -> BTreeSet<&str> {
+    let rows: &[Value] = match data {
+        Value::Array(rows) => rows,
+        single => std::slice::from_ref(single),
+    };
+    rows.iter()
+        .filter_map(Value::as_object)
+        .flat_map(|obj| obj.keys().map(String::as_str))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::template::parse_template_str;
+
+    fn make_sources(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_template_has_no_issues() {
+        let t = parse_template_str(
+            "sources:\n  classes: {primary: true}\n  inst:\n    join:\n      class_id: classes.id\nto: '{{inst.email}}'\nsubject: '{{classes.name}}'\nbody: c",
+        )
+        .unwrap();
+        let sources = make_sources(&[
+            ("classes", json!([{"id": 1, "name": "Math"}])),
+            ("inst", json!([{"class_id": 1, "email": "a@b.com"}])),
+        ]);
+        assert!(check_references(&t, &sources).is_empty());
+    }
+
+    #[test]
+    fn test_misspelled_join_key_suggests_column() {
+        let t = parse_template_str(
+            "sources:\n  classes: {primary: true}\n  inst:\n    join:\n      clas_id: classes.id\nto: a\nsubject: b\nbody: c",
+        )
+        .unwrap();
+        let sources = make_sources(&[
+            ("classes", json!([{"id": 1}])),
+            ("inst", json!([{"class_id": 1}])),
+        ]);
+        assert_eq!(
+            check_references(&t, &sources),
+            vec![ReferenceIssue::UnknownJoinKey {
+                namespace: "inst".into(),
+                join_key: "clas_id".into(),
+                suggestion: Some("class_id".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_misspelled_join_ref_field() {
+        let t = parse_template_str(
+            "sources:\n  classes: {primary: true}\n  inst:\n    join:\n      class_id: classes.idd\nto: a\nsubject: b\nbody: c",
+        )
+        .unwrap();
+        let sources = make_sources(&[
+            ("classes", json!([{"id": 1}])),
+            ("inst", json!([{"class_id": 1}])),
+        ]);
+        assert_eq!(
+            check_references(&t, &sources),
+            vec![ReferenceIssue::UnknownJoinRefField {
+                namespace: "inst".into(),
+                join_key: "class_id".into(),
+                ref_namespace: "classes".into(),
+                ref_field: "idd".into(),
+                suggestion: Some("id".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unknown_template_field() {
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\nto: '{{p.emial}}'\nsubject: '{{p.name}}'\nbody: c",
+        )
+        .unwrap();
+        // Field present in only one row still counts as existing.
+        let sources = make_sources(&[("p", json!([{"email": "a@b.com"}, {"name": "Bo"}]))]);
+        assert_eq!(
+            check_references(&t, &sources),
+            vec![ReferenceIssue::UnknownField {
+                namespace: "p".into(),
+                field: "emial".into(),
+                suggestion: Some("email".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unloaded_and_array_namespaces_skipped() {
+        let t = parse_template_str(
+            "sources:\n  p: {primary: true}\n  cfg: {}\n  s:\n    join:\n      pid: p.id\n    many: true\nto: '{{cfg.length}}'\nsubject: '{{s.length}}'\nbody: '{{p.nope}}'",
+        )
+        .unwrap();
+        let sources = make_sources(&[("cfg", json!([{"a": 1}])), ("s", json!([{"pid": 1}]))]);
+        // `p` is not loaded; `cfg` and `s` resolve to arrays.
+        assert!(check_references(&t, &sources).is_empty());
+    }
+
+    #[test]
+    fn test_computed_fields_count_as_existing() {
+        let t = parse_template_str(
+            "sources:\n  p:\n    primary: true\n    computed:\n      full: '{{a}}'\nto: '{{p.full}}'\nsubject: b\nbody: c",
+        )
+        .unwrap();
+        let sources = make_sources(&[("p", json!([{"a": 1}]))]);
+        assert!(check_references(&t, &sources).is_empty());
+    }
+
+    #[test]
+    fn test_undeclared_var() {
+        let t = parse_template_str(
+            "vars:\n  deadline: May 1\nsources:\n  p: {primary: true}\nto: a\nsubject: '{{vars.deadlin}}'\nbody: c",
+        )
+        .unwrap();
+        assert_eq!(
+            check_references(&t, &HashMap::new()),
+            vec![ReferenceIssue::UnknownVar {
+                name: "deadlin".into(),
+                suggestion: Some("deadline".into()),
+            }]
+        );
+    }
+}
@@ -11,6 +11,7 @@ use mailnir_lib::join::build_contexts_lenient;
 use mailnir_lib::render::render_context;
 use mailnir_lib::template::{infer_form_fields, parse_template, parse_template_str};
 use mailnir_lib::validate::validate_all;
+use mailnir_lib::ValidationIssue;
 
 fn make_sources(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
     pairs
@@ -98,3 +99,56 @@ fn test_form_as_joined_source() {
 
     assert_eq!(rendered.subject, "Welcome");
 }
+
+/// Declared fields: typed values render, and bad values are reported per field.
+#[test]
+fn test_declared_form_fields_validate_and_render() {
+    let t = parse_template_str(
+        "sources:\n  r:\n    primary: true\n    form: true\n    fields:\n\
+         \x20     - {name: email, type: email, required: true}\n\
+         \x20     - {name: seats, type: number}\n\
+         \x20     - {name: vip, type: checkbox}\n\
+         \x20     - {name: tags, type: list}\n\
+         to: '{{r.email}}'\n\
+         subject: '{{r.seats}} seats{{#if r.vip}} (VIP){{/if}}'\n\
+         body: '{{#each r.tags}}[{{this}}]{{/each}}'\n\
+         body_format: text",
+    )
+    .expect("template must parse");
+
+    let good = make_sources(&[(
+        "r",
+        json!([{"email": "a@b.com", "seats": 3, "vip": true, "tags": ["x", "y"]}]),
+    )]);
+    assert!(validate_all(&t, &good, Path::new(".")).unwrap().is_valid());
+    let ctx = build_contexts_lenient(&t, &good)
+        .unwrap()
+        .remove(0)
+        .unwrap();
+    let rendered = render_context(&t, &ctx, Path::new(".")).unwrap();
+    assert_eq!(rendered.subject, "3 seats (VIP)");
+    assert_eq!(rendered.text_body, "[x][y]");
+
+    let bad = make_sources(&[(
+        "r",
+        json!([{"email": "", "seats": "many", "vip": false, "tags": []}]),
+    )]);
+    let report = validate_all(&t, &bad, Path::new(".")).unwrap();
+    let form_issues: Vec<(String, String)> = report.entries[0]
+        .issues
+        .iter()
+        .filter_map(|i| match i {
+            ValidationIssue::InvalidFormValue { field, reason, .. } => {
+                Some((field.clone(), reason.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        form_issues,
+        vec![
+            ("email".to_string(), "is required".to_string()),
+            ("seats".to_string(), "'many' is not a number".to_string()),
+        ]
+    );
+}
@@ -92,3 +92,32 @@ fn test_csv_latin1_fallback() {
         "Expected 'é' in decoded Latin-1 name, got: {name}"
     );
 }
+
+#[test]
+fn test_migrate_legacy_template_file() {
+    use mailnir_lib::template::{migrate_template_file, parse_template_with_report};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("legacy.mailnir.yml");
+    std::fs::copy(fixtures_templates().join("legacy_v1.mailnir.yml"), &path).unwrap();
+
+    let (_, report) = parse_template_with_report(&path).unwrap();
+    let report = report.expect("legacy template should be upgraded in memory");
+    assert_eq!(report.from_version, 1);
+    assert_eq!(report.changes.len(), 1);
+
+    let written = migrate_template_file(&path).unwrap().unwrap();
+    assert_eq!(written, report);
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.starts_with("# Written before templates carried a version.\nversion: 2\n"));
+    assert!(content.contains("x-signature: &signature |"));
+
+    let (template, report) = parse_template_with_report(&path).unwrap();
+    assert!(report.is_none());
+    assert!(template
+        .body
+        .as_single()
+        .unwrap()
+        .starts_with("Best regards,"));
+    assert!(migrate_template_file(&path).unwrap().is_none());
+}
@@ -38,6 +38,7 @@ fn make_email(index: usize) -> RenderedEmail {
         html_body: Some(format!("<p>Entry {index}</p>")),
         text_body: format!("Entry {index}"),
         attachments: vec![],
+        inline_images: vec![],
     }
 }
 
@@ -89,6 +90,7 @@ async fn test_send_with_cc_and_subject() {
         html_body: None,
         text_body: "Plain text body.".to_string(),
         attachments: vec![],
+        inline_images: vec![],
     };
     let profile = mailhog_profile(1);
     let creds = no_credentials();
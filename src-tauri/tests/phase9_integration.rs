@@ -74,6 +74,7 @@ async fn test_send_email_with_2_attachments_correct_mime() {
         html_body: Some("<p>See attachments</p>".to_string()),
         text_body: "See attachments".to_string(),
         attachments: vec![tmp_pdf.path().to_path_buf(), tmp_png.path().to_path_buf()],
+        inline_images: vec![],
     };
 
     let report = send_all(&[email], &mailhog_profile(1), &no_credentials()).await;
@@ -109,6 +110,7 @@ async fn test_cc_bcc_fields_populated() {
         html_body: Some("<p>CC/BCC test</p>".to_string()),
         text_body: "CC/BCC test".to_string(),
         attachments: vec![],
+        inline_images: vec![],
     };
 
     let report = send_all(&[email], &mailhog_profile(1), &no_credentials()).await;
@@ -152,6 +154,7 @@ async fn test_50_entry_batch_2_failures_retry() {
             html_body: Some(format!("<p>Entry {i}</p>")),
             text_body: format!("Entry {i}"),
             attachments: vec![],
+            inline_images: vec![],
         })
         .collect();
 
@@ -211,6 +214,7 @@ async fn test_cancel_mid_batch() {
             html_body: Some(format!("<p>Cancel {i}</p>")),
             text_body: format!("Cancel {i}"),
             attachments: vec![],
+            inline_images: vec![],
         })
         .collect();
 
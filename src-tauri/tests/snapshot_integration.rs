@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use mailnir_lib::snapshot::{run_template_test_file, update_snapshots, SnapshotFailure};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join("templates")
+        .join(name)
+}
+
+/// Copy the snapshot fixture and its test file into a temporary directory.
+fn copy_fixture(dir: &Path) -> PathBuf {
+    for name in ["snapshot.mailnir.yml", "snapshot.mailnir.test.yml"] {
+        std::fs::copy(fixture(name), dir.join(name)).unwrap();
+    }
+    dir.join("snapshot.mailnir.yml")
+}
+
+#[test]
+fn test_fixture_snapshots_pass() {
+    let report = run_template_test_file(&fixture("snapshot.mailnir.yml")).unwrap();
+    assert_eq!(report.outcomes.len(), 2);
+    assert!(report.passed(), "{report}");
+}
+
+#[test]
+fn test_changed_template_reports_diff_then_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = copy_fixture(dir.path());
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, content.replacen("Welcome to", "Welcome aboard", 1)).unwrap();
+
+    let report = run_template_test_file(&path).unwrap();
+    assert_eq!(report.failed_count(), 2);
+    assert!(report.outcomes[1].failures.iter().any(|f| matches!(
+        f,
+        SnapshotFailure::Mismatch { entry: 1, field, diff }
+            if field == "subject"
+                && diff == "- Welcome to Biology\n+ Welcome aboard Biology\n"
+    )));
+
+    update_snapshots(&path).unwrap();
+    assert!(run_template_test_file(&path).unwrap().passed());
+    let test_file = std::fs::read_to_string(dir.path().join("snapshot.mailnir.test.yml")).unwrap();
+    assert!(test_file.contains("Welcome aboard Biology"));
+}
+
+#[test]
+fn test_cli_exit_status() {
+    let cli = env!("CARGO_BIN_EXE_mailnir-cli");
+    let ok = Command::new(cli)
+        .args(["test"])
+        .arg(fixture("snapshot.mailnir.yml"))
+        .output()
+        .unwrap();
+    assert!(ok.status.success());
+    assert!(String::from_utf8_lossy(&ok.stdout).contains("2 tests, 2 passed, 0 failed"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = copy_fixture(dir.path());
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, content.replacen("starts soon", "starts today", 1)).unwrap();
+    let failed = Command::new(cli).arg("test").arg(&path).output().unwrap();
+    assert_eq!(failed.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&failed.stdout).contains("    + Your class Algebra starts today.")
+    );
+
+    let usage = Command::new(cli).output().unwrap();
+    assert_eq!(usage.status.code(), Some(2));
+}
@@ -1,3 +1,4 @@
+import { listen } from "@tauri-apps/api/event";
 import { confirm, open, save } from "@tauri-apps/plugin-dialog";
 import { useCallback, useEffect, useMemo, useRef, useState } from "react";
 import DataPanel, { type SourceState } from "@/components/DataPanel/index";
@@ -14,10 +15,21 @@ import {
 } from "@/components/ui/resizable";
 import { TooltipProvider } from "@/components/ui/tooltip";
 import {
+	cancelValidate,
 	createTemplate,
+	exportBundle,
+	exportTemplateSchema,
+	type FormFieldSchema,
+	type FormValue,
 	getDataFields,
 	getFormFields,
 	getSmtpProfiles,
+	getTemplateVariables,
+	type ImportReport,
+	importBundle,
+	type Localized,
+	type MigrationReport,
+	migrateTemplateFile,
 	type PreviewRenderedEmail,
 	type PreviewValidation,
 	parseTemplate,
@@ -32,12 +44,21 @@ import {
 	saveTemplate,
 	type TemplateFields,
 	type TemplateInfo,
+	type ValidateProgressEvent,
 } from "@/lib/ipc";
 
 function isCsvPath(path: string): boolean {
 	return path.toLowerCase().endsWith(".csv");
 }
 
+/** Starting value of a form input: its default, or an empty value of its type. */
+function initialFormValue(field: FormFieldSchema): FormValue {
+	if (field.default !== undefined) return field.default;
+	if (field.type === "checkbox") return false;
+	if (field.type === "list") return [];
+	return "";
+}
+
 function slotsToSpecs(slots: SourceSlot[]): SourceSpec[] {
 	return slots.map((s) => ({
 		namespace: s.namespace,
@@ -45,6 +66,8 @@ function slotsToSpecs(slots: SourceSlot[]): SourceSpec[] {
 		join: Object.keys(s.join).length > 0 ? s.join : undefined,
 		many: s.is_many || undefined,
 		form: s.is_form || undefined,
+		fields: s.fields ?? undefined,
+		computed: s.computed ?? undefined,
 	}));
 }
 
@@ -71,6 +94,27 @@ export default function App() {
 		Record<string, string[]>
 	>({});
 
+	/** Field names per namespace that the template itself already references. */
+	const [referencedFields, setReferencedFields] = useState<
+		Record<string, string[]>
+	>({});
+	/** Autocomplete fields: loaded data fields plus template references. */
+	const completionFields = useMemo(() => {
+		const merged: Record<string, string[]> = {};
+		for (const ns of new Set([
+			...Object.keys(namespaceFields),
+			...Object.keys(referencedFields),
+		])) {
+			merged[ns] = [
+				...new Set([
+					...(namespaceFields[ns] ?? []),
+					...(referencedFields[ns] ?? []),
+				]),
+			].sort();
+		}
+		return merged;
+	}, [namespaceFields, referencedFields]);
+
 	// ── Save state ──────────────────────────────────────────────────────────────
 	const [saveStatus, setSaveStatus] = useState<
 		"idle" | "saving" | "saved" | "error"
@@ -95,6 +139,10 @@ export default function App() {
 		useState<PreviewRenderedEmail | null>(null);
 	const [previewLoading, setPreviewLoading] = useState(false);
 	const [previewError, setPreviewError] = useState<string | null>(null);
+	const [previewProgress, setPreviewProgress] = useState<{
+		completed: number;
+		total: number;
+	} | null>(null);
 
 	// ── Derived ─────────────────────────────────────────────────────────────────
 	const namespaces = useMemo(
@@ -142,6 +190,22 @@ export default function App() {
 	const previewIndexRef = useRef(previewCurrentIndex);
 	previewIndexRef.current = previewCurrentIndex;
 
+	// Follow validation progress for large sources.
+	useEffect(() => {
+		const promise = listen<ValidateProgressEvent>(
+			"validate-progress",
+			(event) => {
+				setPreviewProgress({
+					completed: event.payload.completed,
+					total: event.payload.total,
+				});
+			},
+		);
+		return () => {
+			promise.then((unlisten) => unlisten());
+		};
+	}, []);
+
 	// Auto-refresh preview when inputs change (debounced).
 	useEffect(() => {
 		if (!templatePath || !templateFields || !allSourcesLoaded) {
@@ -151,18 +215,24 @@ export default function App() {
 		}
 
 		const controller = new AbortController();
+		let validating = false;
 		const timer = setTimeout(async () => {
 			setPreviewLoading(true);
 			setPreviewError(null);
+			setPreviewProgress(null);
 			try {
 				const specs = buildSourceFileSpecs();
+				validating = true;
 				const validation = await previewValidate(
 					templatePath,
 					templateFields,
 					specs,
-				);
+				).finally(() => {
+					validating = false;
+				});
 				if (controller.signal.aborted) return;
 				setPreviewValidation(validation);
+				setPreviewProgress(null);
 
 				if (validation.entry_count > 0) {
 					const idx = Math.min(
@@ -195,6 +265,8 @@ export default function App() {
 		return () => {
 			clearTimeout(timer);
 			controller.abort();
+			// Stop a superseded validation instead of waiting for it.
+			if (validating) cancelValidate();
 		};
 	}, [templatePath, templateFields, allSourcesLoaded, buildSourceFileSpecs]);
 
@@ -233,6 +305,7 @@ export default function App() {
 	const resetState = () => {
 		setSourcesState({});
 		setNamespaceFields({});
+		setReferencedFields({});
 		setSaveStatus("idle");
 		setSaveError(null);
 		setIsDirty(false);
@@ -252,7 +325,11 @@ export default function App() {
 			bcc: null,
 			subject: "",
 			body: "",
+			text_body: null,
+			preheader: null,
 			attachments: null,
+			locale_field: null,
+			fallback_locale: null,
 			body_format: null,
 			stylesheet: null,
 			style: null,
@@ -269,26 +346,53 @@ export default function App() {
 					join: {},
 					is_many: false,
 					is_form: false,
+					fields: null,
+					computed: null,
 				},
 			],
 			fields: defaultFields,
+			migration: null,
 		});
 		setTemplateFields(defaultFields);
 		setTemplateLoadId((n) => n + 1);
 		resetState();
 	};
 
-	const handleOpenTemplate = async () => {
-		if (!(await confirmDiscardChanges())) return;
-		const selected = await open({
-			multiple: false,
-			filters: [{ name: "Mailnir Template", extensions: ["yml", "yaml"] }],
+	/** Ask to rewrite an older template file in the current format. */
+	const offerMigration = async (path: string, report: MigrationReport) => {
+		const changes = report.changes.map((c) =>
+			c.namespace
+				? `sources.${c.namespace}.${c.from} → ${c.to}`
+				: `${c.from} → ${c.to}`,
+		);
+		const upgrade = await confirm(
+			`This template uses format version ${report.from_version}. Upgrade the file to version ${report.to_version}?\n\n${changes.join("\n")}`,
+			{ title: "Upgrade Template", kind: "info" },
+		);
+		if (upgrade) await migrateTemplateFile(path);
+	};
+
+	const handleExportSchema = async () => {
+		const selected = await save({
+			defaultPath: "mailnir-template.schema.json",
+			filters: [{ name: "JSON Schema", extensions: ["json"] }],
 		});
-		if (typeof selected !== "string") return;
+		if (!selected) return;
+		try {
+			await exportTemplateSchema(selected);
+		} catch (e) {
+			setTemplateError(String(e));
+		}
+	};
 
+	/** Open the template at `selected`; false (with the error shown) on failure. */
+	const loadTemplate = async (selected: string): Promise<boolean> => {
 		setTemplateError(null);
 		try {
 			const info = await parseTemplate(selected);
+			if (info.migration && info.migration.changes.length > 0) {
+				await offerMigration(selected, info.migration);
+			}
 			setTemplatePath(selected);
 			setTemplateInfo(info);
 			setTemplateFields(info.fields);
@@ -304,8 +408,8 @@ export default function App() {
 						info.fields,
 						slot.namespace,
 					);
-					const values: Record<string, string> = {};
-					for (const f of fields) values[f] = "";
+					const values: Record<string, FormValue> = {};
+					for (const f of fields) values[f.name] = initialFormValue(f);
 					initialSources[slot.namespace] = {
 						path: "",
 						csvPreview: null,
@@ -315,7 +419,7 @@ export default function App() {
 						formFields: fields,
 						formValues: values,
 					};
-					initialFields[slot.namespace] = fields;
+					initialFields[slot.namespace] = fields.map((f) => f.name);
 				}
 			}
 			setSourcesState(initialSources);
@@ -328,8 +432,90 @@ export default function App() {
 			setPreviewRendered(null);
 			setPreviewCurrentIndex(0);
 			setPreviewError(null);
+			return true;
 		} catch (err) {
 			setTemplateError(String(err));
+			return false;
+		}
+	};
+
+	const handleOpenTemplate = async () => {
+		if (!(await confirmDiscardChanges())) return;
+		const selected = await open({
+			multiple: false,
+			filters: [{ name: "Mailnir Template", extensions: ["yml", "yaml"] }],
+		});
+		if (typeof selected !== "string") return;
+		await loadTemplate(selected);
+	};
+
+	const handleExportBundle = async () => {
+		if (!templatePath) return;
+		if (isDirty) {
+			setTemplateError("Save the template before exporting a bundle.");
+			return;
+		}
+		const specs = buildSourceFileSpecs();
+		const hasDataFiles = specs.some((s) => !s.form_data && s.path);
+		const includeData =
+			hasDataFiles &&
+			(await confirm("Include the bound data files in the bundle?", {
+				title: "Export Bundle",
+				kind: "info",
+				okLabel: "Include Data",
+				cancelLabel: "Template Only",
+			}));
+		const name = templatePath.split(/[\\/]/).pop() ?? "template";
+		const selected = await save({
+			defaultPath: `${name.replace(/\.mailnir\.ya?ml$|\.ya?ml$/, "")}.mailnir.zip`,
+			filters: [{ name: "Mailnir Bundle", extensions: ["zip"] }],
+		});
+		if (!selected) return;
+		try {
+			const manifest = await exportBundle(
+				templatePath,
+				specs,
+				includeData,
+				selected,
+			);
+			setTemplateError(
+				manifest.missing.length > 0
+					? `Bundle written without missing files: ${manifest.missing.map((m) => m.reference).join(", ")}`
+					: null,
+			);
+		} catch (e) {
+			setTemplateError(String(e));
+		}
+	};
+
+	const handleImportBundle = async () => {
+		if (!(await confirmDiscardChanges())) return;
+		const bundle = await open({
+			multiple: false,
+			filters: [{ name: "Mailnir Bundle", extensions: ["zip"] }],
+		});
+		if (typeof bundle !== "string") return;
+		const destDir = await open({ directory: true, title: "Extract Bundle To" });
+		if (typeof destDir !== "string") return;
+
+		let report: ImportReport;
+		try {
+			report = await importBundle(bundle, destDir);
+		} catch (e) {
+			setTemplateError(String(e));
+			return;
+		}
+		if (!(await loadTemplate(report.template_path))) return;
+		for (const [namespace, path] of Object.entries(report.data_files)) {
+			await handleFileSelect(namespace, path);
+		}
+		const problems = [
+			...report.missing.map((m) => `missing ${m.reference}`),
+			...report.corrupted.map((p) => `checksum mismatch in ${p}`),
+			...report.unresolved.map((r) => `fix attachment path ${r}`),
+		];
+		if (problems.length > 0) {
+			setTemplateError(`Imported with problems: ${problems.join("; ")}`);
 		}
 	};
 
@@ -463,7 +649,7 @@ export default function App() {
 	const handleFormValueChange = (
 		namespace: string,
 		field: string,
-		value: string,
+		value: FormValue,
 	) => {
 		setSourcesState((prev) => {
 			const state = prev[namespace];
@@ -478,6 +664,36 @@ export default function App() {
 		});
 	};
 
+	// Collect referenced fields for autocomplete when template fields change.
+	useEffect(() => {
+		if (!templatePath || !templateFields) return;
+		const controller = new AbortController();
+		const timer = setTimeout(async () => {
+			try {
+				const refs = await getTemplateVariables(templatePath, templateFields);
+				if (controller.signal.aborted) return;
+				const byNamespace: Record<string, Set<string>> = {};
+				for (const ref of refs) {
+					if (ref.path.length === 0) continue;
+					byNamespace[ref.namespace] ??= new Set();
+					byNamespace[ref.namespace].add(ref.path[0]);
+				}
+				setReferencedFields(
+					Object.fromEntries(
+						Object.entries(byNamespace).map(([ns, set]) => [ns, [...set]]),
+					),
+				);
+			} catch {
+				// Non-critical: autocomplete falls back to loaded data fields
+			}
+		}, 500);
+
+		return () => {
+			clearTimeout(timer);
+			controller.abort();
+		};
+	}, [templatePath, templateFields]);
+
 	// Re-infer form fields when template fields change.
 	useEffect(() => {
 		if (!templatePath || !templateFields || !templateInfo) return;
@@ -498,8 +714,9 @@ export default function App() {
 						const state = prev[slot.namespace];
 						if (!state) return prev;
 						const oldValues = state.formValues ?? {};
-						const newValues: Record<string, string> = {};
-						for (const f of fields) newValues[f] = oldValues[f] ?? "";
+						const newValues: Record<string, FormValue> = {};
+						for (const f of fields)
+							newValues[f.name] = oldValues[f.name] ?? initialFormValue(f);
 						return {
 							...prev,
 							[slot.namespace]: {
@@ -511,7 +728,7 @@ export default function App() {
 					});
 					setNamespaceFields((prev) => ({
 						...prev,
-						[slot.namespace]: fields,
+						[slot.namespace]: fields.map((f) => f.name),
 					}));
 				} catch {
 					// Non-critical: field inference failure doesn't block anything
@@ -527,7 +744,7 @@ export default function App() {
 
 	const handleFieldChange = (
 		field: keyof TemplateFields,
-		value: string | null,
+		value: Localized | null,
 	) => {
 		setTemplateFields((prev) => (prev ? { ...prev, [field]: value } : prev));
 		setSaveStatus("idle");
@@ -599,7 +816,17 @@ export default function App() {
 		setSaveError(null);
 		try {
 			const specs = slotsToSpecs(templateInfo?.sources ?? []);
-			await saveTemplate(templatePath, templateFields, specs);
+			try {
+				await saveTemplate(templatePath, templateFields, specs);
+			} catch (err) {
+				if (!String(err).includes("cannot edit the template in place")) throw err;
+				const rewrite = await confirm(
+					`${String(err)}.\n\nRewrite the whole file anyway?`,
+					{ title: "Save Template", kind: "warning" },
+				);
+				if (!rewrite) throw err;
+				await saveTemplate(templatePath, templateFields, specs, true);
+			}
 			setSaveStatus("saved");
 			setIsDirty(false);
 			setTimeout(() => setSaveStatus("idle"), 2000);
@@ -633,6 +860,20 @@ export default function App() {
 						<Button size="sm" variant="outline" onClick={handleOpenTemplate}>
 							Open Template
 						</Button>
+						<Button size="sm" variant="outline" onClick={handleImportBundle}>
+							Import Bundle
+						</Button>
+						<Button
+							size="sm"
+							variant="ghost"
+							onClick={handleExportBundle}
+							disabled={!templatePath}
+						>
+							Export Bundle
+						</Button>
+						<Button size="sm" variant="ghost" onClick={handleExportSchema}>
+							Export Schema
+						</Button>
 					</div>
 					{templatePath && (
 						<span className="max-w-md truncate text-xs text-muted-foreground">
@@ -674,7 +915,7 @@ export default function App() {
 							templateFields={templateFields}
 							loadId={templateLoadId}
 							namespaces={namespaces}
-							namespaceFields={namespaceFields}
+							namespaceFields={completionFields}
 							saveStatus={saveStatus}
 							saveError={saveError}
 							onFieldChange={handleFieldChange}
@@ -693,6 +934,7 @@ export default function App() {
 									currentIndex={previewCurrentIndex}
 									rendered={previewRendered}
 									loading={previewLoading}
+									progress={previewProgress}
 									error={previewError}
 									onNavigate={handlePreviewNavigate}
 								/>
@@ -1,10 +1,100 @@
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
+import {
+	Select,
+	SelectContent,
+	SelectItem,
+	SelectTrigger,
+	SelectValue,
+} from "@/components/ui/select";
+import type { FormFieldSchema, FormValue } from "@/lib/ipc";
 
 interface Props {
-	fields: string[];
-	values: Record<string, string>;
-	onValueChange: (field: string, value: string) => void;
+	fields: FormFieldSchema[];
+	values: Record<string, FormValue>;
+	onValueChange: (field: string, value: FormValue) => void;
+}
+
+const INPUT_TYPES: Partial<Record<FormFieldSchema["type"], string>> = {
+	email: "email",
+	number: "number",
+	date: "date",
+};
+
+function FieldInput({
+	field,
+	value,
+	onChange,
+}: {
+	field: FormFieldSchema;
+	value: FormValue | undefined;
+	onChange: (value: FormValue) => void;
+}) {
+	const text = typeof value === "string" || typeof value === "number";
+	switch (field.type) {
+		case "checkbox":
+			return (
+				<input
+					type="checkbox"
+					className="size-4"
+					checked={value === true}
+					onChange={(e) => onChange(e.target.checked)}
+				/>
+			);
+		case "select":
+			return (
+				<Select
+					value={typeof value === "string" ? value : ""}
+					onValueChange={onChange}
+				>
+					<SelectTrigger className="h-7 text-xs">
+						<SelectValue placeholder={field.name} />
+					</SelectTrigger>
+					<SelectContent>
+						{(field.options ?? []).map((opt) => (
+							<SelectItem key={opt} value={opt} className="text-xs">
+								{opt}
+							</SelectItem>
+						))}
+					</SelectContent>
+				</Select>
+			);
+		case "multiline":
+			return (
+				<textarea
+					className="min-h-16 w-full rounded-md border bg-transparent px-3 py-1 text-xs"
+					value={text ? String(value) : ""}
+					onChange={(e) => onChange(e.target.value)}
+					placeholder={field.name}
+				/>
+			);
+		case "list":
+			// One item per line.
+			return (
+				<textarea
+					className="min-h-16 w-full rounded-md border bg-transparent px-3 py-1 text-xs"
+					value={Array.isArray(value) ? value.join("\n") : ""}
+					onChange={(e) =>
+						onChange(e.target.value.split("\n").filter((l) => l !== ""))
+					}
+					placeholder="One item per line"
+				/>
+			);
+		default:
+			return (
+				<Input
+					className="h-7 text-xs"
+					type={INPUT_TYPES[field.type] ?? "text"}
+					value={text ? String(value) : ""}
+					onChange={(e) => {
+						const raw = e.target.value;
+						const numeric = raw !== "" && !Number.isNaN(Number(raw));
+						onChange(field.type === "number" && numeric ? Number(raw) : raw);
+					}}
+					placeholder={field.name}
+				/>
+			);
+	}
 }
 
 export default function FormFieldsPanel({
@@ -25,13 +115,15 @@ export default function FormFieldsPanel({
 	return (
 		<div className="mt-2 space-y-2 rounded-md border bg-muted/30 p-2">
 			{fields.map((field) => (
-				<div key={field} className="space-y-0.5">
-					<Label className="text-xs text-muted-foreground">{field}</Label>
-					<Input
-						className="h-7 text-xs"
-						value={values[field] ?? ""}
-						onChange={(e) => onValueChange(field, e.target.value)}
-						placeholder={field}
+				<div key={field.name} className="space-y-0.5">
+					<Label className="text-xs text-muted-foreground">
+						{field.label ?? field.name}
+						{field.required && " *"}
+					</Label>
+					<FieldInput
+						field={field}
+						value={values[field.name]}
+						onChange={(value) => onValueChange(field.name, value)}
 					/>
 				</div>
 			))}
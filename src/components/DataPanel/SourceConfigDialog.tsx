@@ -11,7 +11,7 @@ import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 import { ScrollArea } from "@/components/ui/scroll-area";
 import { Separator } from "@/components/ui/separator";
-import type { SourceSlot } from "@/lib/ipc";
+import type { FormFieldSchema, JoinCondition, SourceSlot } from "@/lib/ipc";
 
 interface Props {
 	open: boolean;
@@ -23,6 +23,14 @@ interface Props {
 interface JoinRule {
 	localField: string;
 	ref: string;
+	/** Operator condition from the YAML; shown read-only and kept as-is on save. */
+	operators?: Exclude<JoinCondition, string>;
+}
+
+function formatOperators(ops: Exclude<JoinCondition, string>): string {
+	return Object.entries(ops)
+		.map(([op, v]) => `${op} ${Array.isArray(v) ? v.join(" .. ") : v}`)
+		.join(", ");
 }
 
 interface EditableSource {
@@ -31,6 +39,10 @@ interface EditableSource {
 	isForm: boolean;
 	isMany: boolean;
 	joinRules: JoinRule[];
+	/** Declared form inputs from the YAML; not editable here. */
+	fields: FormFieldSchema[] | null;
+	/** Computed fields from the YAML; not editable here. */
+	computed: Record<string, string> | null;
 }
 
 function toEditable(slot: SourceSlot): EditableSource {
@@ -39,17 +51,22 @@ function toEditable(slot: SourceSlot): EditableSource {
 		isPrimary: slot.is_primary,
 		isForm: slot.is_form,
 		isMany: slot.is_many,
-		joinRules: Object.entries(slot.join).map(([k, v]) => ({
-			localField: k,
-			ref: v,
-		})),
+		joinRules: Object.entries(slot.join).map(([k, v]) =>
+			typeof v === "string"
+				? { localField: k, ref: v }
+				: { localField: k, ref: formatOperators(v), operators: v },
+		),
+		fields: slot.fields,
+		computed: slot.computed,
 	};
 }
 
 function toSourceSlot(src: EditableSource): SourceSlot {
-	const join: Record<string, string> = {};
+	const join: Record<string, JoinCondition> = {};
 	for (const rule of src.joinRules) {
-		if (rule.localField.trim() && rule.ref.trim()) {
+		if (rule.localField.trim() && rule.operators) {
+			join[rule.localField.trim()] = rule.operators;
+		} else if (rule.localField.trim() && rule.ref.trim()) {
 			join[rule.localField.trim()] = rule.ref.trim();
 		}
 	}
@@ -60,6 +77,8 @@ function toSourceSlot(src: EditableSource): SourceSlot {
 		join,
 		is_many: src.isMany,
 		is_form: src.isForm,
+		fields: src.isForm ? src.fields : null,
+		computed: src.computed,
 	};
 }
 
@@ -80,6 +99,8 @@ function validate(sources: EditableSource[]): string[] {
 
 	for (const src of sources) {
 		for (const rule of src.joinRules) {
+			// Operator conditions are validated by the backend on save.
+			if (rule.operators) continue;
 			const ref = rule.ref.trim();
 			if (!ref) continue;
 			const parts = ref.split(".");
@@ -211,6 +232,12 @@ function SourceCard({
 							<span className="text-xs text-muted-foreground">&rarr;</span>
 							<Input
 								value={rule.ref}
+								disabled={rule.operators !== undefined}
+								title={
+									rule.operators
+										? "Operator joins are edited in the template YAML"
+										: undefined
+								}
 								onChange={(e) => {
 									const rules = [...source.joinRules];
 									rules[ri] = { ...rules[ri], ref: e.target.value };
@@ -297,6 +324,8 @@ export default function SourceConfigDialog({
 				isForm: false,
 				isMany: false,
 				joinRules: [],
+				fields: null,
+				computed: null,
 			},
 		]);
 		setErrors([]);
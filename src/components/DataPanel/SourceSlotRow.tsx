@@ -7,7 +7,7 @@ import {
 	TooltipContent,
 	TooltipTrigger,
 } from "@/components/ui/tooltip";
-import type { SourceSlot } from "@/lib/ipc";
+import type { FormValue, SourceSlot } from "@/lib/ipc";
 import CsvConfigPanel from "./CsvConfigPanel";
 import FormFieldsPanel from "./FormFieldsPanel";
 
@@ -17,7 +17,11 @@ interface Props {
 	onFileSelect: (namespace: string, path: string) => void;
 	onSeparatorChange: (namespace: string, sep: string) => void;
 	onEncodingChange: (namespace: string, enc: string) => void;
-	onFormValueChange: (namespace: string, field: string, value: string) => void;
+	onFormValueChange: (
+		namespace: string,
+		field: string,
+		value: FormValue,
+	) => void;
 }
 
 function StatusIcon({
@@ -2,7 +2,13 @@ import { SettingsIcon } from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { ScrollArea } from "@/components/ui/scroll-area";
 import { Separator } from "@/components/ui/separator";
-import type { CsvPreviewResult, SourceSlot, TemplateInfo } from "@/lib/ipc";
+import type {
+	CsvPreviewResult,
+	FormFieldSchema,
+	FormValue,
+	SourceSlot,
+	TemplateInfo,
+} from "@/lib/ipc";
 import SourceConfigDialog from "./SourceConfigDialog";
 import SourceSlotRow from "./SourceSlotRow";
 
@@ -12,8 +18,8 @@ export interface SourceState {
 	separatorOverride: string | null;
 	encodingOverride: string | null;
 	error: string | null;
-	formFields: string[] | null;
-	formValues: Record<string, string> | null;
+	formFields: FormFieldSchema[] | null;
+	formValues: Record<string, FormValue> | null;
 }
 
 interface Props {
@@ -25,7 +31,11 @@ interface Props {
 	onFileSelect: (namespace: string, path: string) => void;
 	onSeparatorChange: (namespace: string, sep: string) => void;
 	onEncodingChange: (namespace: string, enc: string) => void;
-	onFormValueChange: (namespace: string, field: string, value: string) => void;
+	onFormValueChange: (
+		namespace: string,
+		field: string,
+		value: FormValue,
+	) => void;
 }
 
 export default function DataPanel({
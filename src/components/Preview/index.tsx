@@ -12,6 +12,8 @@ interface Props {
 	currentIndex: number;
 	rendered: PreviewRenderedEmail | null;
 	loading: boolean;
+	/** Entries validated so far while `loading`. */
+	progress: { completed: number; total: number } | null;
 	error: string | null;
 	onNavigate: (index: number) => void;
 }
@@ -21,6 +23,7 @@ export default function Preview({
 	currentIndex,
 	rendered,
 	loading,
+	progress,
 	error,
 	onNavigate,
 }: Props) {
@@ -37,7 +40,10 @@ export default function Preview({
 	}
 
 	const currentEntry = validation?.entries[currentIndex];
-	const currentIssues = currentEntry?.issues ?? [];
+	const currentIssues = [
+		...(validation?.template_issues ?? []),
+		...(currentEntry?.issues ?? []),
+	];
 
 	return (
 		<div className="flex h-full flex-col">
@@ -45,7 +51,11 @@ export default function Preview({
 			{loading && (
 				<div className="flex items-center gap-2 border-b px-3 py-1.5">
 					<LoaderCircleIcon className="size-3.5 animate-spin text-muted-foreground" />
-					<span className="text-xs text-muted-foreground">Rendering...</span>
+					<span className="text-xs text-muted-foreground">
+						{progress
+							? `Validating ${progress.completed}/${progress.total}...`
+							: "Rendering..."}
+					</span>
 				</div>
 			)}
 
@@ -1,4 +1,5 @@
 import { listen } from "@tauri-apps/api/event";
+import { open as openDialog, save } from "@tauri-apps/plugin-dialog";
 import { useCallback, useEffect, useRef, useState } from "react";
 import { Button } from "@/components/ui/button";
 import {
@@ -12,6 +13,8 @@ import { Progress } from "@/components/ui/progress";
 import { ScrollArea } from "@/components/ui/scroll-area";
 import {
 	cancelSend,
+	type ExportFormat,
+	exportEmails,
 	type PreviewEntryStatus,
 	type SendBatchReport,
 	type SendProgressEvent,
@@ -52,12 +55,14 @@ export default function SendDialog({
 	validationEntries,
 }: Props) {
 	const [view, setView] = useState<View>({ kind: "confirm" });
+	const [exportMessage, setExportMessage] = useState<string | null>(null);
 
 	// Reset to confirm view when dialog opens.
 	const prevOpen = useRef(open);
 	useEffect(() => {
 		if (open && !prevOpen.current) {
 			setView({ kind: "confirm" });
+			setExportMessage(null);
 		}
 		prevOpen.current = open;
 	}, [open]);
@@ -118,6 +123,39 @@ export default function SendDialog({
 		[templatePath, templateFields, sourceFileSpecs, profileName, entryCount],
 	);
 
+	// Write the messages to disk instead of sending them, for review.
+	const handleExport = async (format: ExportFormat) => {
+		const destination =
+			format === "mbox"
+				? await save({
+						defaultPath: "review.mbox",
+						filters: [{ name: "Mailbox", extensions: ["mbox"] }],
+					})
+				: await openDialog({ directory: true, title: "Export Emails To" });
+		if (typeof destination !== "string") return;
+		try {
+			const report = await exportEmails(
+				templatePath,
+				templateFields,
+				sourceFileSpecs,
+				profileName,
+				format,
+				destination,
+			);
+			const failed = report.failures.length;
+			setExportMessage(
+				`Exported ${report.exported.length} email${report.exported.length !== 1 ? "s" : ""}` +
+					(failed > 0
+						? `; ${failed} failed (${report.failures
+								.map((f) => `#${f.entry_index + 1}: ${f.reason}`)
+								.join("; ")})`
+						: ""),
+			);
+		} catch (err) {
+			setExportMessage(String(err));
+		}
+	};
+
 	const handleCancel = async () => {
 		try {
 			await cancelSend();
@@ -148,6 +186,8 @@ export default function SendDialog({
 						entryCount={entryCount}
 						profileName={profileName}
 						warningCount={warningCount}
+						exportMessage={exportMessage}
+						onExport={handleExport}
 						onSend={() => handleSend()}
 						onCancel={() => onOpenChange(false)}
 					/>
@@ -178,12 +218,16 @@ function ConfirmView({
 	entryCount,
 	profileName,
 	warningCount,
+	exportMessage,
+	onExport,
 	onSend,
 	onCancel,
 }: {
 	entryCount: number;
 	profileName: string;
 	warningCount: number;
+	exportMessage: string | null;
+	onExport: (format: ExportFormat) => void;
 	onSend: () => void;
 	onCancel: () => void;
 }) {
@@ -205,8 +249,22 @@ function ConfirmView({
 						but they may contain errors.
 					</div>
 				)}
+				{exportMessage && (
+					<p className="text-xs text-muted-foreground">{exportMessage}</p>
+				)}
 			</div>
 			<DialogFooter>
+				<Button
+					variant="ghost"
+					size="sm"
+					className="mr-auto"
+					onClick={() => onExport("eml")}
+				>
+					Export .eml…
+				</Button>
+				<Button variant="ghost" size="sm" onClick={() => onExport("mbox")}>
+					Export mbox…
+				</Button>
 				<Button variant="outline" size="sm" onClick={onCancel}>
 					Cancel
 				</Button>
@@ -8,6 +8,7 @@ import {
 } from "./handlebarsExtension";
 
 interface Props {
+	label?: string;
 	defaultValue: string;
 	loadId: number;
 	namespaces: string[];
@@ -16,6 +17,7 @@ interface Props {
 }
 
 export default function BodyEditor({
+	label = "Body",
 	defaultValue,
 	loadId,
 	namespaces,
@@ -37,7 +39,7 @@ export default function BodyEditor({
 
 	return (
 		<div className="space-y-1">
-			<p className="text-xs font-medium">Body</p>
+			<p className="text-xs font-medium">{label}</p>
 			<div className="min-h-52 rounded-md border bg-background focus-within:ring-1 focus-within:ring-ring">
 				<CodeMirror
 					key={loadId}
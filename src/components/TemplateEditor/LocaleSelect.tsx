@@ -0,0 +1,88 @@
+import { PlusIcon, XIcon } from "lucide-react";
+import { useState } from "react";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import {
+	Select,
+	SelectContent,
+	SelectItem,
+	SelectTrigger,
+	SelectValue,
+} from "@/components/ui/select";
+
+interface Props {
+	locales: string[];
+	value: string | null;
+	onChange: (locale: string) => void;
+	onAdd: (locale: string) => void;
+	onRemove: (locale: string) => void;
+}
+
+/** Picks which locale variant of subject, body and attachments is edited. */
+export default function LocaleSelect({
+	locales,
+	value,
+	onChange,
+	onAdd,
+	onRemove,
+}: Props) {
+	const [draft, setDraft] = useState("");
+
+	const add = () => {
+		const tag = draft.trim();
+		if (!tag || locales.includes(tag)) return;
+		onAdd(tag);
+		setDraft("");
+	};
+
+	return (
+		<div className="space-y-1">
+			<p className="text-xs font-medium text-muted-foreground">Locale</p>
+			<div className="flex items-center gap-2">
+				{locales.length > 0 && value && (
+					<>
+						<Select value={value} onValueChange={onChange}>
+							<SelectTrigger className="h-8 w-28 text-xs">
+								<SelectValue />
+							</SelectTrigger>
+							<SelectContent>
+								{locales.map((locale) => (
+									<SelectItem key={locale} value={locale} className="text-xs">
+										{locale}
+									</SelectItem>
+								))}
+							</SelectContent>
+						</Select>
+						<Button
+							size="sm"
+							variant="ghost"
+							className="h-8 w-8 p-0"
+							title={`Remove the '${value}' variants`}
+							onClick={() => onRemove(value)}
+						>
+							<XIcon className="h-3 w-3" />
+						</Button>
+					</>
+				)}
+				<Input
+					value={draft}
+					placeholder={locales.length > 0 ? "Add locale" : "Localize as…"}
+					className="h-8 w-28 text-xs"
+					onChange={(e) => setDraft(e.target.value)}
+					onKeyDown={(e) => {
+						if (e.key === "Enter") add();
+					}}
+				/>
+				<Button
+					size="sm"
+					variant="ghost"
+					className="h-8 w-8 p-0"
+					disabled={!draft.trim()}
+					onClick={add}
+				>
+					<PlusIcon className="h-3 w-3" />
+				</Button>
+			</div>
+		</div>
+	);
+}
@@ -1,12 +1,46 @@
 import { CheckIcon, LoaderCircleIcon, SaveIcon } from "lucide-react";
-import { useEffect } from "react";
+import { useEffect, useState } from "react";
 import { Button } from "@/components/ui/button";
 import { ScrollArea } from "@/components/ui/scroll-area";
 import { Separator } from "@/components/ui/separator";
-import type { TemplateFields } from "@/lib/ipc";
+import type { Localized, TemplateFields } from "@/lib/ipc";
 import BodyEditor from "./BodyEditor";
 import BodyFormatSelect from "./BodyFormatSelect";
 import FieldEditor from "./FieldEditor";
+import LocaleSelect from "./LocaleSelect";
+
+type LocalizedKey =
+	| "subject"
+	| "body"
+	| "text_body"
+	| "preheader"
+	| "attachments";
+const LOCALIZED_KEYS: LocalizedKey[] = [
+	"subject",
+	"body",
+	"text_body",
+	"preheader",
+	"attachments",
+];
+
+/** Locale tags used by any localized field, sorted. */
+function localesOf(fields: TemplateFields): string[] {
+	const tags = new Set<string>();
+	for (const key of LOCALIZED_KEYS) {
+		const value = fields[key];
+		if (value && typeof value === "object") {
+			for (const tag of Object.keys(value)) tags.add(tag);
+		}
+	}
+	return [...tags].sort();
+}
+
+/** The text edited for `locale`: the single text, or that locale's variant. */
+function variantText(value: Localized | null, locale: string | null): string {
+	if (value === null) return "";
+	if (typeof value === "string") return value;
+	return locale ? (value[locale] ?? "") : "";
+}
 
 interface Props {
 	templateFields: TemplateFields | null;
@@ -15,7 +49,10 @@ interface Props {
 	namespaceFields: Record<string, string[]>;
 	saveStatus: "idle" | "saving" | "saved" | "error";
 	saveError: string | null;
-	onFieldChange: (field: keyof TemplateFields, value: string | null) => void;
+	onFieldChange: (
+		field: keyof TemplateFields,
+		value: Localized | null,
+	) => void;
 	onSave: () => void;
 }
 
@@ -29,6 +66,20 @@ export default function TemplateEditor({
 	onFieldChange,
 	onSave,
 }: Props) {
+	// The chosen locale belongs to one load; a newly loaded template starts on
+	// its first locale.
+	const [chosen, setChosen] = useState<{
+		loadId: number;
+		locale: string | null;
+	} | null>(null);
+	const locales = templateFields ? localesOf(templateFields) : [];
+	const locale =
+		chosen?.loadId === loadId && chosen.locale && locales.includes(chosen.locale)
+			? chosen.locale
+			: (locales[0] ?? null);
+	const setActiveLocale = (next: string | null) =>
+		setChosen({ loadId, locale: next });
+
 	// Ctrl+S / Cmd+S to save
 	useEffect(() => {
 		const handler = (e: KeyboardEvent) => {
@@ -61,6 +112,46 @@ export default function TemplateEditor({
 	const setRequired = (field: keyof TemplateFields) => (value: string) =>
 		onFieldChange(field, value);
 
+	// Localized fields edit the active locale's variant; without locales they
+	// behave like plain fields.
+	const setLocalized =
+		(field: LocalizedKey, optional = false) =>
+		(text: string) => {
+			const current = templateFields[field];
+			if (locale === null || typeof current === "string") {
+				onFieldChange(field, optional && text === "" ? null : text);
+				return;
+			}
+			onFieldChange(field, { ...(current ?? {}), [locale]: text });
+		};
+
+	// The first locale takes over the existing texts; later ones start empty.
+	const addLocale = (tag: string) => {
+		for (const key of LOCALIZED_KEYS) {
+			const value = templateFields[key];
+			if (value === null) continue;
+			onFieldChange(
+				key,
+				typeof value === "string" ? { [tag]: value } : { ...value, [tag]: "" },
+			);
+		}
+		setActiveLocale(tag);
+	};
+
+	// Removing the last locale turns its variants back into plain texts.
+	const removeLocale = (tag: string) => {
+		for (const key of LOCALIZED_KEYS) {
+			const value = templateFields[key];
+			if (value === null || typeof value === "string") continue;
+			const { [tag]: removed, ...rest } = value;
+			onFieldChange(
+				key,
+				Object.keys(rest).length > 0 ? rest : (removed ?? ""),
+			);
+		}
+		setActiveLocale(null);
+	};
+
 	return (
 		<div className="flex h-full flex-col overflow-hidden">
 			{/* Header */}
@@ -132,16 +223,67 @@ export default function TemplateEditor({
 						optional
 					/>
 
+					<Separator />
+
+					<LocaleSelect
+						locales={locales}
+						value={locale}
+						onChange={setActiveLocale}
+						onAdd={addLocale}
+						onRemove={removeLocale}
+					/>
+
+					{locales.length > 0 && (
+						<>
+							<FieldEditor
+								label="Locale field"
+								defaultValue={templateFields.locale_field ?? ""}
+								loadId={loadId}
+								placeholder="namespace.lang"
+								namespaces={namespaces}
+								namespaceFields={namespaceFields}
+								onChange={set("locale_field")}
+								optional
+							/>
+
+							<FieldEditor
+								label="Fallback locale"
+								defaultValue={templateFields.fallback_locale ?? ""}
+								loadId={loadId}
+								placeholder="en"
+								namespaces={namespaces}
+								namespaceFields={namespaceFields}
+								onChange={set("fallback_locale")}
+								optional
+							/>
+						</>
+					)}
+
 					<FieldEditor
-						label="Subject"
-						defaultValue={templateFields.subject}
+						key={`subject-${locale}`}
+						label={locale ? `Subject (${locale})` : "Subject"}
+						defaultValue={variantText(templateFields.subject, locale)}
 						loadId={loadId}
 						placeholder="{{namespace.name}} — your credentials"
 						namespaces={namespaces}
 						namespaceFields={namespaceFields}
-						onChange={setRequired("subject")}
+						onChange={setLocalized("subject")}
 					/>
 
+					{templateFields.body_format !== "text" && (
+						<FieldEditor
+							key={`preheader-${locale}`}
+							label={locale ? `Preheader (${locale})` : "Preheader"}
+							defaultValue={variantText(templateFields.preheader, locale)}
+							loadId={loadId}
+							placeholder="Preview text shown after the subject"
+							namespaces={namespaces}
+							namespaceFields={namespaceFields}
+							onChange={setLocalized("preheader", true)}
+							optional
+						/>
+					)}
+
 					<Separator />
 
 					<BodyFormatSelect
@@ -150,23 +292,37 @@ export default function TemplateEditor({
 					/>
 
 					<BodyEditor
-						defaultValue={templateFields.body}
+						key={`body-${locale}`}
+						defaultValue={variantText(templateFields.body, locale)}
 						loadId={loadId}
 						namespaces={namespaces}
 						namespaceFields={namespaceFields}
-						onChange={setRequired("body")}
+						onChange={setLocalized("body")}
 					/>
 
+					{templateFields.body_format !== "text" && (
+						<BodyEditor
+							key={`text_body-${locale}`}
+							label="Text body (optional)"
+							defaultValue={variantText(templateFields.text_body, locale)}
+							loadId={loadId}
+							namespaces={namespaces}
+							namespaceFields={namespaceFields}
+							onChange={setLocalized("text_body", true)}
+						/>
+					)}
+
 					<Separator />
 
 					<FieldEditor
-						label="Attachments"
-						defaultValue={templateFields.attachments ?? ""}
+						key={`attachments-${locale}`}
+						label={locale ? `Attachments (${locale})` : "Attachments"}
+						defaultValue={variantText(templateFields.attachments, locale)}
 						loadId={loadId}
 						placeholder="{{namespace.name}}/handout.pdf"
 						namespaces={namespaces}
 						namespaceFields={namespaceFields}
-						onChange={set("attachments")}
+						onChange={setLocalized("attachments", true)}
 						optional
 					/>
 
@@ -4,31 +4,94 @@ import { invoke } from "@tauri-apps/api/core";
 
 export type BodyFormat = "markdown" | "html" | "text";
 
+/**
+ * Right-hand side of a join entry: "namespace.field" for equality, or an
+ * operator map such as `{ contains: "classes.id" }` or
+ * `{ between: ["p.from", "p.until"] }`.
+ */
+export type JoinCondition = string | Record<string, string | string[]>;
+
+export type FormFieldType =
+	| "text"
+	| "multiline"
+	| "email"
+	| "number"
+	| "date"
+	| "select"
+	| "checkbox"
+	| "list";
+
+/** Submitted value of a form field; its shape follows the field type. */
+export type FormValue = string | number | boolean | string[];
+
+/** One declared (or inferred) input of a form source. */
+export interface FormFieldSchema {
+	name: string;
+	type: FormFieldType;
+	label?: string;
+	default?: FormValue;
+	required?: boolean;
+	/** Regex the whole value must match. */
+	pattern?: string;
+	/** Allowed values for "select". */
+	options?: string[];
+}
+
 export interface SourceSlot {
 	namespace: string;
 	is_primary: boolean;
 	has_join: boolean;
-	join: Record<string, string>;
+	join: Record<string, JoinCondition>;
 	is_many: boolean;
 	is_form: boolean;
+	/** Declared form inputs; kept as-is when sources are saved. */
+	fields: FormFieldSchema[] | null;
+	/** Per-row computed fields (name → expression); kept as-is when saved. */
+	computed: Record<string, string> | null;
 }
 
+/** A field given once, or as locale tag → variant. */
+export type Localized = string | Record<string, string>;
+
 export interface TemplateFields {
 	to: string;
 	cc: string | null;
 	bcc: string | null;
-	subject: string;
-	body: string;
-	attachments: string | null;
+	subject: Localized;
+	body: Localized;
+	/** Hand-written plain-text part; null derives it from the HTML body. */
+	text_body: Localized | null;
+	/** Preview text shown after the subject; hidden in the HTML body. */
+	preheader: Localized | null;
+	attachments: Localized | null;
+	/** namespace.field holding each entry's locale. */
+	locale_field: string | null;
+	fallback_locale: string | null;
 	body_format: BodyFormat | null;
 	stylesheet: string | null;
 	style: string | null;
 }
 
+export type MigrationChange = {
+	kind: "rename_key";
+	namespace: string | null;
+	from: string;
+	to: string;
+};
+
+export interface MigrationReport {
+	from_version: number;
+	to_version: number;
+	/** Empty when the file only lacked the `version:` key. */
+	changes: MigrationChange[];
+}
+
 export interface TemplateInfo {
 	path: string;
 	sources: SourceSlot[];
 	fields: TemplateFields;
+	/** Set when the file uses an older format and was upgraded in memory. */
+	migration: MigrationReport | null;
 }
 
 export interface CsvPreviewResult {
@@ -52,9 +115,11 @@ export interface SmtpProfile {
 export interface SourceSpec {
 	namespace: string;
 	primary?: boolean;
-	join?: Record<string, string>;
+	join?: Record<string, JoinCondition>;
 	many?: boolean;
 	form?: boolean;
+	fields?: FormFieldSchema[];
+	computed?: Record<string, string>;
 }
 
 export interface SourceFileSpec {
@@ -62,7 +127,7 @@ export interface SourceFileSpec {
 	path: string;
 	separator: string | null;
 	encoding: string | null;
-	form_data: Record<string, string> | null;
+	form_data: Record<string, FormValue> | null;
 }
 
 export interface PreviewEntryStatus {
@@ -74,6 +139,8 @@ export interface PreviewEntryStatus {
 export interface PreviewValidation {
 	entry_count: number;
 	entries: PreviewEntryStatus[];
+	/** Template-wide problems, e.g. join keys or fields missing from the data */
+	template_issues: string[];
 }
 
 export interface PreviewRenderedEmail {
@@ -100,6 +167,20 @@ export interface SendBatchReport {
 	results: SendResultEntry[];
 }
 
+export type ExportFormat = "eml" | "mbox";
+
+export interface ExportReport {
+	exported: { entry_index: number; path: string }[];
+	failures: { entry_index: number; reason: string }[];
+}
+
+export interface ValidateProgressEvent {
+	completed: number;
+	total: number;
+	entry_index: number;
+	is_valid: boolean;
+}
+
 export interface SendProgressEvent {
 	completed: number;
 	total: number;
@@ -109,6 +190,57 @@ export interface SendProgressEvent {
 	error: string | null;
 }
 
+export type BundleFileKind =
+	| "template"
+	| "test_file"
+	| "stylesheet"
+	| "translation"
+	| "attachment"
+	| "inline_image"
+	| "data";
+
+export interface BundleFile {
+	kind: BundleFileKind;
+	/** Path inside the archive. */
+	path: string;
+	/** The path as the template wrote it; the original file path for data. */
+	reference: string;
+	namespace?: string;
+	size: number;
+	sha256: string;
+}
+
+export interface MissingReference {
+	kind: BundleFileKind;
+	reference: string;
+}
+
+export interface BundleManifest {
+	format: number;
+	mailnir_version: string;
+	template: string;
+	files: BundleFile[];
+	missing: MissingReference[];
+}
+
+export interface PathRewrite {
+	kind: BundleFileKind;
+	from: string;
+	to: string;
+}
+
+export interface ImportReport {
+	template_path: string;
+	/** Extracted data files by namespace. */
+	data_files: Record<string, string>;
+	rewritten: PathRewrite[];
+	missing: MissingReference[];
+	/** Archive paths whose checksum did not match; not extracted. */
+	corrupted: string[];
+	/** External attachments built with Handlebars that could not be rewritten. */
+	unresolved: string[];
+}
+
 // ── Command wrappers ──────────────────────────────────────────────────────────
 
 export const getVersionInfo = (): Promise<string> => invoke("get_version_info");
@@ -116,6 +248,27 @@ export const getVersionInfo = (): Promise<string> => invoke("get_version_info");
 export const parseTemplate = (path: string): Promise<TemplateInfo> =>
 	invoke("parse_template_cmd", { path });
 
+export const migrateTemplateFile = (
+	path: string,
+): Promise<MigrationReport | null> =>
+	invoke("migrate_template_file", { path });
+
+export const exportTemplateSchema = (path: string): Promise<void> =>
+	invoke("export_template_schema", { path });
+
+export const exportBundle = (
+	templatePath: string,
+	sourceFiles: SourceFileSpec[],
+	includeData: boolean,
+	outPath: string,
+): Promise<BundleManifest> =>
+	invoke("export_bundle", { templatePath, sourceFiles, includeData, outPath });
+
+export const importBundle = (
+	bundlePath: string,
+	destDir: string,
+): Promise<ImportReport> => invoke("import_bundle", { bundlePath, destDir });
+
 export const previewCsv = (
 	path: string,
 	separator?: string | null,
@@ -157,21 +310,41 @@ export const getFormFields = (
 	templatePath: string,
 	fields: TemplateFields,
 	namespace: string,
-): Promise<string[]> =>
+): Promise<FormFieldSchema[]> =>
 	invoke("get_form_fields", { templatePath, fields, namespace });
 
+export interface VariableRef {
+	namespace: string;
+	/** Segments below the namespace, e.g. ["address", "city"]. */
+	path: string[];
+	/** Template field the reference appears in. */
+	field: string;
+	/** Byte offset of the enclosing `{{` within the field. */
+	offset: number;
+	usage: "scalar" | "iterated";
+}
+
+export const getTemplateVariables = (
+	templatePath: string,
+	fields: TemplateFields,
+): Promise<VariableRef[]> =>
+	invoke("get_template_variables", { templatePath, fields });
+
 export const createTemplate = (
 	path: string,
 	sources: SourceSpec[],
 	patch: TemplateFields,
 ): Promise<void> => invoke("create_template", { path, sources, patch });
 
+/** Rejects with an error naming "cannot edit the template in place" when
+ * the file's layout would need a full rewrite; pass `rewrite` to allow it. */
 export const saveTemplate = (
 	path: string,
 	patch: TemplateFields,
 	sources?: SourceSpec[] | null,
+	rewrite = false,
 ): Promise<void> =>
-	invoke("save_template", { path, patch, sources: sources ?? null });
+	invoke("save_template", { path, patch, sources: sources ?? null, rewrite });
 
 export const previewValidate = (
 	templatePath: string,
@@ -180,6 +353,8 @@ export const previewValidate = (
 ): Promise<PreviewValidation> =>
 	invoke("preview_validate", { templatePath, fields, sourceFiles });
 
+export const cancelValidate = (): Promise<void> => invoke("cancel_validate");
+
 export const previewRenderEntry = (
 	templatePath: string,
 	fields: TemplateFields,
@@ -209,3 +384,22 @@ export const sendBatch = (
 	});
 
 export const cancelSend = (): Promise<void> => invoke("cancel_send");
+
+export const exportEmails = (
+	templatePath: string,
+	fields: TemplateFields,
+	sourceFiles: SourceFileSpec[],
+	profileName: string,
+	format: ExportFormat,
+	destination: string,
+	fileName?: string | null,
+): Promise<ExportReport> =>
+	invoke("export_emails", {
+		templatePath,
+		fields,
+		sourceFiles,
+		profileName,
+		format,
+		destination,
+		fileName: fileName ?? null,
+	});